@@ -0,0 +1,556 @@
+//! Files outside a plugin that its records refer to, and finding them among a data directory's loose files and
+//! archives
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    bsa::{normalize_path, Archive},
+    dialogue::{Dialogue, Info, Topic},
+    load_order::{GlobalFormId, LoadOrder, LoadedPlugin},
+    nif::Nif,
+    parsers::{
+        common::{decode_string, form_id, FormId, TypeCode},
+        plugin::Plugin,
+        records::{
+            condition::{Comparison, ComparisonValue},
+            flags::PluginFlags,
+            quest::Quest,
+            vmad::Vmad,
+            Record,
+        },
+    },
+};
+
+/// Subrecords holding a model path relative to meshes\
+pub const MODEL_SUBRECORDS: &[[u8; 4]] = &[*b"MODL", *b"MOD2", *b"MOD3", *b"MOD4", *b"MOD5", *b"DMDL"];
+
+/// The model paths a record refers to, with the subrecord holding each, relative to the data directory
+pub fn model_paths(record: &Record) -> Vec<(TypeCode, String)> {
+    record
+        .subrecords()
+        .into_iter()
+        .filter(|subrecord| MODEL_SUBRECORDS.contains(&*subrecord.code))
+        .filter_map(|subrecord| {
+            path_string(subrecord.data).map(|path| (subrecord.code, prefixed_path("meshes", &path)))
+        })
+        .collect()
+}
+
+/// A null-terminated path, or `None` if it is empty
+fn path_string(data: &[u8]) -> Option<String> {
+    let end = data.iter().position(|byte| *byte == 0).unwrap_or(data.len());
+
+    if end == 0 {
+        None
+    } else {
+        Some(decode_string(&data[..end]))
+    }
+}
+
+/// A normalized path under `folder`, which record paths usually leave out
+pub(crate) fn prefixed_path(folder: &str, path: &str) -> String {
+    let path = normalize_path(path);
+    let path = path.trim_start_matches('\\');
+    let prefix = format!("{}\\", folder);
+
+    if path.starts_with(&prefix) {
+        String::from(path)
+    } else {
+        format!("{}{}", prefix, path)
+    }
+}
+
+/// The loose files and BSA archives of a data directory. Loose files win over archived ones, and later archives,
+/// by name, over earlier ones
+#[derive(Debug)]
+pub struct DataFiles {
+    pub data_dir: PathBuf,
+    loose: HashMap<String, PathBuf>,
+    pub archives: Vec<Archive>,
+}
+
+impl DataFiles {
+    pub fn open<P>(data_dir: P) -> Result<Self, crate::Error>
+    where
+        P: AsRef<Path>,
+    {
+        let data_dir = data_dir.as_ref();
+        let mut loose = HashMap::new();
+        let mut archive_paths = vec![];
+
+        let mut directories = vec![data_dir.to_path_buf()];
+
+        while let Some(directory) = directories.pop() {
+            for entry in fs::read_dir(&directory)? {
+                let path = entry?.path();
+
+                if path.is_dir() {
+                    directories.push(path);
+                } else if let Ok(relative) = path.strip_prefix(data_dir) {
+                    if directory == data_dir {
+                        if path
+                            .extension()
+                            .is_some_and(|extension| extension.eq_ignore_ascii_case("bsa"))
+                        {
+                            archive_paths.push(path.clone());
+                        }
+                    } else {
+                        loose.insert(normalize_path(&relative.to_string_lossy()), path.clone());
+                    }
+                }
+            }
+        }
+
+        archive_paths.sort();
+
+        let archives = archive_paths.iter().map(Archive::open).collect::<Result<Vec<_>, _>>()?;
+
+        Ok(DataFiles {
+            data_dir: data_dir.to_path_buf(),
+            loose,
+            archives,
+        })
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        let path = normalize_path(path);
+        self.loose.contains_key(&path) || self.archives.iter().any(|archive| archive.contains(&path))
+    }
+
+    pub fn read(&self, path: &str) -> Result<Option<Vec<u8>>, crate::Error> {
+        let path = normalize_path(path);
+
+        if let Some(loose) = self.loose.get(&path) {
+            return Ok(Some(fs::read(loose)?));
+        }
+
+        for archive in self.archives.iter().rev() {
+            if archive.contains(&path) {
+                return archive.read(&path);
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelCheck {
+    pub record: FormId,
+    pub subrecord: TypeCode,
+    pub path: String,
+    pub found: bool,
+    /// Textures the mesh uses, or `None` if it is missing or couldn't be read
+    pub textures: Option<Vec<String>>,
+    pub missing_textures: Vec<String>,
+}
+
+/// Look up every model the plugin's records use, along with the textures of the meshes that are present
+pub fn check_models(plugin: &Plugin, files: &DataFiles) -> Vec<ModelCheck> {
+    let mut records = plugin.records();
+    records.sort_by_key(|record| record.header.id);
+
+    let mut meshes: HashMap<String, Option<Vec<String>>> = HashMap::new();
+    let mut checks = vec![];
+
+    for record in records {
+        for (subrecord, path) in model_paths(record) {
+            let found = files.contains(&path);
+
+            let textures = if found {
+                meshes
+                    .entry(path.clone())
+                    .or_insert_with(|| match files.read(&path) {
+                        Ok(Some(bytes)) => Nif::parse(&bytes).ok().map(|nif| nif.textures()),
+                        _ => None,
+                    })
+                    .clone()
+            } else {
+                None
+            };
+
+            let missing_textures = textures
+                .iter()
+                .flatten()
+                .filter(|texture| !files.contains(texture))
+                .cloned()
+                .collect();
+
+            checks.push(ModelCheck {
+                record: record.header.id,
+                subrecord,
+                path,
+                found,
+                textures,
+                missing_textures,
+            });
+        }
+    }
+
+    checks
+}
+
+/// Subrecords holding a texture path relative to textures\
+pub const TEXTURE_SUBRECORDS: &[[u8; 4]] = &[
+    *b"TX00", *b"TX01", *b"TX02", *b"TX03", *b"TX04", *b"TX05", *b"TX06", *b"TX07", *b"ICON", *b"MICO",
+];
+
+/// The kinds of string table a localized plugin needs for each language
+pub const STRINGS_EXTENSIONS: &[&str] = &["strings", "dlstrings", "ilstrings"];
+
+/// Formats the engine accepts for a voice file, any one of which satisfies it
+const VOICE_EXTENSIONS: &[&str] = &["fuz", "xwm", "wav"];
+
+/// ACBS template flag for NPCs that take their face from their template, and so have no facegen of their own
+const USE_TRAITS: u16 = 0x0001;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AssetKind {
+    Mesh,
+    Texture,
+    Sound,
+    Script,
+    FaceGen,
+    Voice,
+    Sequence,
+    Strings,
+}
+
+impl fmt::Display for AssetKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            AssetKind::Mesh => "mesh",
+            AssetKind::Texture => "texture",
+            AssetKind::Sound => "sound",
+            AssetKind::Script => "script",
+            AssetKind::FaceGen => "facegen",
+            AssetKind::Voice => "voice",
+            AssetKind::Sequence => "seq",
+            AssetKind::Strings => "strings",
+        };
+
+        f.write_str(name)
+    }
+}
+
+/// A file a plugin needs at runtime
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dependency {
+    pub kind: AssetKind,
+    /// Normalized and relative to the data directory
+    pub path: String,
+    /// The records that need the file. Empty for files the plugin as a whole needs, such as string tables
+    pub records: Vec<FormId>,
+}
+
+impl Dependency {
+    /// Whether the file is among the data files. Voice files may also be present as .xwm or .wav
+    pub fn is_present(&self, files: &DataFiles) -> bool {
+        if self.kind != AssetKind::Voice {
+            return files.contains(&self.path);
+        }
+
+        let stem = self.path.rsplit_once('.').map_or(self.path.as_str(), |(stem, _)| stem);
+
+        VOICE_EXTENSIONS
+            .iter()
+            .any(|extension| files.contains(&format!("{}.{}", stem, extension)))
+    }
+}
+
+/// Dependencies gathered by kind and path, with the records needing each
+#[derive(Default)]
+struct Dependencies(BTreeMap<(AssetKind, String), Vec<FormId>>);
+
+impl Dependencies {
+    fn add(&mut self, kind: AssetKind, path: String, record: Option<FormId>) {
+        let records = self.0.entry((kind, normalize_path(&path))).or_default();
+
+        if let Some(record) = record {
+            if !records.contains(&record) {
+                records.push(record);
+            }
+        }
+    }
+}
+
+/// Every file the plugin's records refer to, or that the engine looks for on the plugin's behalf: meshes and their
+/// textures, texture sets, sounds, compiled scripts, facegen for new NPCs, voice files, the SEQ file and string
+/// tables in `language`.
+///
+/// Mesh textures are only listed when `files` is given to read the meshes from. Quests, topics and voice types from
+/// masters are looked up in `load_order`, and voice files are only listed for INFOs where they can be found
+pub fn dependencies(
+    plugin: &LoadedPlugin,
+    load_order: &LoadOrder,
+    files: Option<&DataFiles>,
+    language: &str,
+) -> Result<Vec<Dependency>, crate::Error> {
+    let mut dependencies = Dependencies::default();
+
+    let mut records = plugin.plugin.records();
+    records.sort_by_key(|record| record.header.id);
+
+    let stem = Path::new(&plugin.name)
+        .file_stem()
+        .map_or_else(|| plugin.name.clone(), |stem| stem.to_string_lossy().into_owned());
+
+    for record in &records {
+        let id = Some(record.header.id);
+
+        for (_, path) in model_paths(record) {
+            dependencies.add(AssetKind::Mesh, path, id);
+        }
+
+        for subrecord in record.subrecords() {
+            let path = match path_string(subrecord.data) {
+                Some(path) => path,
+                None => continue,
+            };
+
+            match (&*record.header.code, &*subrecord.code) {
+                (_, code) if TEXTURE_SUBRECORDS.contains(code) => {
+                    dependencies.add(AssetKind::Texture, prefixed_path("textures", &path), id);
+                }
+                (b"SNDR", b"ANAM") | (b"SOUN", b"FNAM") => {
+                    dependencies.add(AssetKind::Sound, prefixed_path("sound", &path), id);
+                }
+                _ => (),
+            }
+        }
+
+        if let Some(vmad) = Vmad::decode(record)? {
+            for name in vmad.script_names() {
+                dependencies.add(AssetKind::Script, format!("scripts\\{}.pex", name), id);
+            }
+        }
+
+        match &*record.header.code {
+            b"NPC_" if plugin.is_new_record(record.header.id) => {
+                for path in npc_facegen_paths(plugin, load_order, record.header.id)
+                    .into_iter()
+                    .flatten()
+                {
+                    dependencies.add(AssetKind::FaceGen, path, id);
+                }
+            }
+            b"QUST" if Quest::decode(record).is_ok_and(|quest| quest.is_start_game_enabled()) => {
+                dependencies.add(AssetKind::Sequence, format!("seq\\{}.seq", stem), id);
+            }
+            _ => (),
+        }
+    }
+
+    if let Some(files) = files {
+        let meshes = dependencies
+            .0
+            .iter()
+            .filter(|((kind, _), _)| *kind == AssetKind::Mesh)
+            .map(|((_, path), records)| (path.clone(), records.clone()))
+            .collect::<Vec<_>>();
+
+        for (path, records) in meshes {
+            let textures = match files.read(&path) {
+                Ok(Some(bytes)) => Nif::parse(&bytes).map(|nif| nif.textures()).unwrap_or_default(),
+                _ => continue,
+            };
+
+            for texture in textures {
+                for record in &records {
+                    dependencies.add(AssetKind::Texture, texture.clone(), Some(*record));
+                }
+            }
+        }
+    }
+
+    for quest in Dialogue::build(&plugin.plugin)?.quests {
+        let topics = quest
+            .branches
+            .iter()
+            .flat_map(|branch| &branch.topics)
+            .chain(&quest.topics);
+
+        for topic in topics {
+            for info in &topic.infos {
+                for path in info_voice_paths(plugin, load_order, &topic.topic, info) {
+                    dependencies.add(AssetKind::Voice, path, Some(info.id));
+                }
+            }
+        }
+    }
+
+    if plugin.plugin.tes4.header.flags.contains(PluginFlags::LOCALIZED) {
+        for extension in STRINGS_EXTENSIONS {
+            dependencies.add(
+                AssetKind::Strings,
+                format!("strings\\{}_{}.{}", stem, language, extension),
+                None,
+            );
+        }
+    }
+
+    Ok(dependencies
+        .0
+        .into_iter()
+        .map(|((kind, path), records)| Dependency { kind, path, records })
+        .collect())
+}
+
+/// The facegen mesh and tint of an NPC. They are named after the plugin defining the NPC, even when a later plugin
+/// overrides it
+pub fn facegen_paths(npc: &GlobalFormId) -> [String; 2] {
+    let name = format!("{}\\{:08x}", npc.plugin, npc.object_id);
+
+    [
+        normalize_path(&format!(
+            "meshes\\actors\\character\\facegendata\\facegeom\\{}.nif",
+            name
+        )),
+        normalize_path(&format!(
+            "textures\\actors\\character\\facegendata\\facetint\\{}.dds",
+            name
+        )),
+    ]
+}
+
+/// The voice file of one of an INFO's responses, named after the plugin defining the INFO. Long quest and topic
+/// editor IDs are cut short, as the Creation Kit does
+pub fn voice_path(info: &GlobalFormId, voice_type: &str, quest: &str, topic: &str, response: u8) -> String {
+    let (quest, topic) = if quest.chars().count() + topic.chars().count() > 25 {
+        (
+            quest.chars().take(10).collect::<String>(),
+            topic.chars().take(15).collect::<String>(),
+        )
+    } else {
+        (String::from(quest), String::from(topic))
+    };
+
+    normalize_path(&format!(
+        "sound\\voice\\{}\\{}\\{}_{}_{:08x}_{}.fuz",
+        info.plugin, voice_type, quest, topic, info.object_id, response
+    ))
+}
+
+/// The winning version of a form the plugin refers to, with the plugin it comes from. Falls back to the plugin's own
+/// version when the plugin isn't part of the load order
+fn resolve<'a>(
+    plugin: &'a LoadedPlugin,
+    load_order: &'a LoadOrder,
+    id: FormId,
+) -> Option<(&'a LoadedPlugin, &'a Record)> {
+    let global = plugin.global_form_id(id);
+
+    load_order
+        .winning_record(&global)
+        .or_else(|| plugin.record(&global).map(|record| (plugin, record)))
+}
+
+/// The facegen of one of the plugin's NPCs, or `None` if the winning version takes its face from a template or the
+/// NPC can't be found
+pub fn npc_facegen_paths(plugin: &LoadedPlugin, load_order: &LoadOrder, npc: FormId) -> Option<[String; 2]> {
+    let (_, record) = resolve(plugin, load_order, npc)?;
+
+    if &*record.header.code == b"NPC_" && has_facegen(record) {
+        Some(facegen_paths(&plugin.global_form_id(npc)))
+    } else {
+        None
+    }
+}
+
+/// Whether the engine expects a facegen mesh and tint for the NPC, which it doesn't when a template supplies the face
+fn has_facegen(record: &Record) -> bool {
+    let template_flags = record
+        .subrecord(b"ACBS")
+        .and_then(|acbs| acbs.data.get(18..20))
+        .map_or(0, |flags| u16::from_le_bytes([flags[0], flags[1]]));
+
+    record.subrecord(b"TPLT").is_none() || template_flags & USE_TRAITS == 0
+}
+
+/// A FormID subrecord of a record, in the terms of the plugin the record comes from
+fn form_id_field(record: &Record, code: &[u8; 4]) -> Option<FormId> {
+    Some(form_id(record.subrecord(code)?.data).ok()?.1)
+}
+
+/// The editor IDs of the voice types a form stands for: a voice type itself, an NPC's voice type, or the voice types
+/// in a form list
+fn voice_types(plugin: &LoadedPlugin, load_order: &LoadOrder, id: FormId, names: &mut Vec<String>) {
+    let (owner, record) = match resolve(plugin, load_order, id) {
+        Some(resolved) => resolved,
+        None => return,
+    };
+
+    match &*record.header.code {
+        b"VTYP" => {
+            if let Some(editor_id) = &record.header.editor_id {
+                if !names.iter().any(|name| name.eq_ignore_ascii_case(editor_id)) {
+                    names.push(editor_id.clone());
+                }
+            }
+        }
+        b"NPC_" => {
+            if let Some(voice_type) = form_id_field(record, b"VTCK") {
+                voice_types(owner, load_order, voice_type, names);
+            }
+        }
+        b"FLST" => {
+            for entry in record.subrecords().into_iter().filter(|entry| &*entry.code == b"LNAM") {
+                if let Ok((_, entry)) = form_id(entry.data) {
+                    // Lists of lists aren't followed, so a list containing itself can't recurse forever
+                    if resolve(owner, load_order, entry).is_some_and(|(_, record)| &*record.header.code != b"FLST") {
+                        voice_types(owner, load_order, entry, names);
+                    }
+                }
+            }
+        }
+        _ => (),
+    }
+}
+
+/// The voice files of an INFO's responses, for every voice type that can speak it: the speaker's, and those the
+/// INFO's conditions require with GetIsVoiceType or GetIsID. Quests, speakers and voice types are looked up through
+/// the load order. Shared INFOs have no files of their own
+pub fn info_voice_paths(plugin: &LoadedPlugin, load_order: &LoadOrder, topic: &Topic, info: &Info) -> Vec<String> {
+    if *info.shared != 0 || info.responses.is_empty() {
+        return vec![];
+    }
+
+    let quest = match resolve(plugin, load_order, topic.quest).and_then(|(_, quest)| quest.header.editor_id.as_ref()) {
+        Some(quest) => quest,
+        None => return vec![],
+    };
+    let topic_name = topic.editor_id.as_deref().unwrap_or_default();
+
+    let mut names = vec![];
+
+    if *info.speaker != 0 {
+        voice_types(plugin, load_order, info.speaker, &mut names);
+    }
+
+    for condition in &info.conditions {
+        let is_true = condition.comparison == Comparison::Equal && condition.value == ComparisonValue::Float(1.0);
+
+        if is_true
+            && condition
+                .function()
+                .is_some_and(|function| function.name == "GetIsVoiceType" || function.name == "GetIsID")
+        {
+            voice_types(plugin, load_order, FormId::from(condition.params[0]), &mut names);
+        }
+    }
+
+    let info_id = plugin.global_form_id(info.id);
+    let mut paths = vec![];
+
+    for name in &names {
+        for response in &info.responses {
+            paths.push(voice_path(&info_id, name, quest, topic_name, response.number));
+        }
+    }
+
+    paths
+}
@@ -0,0 +1,222 @@
+//! Skyrim BSA archives: the file listing, and reading files that are stored uncompressed or zlib-compressed
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use crate::parsers::common::decode_string;
+
+use flate2::read::ZlibDecoder;
+use nom::{
+    bytes::complete::{tag, take},
+    combinator::map,
+    multi::count,
+    number::complete::{le_u32, le_u64, le_u8},
+    sequence::tuple,
+};
+
+const HEADER_SIZE: usize = 36;
+
+/// Archive flags
+pub const INCLUDE_DIRECTORY_NAMES: u32 = 0x001;
+pub const INCLUDE_FILE_NAMES: u32 = 0x002;
+pub const COMPRESSED: u32 = 0x004;
+pub const EMBED_FILE_NAMES: u32 = 0x100;
+
+/// Set in a file's size when its compression differs from the archive default
+const COMPRESSION_TOGGLE: u32 = 0x4000_0000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchiveFile {
+    /// Lowercase, backslash-separated, as archives store them
+    pub path: String,
+    pub size: u32,
+    pub offset: u32,
+    pub compressed: bool,
+}
+
+/// The index of a BSA. Files are read from disk on request
+#[derive(Debug, Clone)]
+pub struct Archive {
+    pub path: PathBuf,
+    /// 104 for Skyrim, 105 for Skyrim Special Edition
+    pub version: u32,
+    pub flags: u32,
+    pub files: Vec<ArchiveFile>,
+    by_path: HashMap<String, usize>,
+}
+
+/// The form archives store paths in: lowercase with backslashes
+pub fn normalize_path(path: &str) -> String {
+    path.replace('/', "\\").to_ascii_lowercase()
+}
+
+impl Archive {
+    pub fn open<P>(path: P) -> Result<Self, crate::Error>
+    where
+        P: AsRef<Path>,
+    {
+        let mut file = File::open(path.as_ref())?;
+
+        let mut header = [0u8; HEADER_SIZE];
+        file.read_exact(&mut header)?;
+
+        let (_, (_, version, offset, flags, folder_count, file_count, folder_names_size, file_names_size)) =
+            tuple((tag(b"BSA\0"), le_u32, le_u32, le_u32, le_u32, le_u32, le_u32, le_u32))(&header[..]).map_err(
+                |_: nom::Err<crate::Error>| crate::Error::CorruptOrInvalidFile(path.as_ref().display().to_string()),
+            )?;
+
+        if version != 104 && version != 105 {
+            return Err(crate::Error::CorruptOrInvalidFile(format!(
+                "{}: unsupported BSA version {}",
+                path.as_ref().display(),
+                version
+            )));
+        }
+
+        let folder_record_size = if version == 105 { 24 } else { 16 };
+        let mut index_size = folder_count as usize * folder_record_size + file_count as usize * 16;
+
+        if flags & INCLUDE_DIRECTORY_NAMES != 0 {
+            // Each name is preceded by a length byte not counted in the total
+            index_size += folder_count as usize + folder_names_size as usize;
+        }
+        if flags & INCLUDE_FILE_NAMES != 0 {
+            index_size += file_names_size as usize;
+        }
+
+        let mut index = vec![0u8; index_size];
+        file.seek(SeekFrom::Start(offset as u64))?;
+        file.read_exact(&mut index)?;
+
+        let files = index_files(&index, version, flags, folder_count as usize)?.1;
+        let by_path = files
+            .iter()
+            .enumerate()
+            .map(|(position, file)| (file.path.clone(), position))
+            .collect();
+
+        Ok(Archive {
+            path: path.as_ref().to_path_buf(),
+            version,
+            flags,
+            files,
+            by_path,
+        })
+    }
+
+    pub fn get(&self, path: &str) -> Option<&ArchiveFile> {
+        self.by_path
+            .get(&normalize_path(path))
+            .map(|position| &self.files[*position])
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.get(path).is_some()
+    }
+
+    /// Read a file's contents. Files compressed with LZ4, as Special Edition archives use, can't be read
+    pub fn read(&self, path: &str) -> Result<Option<Vec<u8>>, crate::Error> {
+        let entry = match self.get(path) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+
+        let mut file = File::open(&self.path)?;
+        let mut data = vec![0u8; entry.size as usize];
+        file.seek(SeekFrom::Start(entry.offset as u64))?;
+        file.read_exact(&mut data)?;
+
+        let mut data = &data[..];
+
+        if self.flags & EMBED_FILE_NAMES != 0 {
+            data = bstring(data)?.0;
+        }
+
+        if !entry.compressed {
+            return Ok(Some(data.to_vec()));
+        }
+
+        if self.version == 105 {
+            return Err(crate::Error::CorruptOrInvalidFile(format!(
+                "{} is LZ4-compressed",
+                entry.path
+            )));
+        }
+
+        let (data, original_size) = le_u32(data)?;
+
+        let mut decompressed = Vec::with_capacity(original_size as usize);
+        ZlibDecoder::new(data).read_to_end(&mut decompressed)?;
+
+        Ok(Some(decompressed))
+    }
+}
+
+/// A name preceded by its length, which may or may not include a null terminator
+fn bstring(bytes: &[u8]) -> crate::IResult<&[u8], String> {
+    let (bytes, length) = le_u8(bytes)?;
+    let (bytes, name) = take(length)(bytes)?;
+
+    Ok((bytes, decode_string(name.strip_suffix(&[0]).unwrap_or(name))))
+}
+
+fn index_files(bytes: &[u8], version: u32, flags: u32, folder_count: usize) -> crate::IResult<&[u8], Vec<ArchiveFile>> {
+    let folder_record = |bytes| {
+        if version == 105 {
+            map(tuple((le_u64, le_u32, le_u32, le_u64)), |(_, count, _, _)| count)(bytes)
+        } else {
+            map(tuple((le_u64, le_u32, le_u32)), |(_, count, _)| count)(bytes)
+        }
+    };
+
+    let (mut bytes, file_counts) = count(folder_record, folder_count)(bytes)?;
+    let mut folders = vec![];
+
+    for file_count in file_counts {
+        let (rest, folder) = if flags & INCLUDE_DIRECTORY_NAMES != 0 {
+            bstring(bytes)?
+        } else {
+            (bytes, String::new())
+        };
+
+        let (rest, records) = count(tuple((le_u64, le_u32, le_u32)), file_count as usize)(rest)?;
+        folders.push((folder, records));
+        bytes = rest;
+    }
+
+    let mut files = vec![];
+    let compressed_by_default = flags & COMPRESSED != 0;
+
+    for (folder, records) in folders {
+        for (_, size, offset) in records {
+            let (rest, name) = if flags & INCLUDE_FILE_NAMES != 0 {
+                let end = bytes.iter().position(|byte| *byte == 0).unwrap_or(bytes.len());
+                let name = decode_string(&bytes[..end]);
+                (&bytes[(end + 1).min(bytes.len())..], name)
+            } else {
+                (bytes, String::new())
+            };
+
+            let path = if folder.is_empty() {
+                name
+            } else {
+                format!("{}\\{}", folder, name)
+            };
+
+            files.push(ArchiveFile {
+                path: normalize_path(&path),
+                size: size & !COMPRESSION_TOGGLE,
+                offset,
+                compressed: compressed_by_default != (size & COMPRESSION_TOGGLE != 0),
+            });
+
+            bytes = rest;
+        }
+    }
+
+    Ok((bytes, files))
+}
@@ -0,0 +1,189 @@
+//! Creating new records from scratch, for generating content rather than editing what a plugin already holds
+
+use crate::{
+    merge::FIRST_OBJECT_ID,
+    parsers::{
+        common::{encode_string, FormId, TypeCode},
+        plugin::Plugin,
+        records::{Record, RecordData, RecordHeader},
+    },
+};
+
+pub use crate::parsers::records::flags::RecordFlags;
+
+const MAX_OBJECT_ID: u32 = 0x00FF_FFFF;
+
+/// The form version records get when neither the builder nor the plugin gives one: Skyrim Special Edition's
+pub const DEFAULT_FORM_VERSION: u16 = 44;
+
+/// Claim the next free FormID for a new record of the plugin, starting from `Hedr::next_id`, and move `next_id`
+/// past it
+pub fn allocate_form_id(plugin: &mut Plugin) -> Result<FormId, crate::Error> {
+    let index = plugin.masters().len() as u8;
+    let mut object_id = plugin.file_header().hedr.next_id.object_id().max(FIRST_OBJECT_ID);
+
+    while plugin.record(FormId::from(object_id).with_index(index)).is_some() {
+        object_id += 1;
+    }
+
+    if object_id > MAX_OBJECT_ID {
+        return Err(crate::Error::CorruptOrInvalidFile(String::from(
+            "no FormIDs left for new records",
+        )));
+    }
+
+    plugin.file_header_mut().hedr.next_id = FormId::from(object_id + 1);
+
+    Ok(FormId::from(object_id).with_index(index))
+}
+
+/// A new record, built up subrecord by subrecord. Subrecords are written in the order they are added, after the
+/// editor ID
+#[derive(Debug, Clone)]
+pub struct RecordBuilder {
+    code: TypeCode,
+    editor_id: Option<String>,
+    flags: RecordFlags,
+    version: Option<u16>,
+    subrecords: Vec<(TypeCode, Vec<u8>)>,
+}
+
+impl RecordBuilder {
+    pub fn new(code: [u8; 4]) -> Self {
+        RecordBuilder {
+            code: TypeCode::from(code),
+            editor_id: None,
+            flags: RecordFlags::empty(),
+            version: None,
+            subrecords: vec![],
+        }
+    }
+
+    pub fn editor_id(mut self, editor_id: &str) -> Self {
+        self.editor_id = Some(String::from(editor_id));
+        self
+    }
+
+    pub fn flags(mut self, flags: RecordFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Whether the record's data is zlib-compressed when the plugin is written
+    pub fn compressed(mut self, compressed: bool) -> Self {
+        self.flags.set(RecordFlags::COMPRESSED, compressed);
+        self
+    }
+
+    /// The form version of the record header. Records added with `insert` otherwise take the plugin's
+    pub fn version(mut self, version: u16) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    /// A subrecord with raw data
+    pub fn subrecord(mut self, code: [u8; 4], data: Vec<u8>) -> Self {
+        self.subrecords.push((TypeCode::from(code), data));
+        self
+    }
+
+    /// A null-terminated string, as in FULL for plugins that aren't localized
+    pub fn string(self, code: [u8; 4], value: &str) -> Self {
+        let mut data = encode_string(value);
+        data.push(0);
+        self.subrecord(code, data)
+    }
+
+    pub fn form_id(self, code: [u8; 4], id: FormId) -> Self {
+        self.subrecord(code, id.to_le_bytes().to_vec())
+    }
+
+    /// A packed array of FormIDs
+    pub fn form_ids(self, code: [u8; 4], ids: &[FormId]) -> Self {
+        self.subrecord(code, ids.iter().flat_map(|id| id.to_le_bytes()).collect())
+    }
+
+    pub fn u8(self, code: [u8; 4], value: u8) -> Self {
+        self.subrecord(code, vec![value])
+    }
+
+    pub fn u16(self, code: [u8; 4], value: u16) -> Self {
+        self.subrecord(code, value.to_le_bytes().to_vec())
+    }
+
+    pub fn u32(self, code: [u8; 4], value: u32) -> Self {
+        self.subrecord(code, value.to_le_bytes().to_vec())
+    }
+
+    pub fn i32(self, code: [u8; 4], value: i32) -> Self {
+        self.subrecord(code, value.to_le_bytes().to_vec())
+    }
+
+    pub fn f32(self, code: [u8; 4], value: f32) -> Self {
+        self.subrecord(code, value.to_le_bytes().to_vec())
+    }
+
+    /// OBND, the object bounds of placeable objects
+    pub fn bounds(self, min: [i16; 3], max: [i16; 3]) -> Self {
+        let data = min.iter().chain(&max).flat_map(|value| value.to_le_bytes()).collect();
+        self.subrecord(*b"OBND", data)
+    }
+
+    /// KSIZ and KWDA, leaving both out when there are no keywords
+    pub fn keywords(self, keywords: &[FormId]) -> Self {
+        if keywords.is_empty() {
+            return self;
+        }
+
+        self.u32(*b"KSIZ", keywords.len() as u32).form_ids(*b"KWDA", keywords)
+    }
+
+    /// The record with the given FormID
+    pub fn build(self, id: FormId) -> Record {
+        let mut record = Record {
+            header: RecordHeader {
+                code: self.code,
+                size: 0,
+                flags: self.flags,
+                unknown_flags: 0,
+                id,
+                timestamp: 0,
+                vc_info: 0,
+                version: self.version.unwrap_or(DEFAULT_FORM_VERSION),
+                unknown: 0,
+                editor_id: None,
+            },
+            data: RecordData::Unknown(vec![]),
+            compressed: None,
+        };
+
+        let editor_id = self.editor_id.map(|editor_id| {
+            let mut data = encode_string(&editor_id);
+            data.push(0);
+            (TypeCode::from(*b"EDID"), data)
+        });
+        let mut subrecords = self.subrecords;
+
+        record.edit_subrecords(|existing| {
+            existing.extend(editor_id);
+            existing.append(&mut subrecords);
+        });
+
+        record
+    }
+
+    /// Build the record with a newly allocated FormID, add it to its top group in the plugin and return its FormID
+    pub fn insert(mut self, plugin: &mut Plugin) -> Result<FormId, crate::Error> {
+        self.version = self.version.or(Some(plugin.tes4.header.version));
+
+        let next_id = plugin.file_header().hedr.next_id;
+        let id = allocate_form_id(plugin)?;
+
+        if let Err(err) = plugin.insert_record(self.build(id)) {
+            plugin.file_header_mut().hedr.next_id = next_id;
+            return Err(err);
+        }
+
+        Ok(id)
+    }
+}
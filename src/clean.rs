@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+
+use crate::{
+    load_order::{LoadOrder, LoadedPlugin},
+    parsers::{
+        common::{FormId, TypeCode},
+        plugin::Plugin,
+        records::{flags::RecordFlags, Record, RecordData},
+    },
+};
+
+/// Z position deleted references are moved to when they are undeleted and disabled, as xEdit does
+pub const DISABLED_REFERENCE_Z: f32 = -30000.0;
+
+const PLAYER_REF: u32 = 0x00000014;
+/// XESP flag making the reference's enable state the opposite of its parent's
+const XESP_OPPOSITE_OF_PARENT: u8 = 0x01;
+
+/// Records a cleaning pass would fix
+#[derive(Debug, Default)]
+pub struct CleaningReport {
+    /// Overrides identical to the version found in the plugin's masters
+    pub identical_to_master: Vec<FormId>,
+    /// Deleted placed references, which should be undeleted and disabled instead
+    pub deleted_references: Vec<FormId>,
+}
+
+impl CleaningReport {
+    pub fn is_clean(&self) -> bool {
+        self.identical_to_master.is_empty() && self.deleted_references.is_empty()
+    }
+}
+
+/// Find Identical-To-Master records and deleted references in a plugin. `masters` must hold the plugin's
+/// masters, along with their own masters; records referring to any that are missing are skipped.
+///
+/// CELL, WRLD and DIAL records are never reported as ITMs, since they head child groups that still depend on
+/// them.
+pub fn check(plugin: &LoadedPlugin, masters: &LoadOrder) -> CleaningReport {
+    let mut report = CleaningReport::default();
+
+    // Indexed once up front, as looking records up in each master's nested groups one at a time is far too slow
+    let master_records = plugin
+        .plugin
+        .masters()
+        .iter()
+        .rev()
+        .filter_map(|name| masters.get(name))
+        .map(|master| (master, records_by_id(&master.plugin)))
+        .collect::<Vec<_>>();
+
+    for record in plugin.plugin.records() {
+        let code = record.header.code.to_string();
+
+        if record.header.flags.contains(RecordFlags::DELETED) {
+            if let "REFR" | "ACHR" = code.as_str() {
+                report.deleted_references.push(record.header.id);
+            }
+
+            continue;
+        }
+
+        if plugin.is_new_record(record.header.id) {
+            continue;
+        }
+
+        if let "CELL" | "WRLD" | "DIAL" = code.as_str() {
+            continue;
+        }
+
+        if let Some((master, master_record)) = master_version(plugin, &master_records, record.header.id) {
+            if identical(masters, (plugin, record), (master, master_record)) {
+                report.identical_to_master.push(record.header.id);
+            }
+        }
+    }
+
+    report.identical_to_master.sort();
+    report.deleted_references.sort();
+    report
+}
+
+/// Apply a cleaning report: ITM records are removed and deleted references are undeleted and disabled.
+/// `Hedr::num_records` and the ONAM overrides are updated to match.
+pub fn clean(plugin: &mut Plugin, report: &CleaningReport) {
+    for id in &report.identical_to_master {
+        plugin.remove_record(*id);
+    }
+
+    for id in &report.deleted_references {
+        if let Some(record) = plugin.record_mut(*id) {
+            undelete_and_disable(record);
+        }
+    }
+
+    plugin.update_num_records();
+    plugin.update_overrides();
+}
+
+fn records_by_id(plugin: &Plugin) -> HashMap<FormId, &Record> {
+    plugin
+        .records()
+        .into_iter()
+        .map(|record| (record.header.id, record))
+        .collect()
+}
+
+/// The version of a record this plugin overrides: the one in the last of its masters that holds it. `masters` are
+/// the plugin's masters in reverse order, each with its records by FormID
+fn master_version<'a, 'b>(
+    plugin: &LoadedPlugin,
+    masters: &[(&'b LoadedPlugin, HashMap<FormId, &'a Record>)],
+    id: FormId,
+) -> Option<(&'b LoadedPlugin, &'a Record)> {
+    let global_id = plugin.global_form_id(id);
+
+    masters.iter().find_map(|(master, records)| {
+        master
+            .local_form_id(&global_id)
+            .and_then(|id| records.get(&id))
+            .map(|record| (*master, *record))
+    })
+}
+
+/// Compare two versions of a record, ignoring whether either is stored compressed. FormIDs are compared in load order
+/// terms, as the plugin and its master may list their own masters in a different order; records that can't be
+/// resolved, such as those holding subrecords of unknown layout, never compare identical
+fn identical(load_order: &LoadOrder, record: (&LoadedPlugin, &Record), master: (&LoadedPlugin, &Record)) -> bool {
+    let flags = record.1.header.flags & !RecordFlags::COMPRESSED;
+    let master_flags = master.1.header.flags & !RecordFlags::COMPRESSED;
+
+    let same_size = match (&record.1.data, &master.1.data) {
+        (RecordData::Unknown(data), RecordData::Unknown(master_data)) => data.len() == master_data.len(),
+        _ => false,
+    };
+
+    if record.1.header.code != master.1.header.code || flags != master_flags || !same_size {
+        return false;
+    }
+
+    match (
+        load_order.absolute_record(record.0, record.1),
+        load_order.absolute_record(master.0, master.1),
+    ) {
+        (Ok(record), Ok(master)) => subrecords(&record) == subrecords(&master),
+        _ => false,
+    }
+}
+
+fn subrecords(record: &Record) -> Vec<(TypeCode, Vec<u8>)> {
+    record
+        .subrecords()
+        .into_iter()
+        .map(|subrecord| (subrecord.code, subrecord.data.to_vec()))
+        .collect()
+}
+
+/// Restore a deleted reference as disabled, parented to the player with the opposite enable state and moved
+/// below the world, so anything depending on it keeps working
+pub fn undelete_and_disable(record: &mut Record) {
+    record.header.flags.remove(RecordFlags::DELETED);
+    record.header.flags.insert(RecordFlags::INITIALLY_DISABLED);
+
+    let xesp = TypeCode::from(*b"XESP");
+    let data = TypeCode::from(*b"DATA");
+
+    record.edit_subrecords(|subrecords| {
+        subrecords.retain(|(code, _)| *code != xesp);
+
+        let mut parent = PLAYER_REF.to_le_bytes().to_vec();
+        parent.extend_from_slice(&[XESP_OPPOSITE_OF_PARENT, 0, 0, 0]);
+
+        let position = subrecords
+            .iter()
+            .position(|(code, _)| *code == data)
+            .unwrap_or(subrecords.len());
+
+        subrecords.insert(position, (xesp.clone(), parent));
+
+        if let Some((_, bytes)) = subrecords.iter_mut().find(|(code, _)| *code == data) {
+            if bytes.len() >= 12 {
+                bytes[8..12].copy_from_slice(&DISABLED_REFERENCE_Z.to_le_bytes());
+            }
+        }
+    });
+}
@@ -0,0 +1,373 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Write,
+};
+
+use crate::{
+    json,
+    parsers::{
+        common::{lstring, FormId, LString, TypeCode},
+        group::{GroupChild, GroupData, GroupType, Label},
+        plugin::Plugin,
+        records::{condition, flags::PluginFlags},
+    },
+};
+
+pub use crate::parsers::records::dialogue::{Branch, Info, Response, Topic};
+
+/// A topic with its INFOs, ordered by their PNAM chain
+#[derive(Debug, Clone)]
+pub struct TopicDialogue {
+    pub topic: Topic,
+    pub infos: Vec<Info>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BranchDialogue {
+    pub branch: Branch,
+    /// Starting topic first, then the rest by FormID
+    pub topics: Vec<TopicDialogue>,
+}
+
+/// Dialogue owned by one quest. The quest itself may be defined in a master, in which case only its FormID is known
+#[derive(Debug, Clone)]
+pub struct QuestDialogue {
+    pub quest: FormId,
+    pub editor_id: Option<String>,
+    pub name: Option<LString>,
+    pub branches: Vec<BranchDialogue>,
+    /// Topics outside any branch, such as scenes and combat barks
+    pub topics: Vec<TopicDialogue>,
+}
+
+/// The dialogue tree of a plugin: quests, their dialogue branches, topics and responses
+#[derive(Debug, Clone, Default)]
+pub struct Dialogue {
+    pub quests: Vec<QuestDialogue>,
+}
+
+impl Dialogue {
+    pub fn build(plugin: &Plugin) -> Result<Self, crate::Error> {
+        let localized = plugin.tes4.header.flags.contains(PluginFlags::LOCALIZED);
+
+        let mut topics = vec![];
+        let mut infos: HashMap<FormId, Vec<Info>> = HashMap::new();
+
+        if let Some(GroupData::Children(children)) =
+            plugin.groups.get(&TypeCode::from(*b"DIAL")).map(|group| &group.data)
+        {
+            for child in children {
+                match child {
+                    GroupChild::Record(record) if &*record.header.code == b"DIAL" => {
+                        topics.push(Topic::decode(record, localized)?);
+                    }
+                    GroupChild::Group(group) => {
+                        if let (GroupType::TopicChildren, Label::ParentDialog(topic)) = (group.group_type, &group.label)
+                        {
+                            for record in group.records() {
+                                if &*record.header.code == b"INFO" {
+                                    infos.entry(*topic).or_default().push(Info::decode(record, localized)?);
+                                }
+                            }
+                        }
+                    }
+                    GroupChild::Record(_) => (),
+                }
+            }
+        }
+
+        let mut quests: BTreeMap<FormId, QuestDialogue> = BTreeMap::new();
+
+        let mut branches = plugin
+            .records()
+            .into_iter()
+            .filter(|record| &*record.header.code == b"DLBR")
+            .map(Branch::decode)
+            .collect::<Result<Vec<_>, _>>()?;
+        branches.sort_by_key(|branch| branch.id);
+
+        let branch_ids = branches.iter().map(|branch| branch.id).collect::<HashSet<_>>();
+        let mut branch_topics: HashMap<FormId, Vec<TopicDialogue>> = HashMap::new();
+
+        topics.sort_by_key(|topic| topic.id);
+
+        for topic in topics {
+            let dialogue = TopicDialogue {
+                infos: order_infos(infos.remove(&topic.id).unwrap_or_default()),
+                topic,
+            };
+
+            if branch_ids.contains(&dialogue.topic.branch) {
+                branch_topics.entry(dialogue.topic.branch).or_default().push(dialogue);
+            } else {
+                quest(&mut quests, dialogue.topic.quest).topics.push(dialogue);
+            }
+        }
+
+        for branch in branches {
+            let mut topics = branch_topics.remove(&branch.id).unwrap_or_default();
+            topics.sort_by_key(|topic| (topic.topic.id != branch.starting_topic, topic.topic.id));
+
+            quest(&mut quests, branch.quest)
+                .branches
+                .push(BranchDialogue { branch, topics });
+        }
+
+        for record in plugin.records() {
+            if &*record.header.code == b"QUST" {
+                if let Some(quest) = quests.get_mut(&record.header.id) {
+                    quest.editor_id = record.header.editor_id.clone();
+                    quest.name = match record.subrecord(b"FULL") {
+                        Some(full) => Some(lstring(full.data, localized)?.1),
+                        None => None,
+                    };
+                }
+            }
+        }
+
+        Ok(Dialogue {
+            quests: quests.into_values().collect(),
+        })
+    }
+
+    /// A Graphviz graph of quests, branches, topics and INFOs. Dashed edges lead to the topics an INFO links to
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph dialogue {\n    rankdir=LR;\n    node [shape=box];\n");
+
+        for quest in &self.quests {
+            let quest_node = node_id('q', quest.quest);
+            let label = match (&quest.editor_id, &quest.name) {
+                (Some(editor_id), Some(name)) => format!("{}\n{}", editor_id, name),
+                (Some(editor_id), None) => editor_id.clone(),
+                _ => quest.quest.to_string(),
+            };
+
+            writeln!(dot, "    {} [label={}, shape=folder];", quest_node, dot_string(&label)).unwrap();
+
+            for branch in &quest.branches {
+                let branch_node = node_id('b', branch.branch.id);
+                let label = branch
+                    .branch
+                    .editor_id
+                    .clone()
+                    .unwrap_or_else(|| branch.branch.id.to_string());
+
+                writeln!(
+                    dot,
+                    "    {} [label={}, shape=component];",
+                    branch_node,
+                    dot_string(&label)
+                )
+                .unwrap();
+                writeln!(dot, "    {} -> {};", quest_node, branch_node).unwrap();
+
+                for topic in &branch.topics {
+                    write_topic(&mut dot, &branch_node, topic);
+                }
+            }
+
+            for topic in &quest.topics {
+                write_topic(&mut dot, &quest_node, topic);
+            }
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+
+    pub fn to_json(&self) -> String {
+        let quests = self
+            .quests
+            .iter()
+            .map(|quest| {
+                format!(
+                    "{{\"form_id\":{},\"editor_id\":{},\"name\":{},\"branches\":[{}],\"topics\":[{}]}}",
+                    json_form_id(quest.quest),
+                    json::optional_string(quest.editor_id.as_deref()),
+                    json_lstring(quest.name.as_ref()),
+                    quest
+                        .branches
+                        .iter()
+                        .map(|branch| format!(
+                            "{{\"form_id\":{},\"editor_id\":{},\"starting_topic\":{},\"topics\":[{}]}}",
+                            json_form_id(branch.branch.id),
+                            json::optional_string(branch.branch.editor_id.as_deref()),
+                            json_form_id(branch.branch.starting_topic),
+                            json_list(&branch.topics, topic_json),
+                        ))
+                        .collect::<Vec<_>>()
+                        .join(","),
+                    json_list(&quest.topics, topic_json),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        format!("{{\"quests\":[{}]}}", quests.join(","))
+    }
+}
+
+fn quest(quests: &mut BTreeMap<FormId, QuestDialogue>, id: FormId) -> &mut QuestDialogue {
+    quests.entry(id).or_insert_with(|| QuestDialogue {
+        quest: id,
+        editor_id: None,
+        name: None,
+        branches: vec![],
+        topics: vec![],
+    })
+}
+
+/// Follow each INFO's PNAM to put them in the order the topic lists them. INFOs that follow nothing in the topic
+/// come first, in FormID order, and any caught in a cycle come last
+fn order_infos(mut infos: Vec<Info>) -> Vec<Info> {
+    infos.sort_by_key(|info| info.id);
+
+    let ids = infos.iter().map(|info| info.id).collect::<HashSet<_>>();
+    let mut next: HashMap<FormId, Vec<usize>> = HashMap::new();
+    let mut stack = vec![];
+
+    for (index, info) in infos.iter().enumerate().rev() {
+        if ids.contains(&info.previous) && info.previous != info.id {
+            next.entry(info.previous).or_default().insert(0, index);
+        } else {
+            stack.push(index);
+        }
+    }
+
+    let mut placed = vec![false; infos.len()];
+    let mut order = vec![];
+
+    while let Some(index) = stack.pop() {
+        if placed[index] {
+            continue;
+        }
+
+        placed[index] = true;
+        order.push(index);
+
+        if let Some(following) = next.get(&infos[index].id) {
+            stack.extend(following.iter().rev());
+        }
+    }
+
+    order.extend((0..infos.len()).filter(|index| !placed[*index]));
+
+    let mut infos = infos.into_iter().map(Some).collect::<Vec<_>>();
+    order.into_iter().filter_map(|index| infos[index].take()).collect()
+}
+
+fn write_topic(dot: &mut String, parent: &str, topic: &TopicDialogue) {
+    let topic_node = node_id('t', topic.topic.id);
+    let mut label = topic
+        .topic
+        .editor_id
+        .clone()
+        .unwrap_or_else(|| topic.topic.id.to_string());
+
+    if let Some(prompt) = &topic.topic.prompt {
+        write!(label, "\n{}", prompt).unwrap();
+    }
+
+    writeln!(dot, "    {} [label={}];", topic_node, dot_string(&label)).unwrap();
+    writeln!(dot, "    {} -> {};", parent, topic_node).unwrap();
+
+    for info in &topic.infos {
+        let info_node = node_id('i', info.id);
+        let mut label = info.id.to_string();
+
+        if let Some(conditions) = conditions(info) {
+            write!(label, "\n[{}]", conditions).unwrap();
+        }
+
+        if let Some(prompt) = &info.prompt {
+            write!(label, "\n> {}", prompt).unwrap();
+        }
+
+        if !info.scripts.is_empty() {
+            write!(label, "\nScripts: {}", info.scripts.join(", ")).unwrap();
+        }
+
+        for response in &info.responses {
+            if let Some(text) = &response.text {
+                write!(label, "\n{}", text).unwrap();
+            }
+        }
+
+        writeln!(dot, "    {} [label={}, shape=note];", info_node, dot_string(&label)).unwrap();
+        writeln!(dot, "    {} -> {};", topic_node, info_node).unwrap();
+
+        for linked in &info.linked_topics {
+            writeln!(dot, "    {} -> {} [style=dashed];", info_node, node_id('t', *linked)).unwrap();
+        }
+    }
+}
+
+/// The INFO's conditions as one expression, if it has any
+fn conditions(info: &Info) -> Option<String> {
+    if info.conditions.is_empty() {
+        None
+    } else {
+        Some(condition::render_all(&info.conditions, |_| None))
+    }
+}
+
+fn node_id(kind: char, id: FormId) -> String {
+    format!("{}{:08X}", kind, *id)
+}
+
+fn dot_string(value: &str) -> String {
+    format!(
+        "\"{}\"",
+        value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+    )
+}
+
+fn topic_json(topic: &TopicDialogue) -> String {
+    format!(
+        "{{\"form_id\":{},\"editor_id\":{},\"prompt\":{},\"infos\":[{}]}}",
+        json_form_id(topic.topic.id),
+        json::optional_string(topic.topic.editor_id.as_deref()),
+        json_lstring(topic.topic.prompt.as_ref()),
+        json_list(&topic.infos, info_json),
+    )
+}
+
+fn info_json(info: &Info) -> String {
+    format!(
+        "{{\"form_id\":{},\"previous\":{},\"prompt\":{},\"speaker\":{},\"linked_topics\":[{}],\"conditions\":{},\"scripts\":[{}],\"responses\":[{}]}}",
+        json_form_id(info.id),
+        json_form_id(info.previous),
+        json_lstring(info.prompt.as_ref()),
+        json_form_id(info.speaker),
+        info.linked_topics.iter().map(|id| json_form_id(*id)).collect::<Vec<_>>().join(","),
+        json::optional_string(conditions(info).as_deref()),
+        info.scripts.iter().map(|script| json::string(script)).collect::<Vec<_>>().join(","),
+        json_list(&info.responses, |response| format!(
+            "{{\"number\":{},\"emotion\":{},\"emotion_value\":{},\"text\":{},\"notes\":{}}}",
+            response.number,
+            response.emotion,
+            response.emotion_value,
+            json_lstring(response.text.as_ref()),
+            json::optional_string(response.notes.as_deref()),
+        )),
+    )
+}
+
+fn json_list<T, F>(items: &[T], to_json: F) -> String
+where
+    F: Fn(&T) -> String,
+{
+    items.iter().map(to_json).collect::<Vec<_>>().join(",")
+}
+
+/// Null FormIDs become `null`
+fn json_form_id(id: FormId) -> String {
+    if *id == 0 {
+        String::from("null")
+    } else {
+        json::string(&id.to_string())
+    }
+}
+
+fn json_lstring(value: Option<&LString>) -> String {
+    json::optional_string(value.map(LString::to_string).as_deref())
+}
@@ -0,0 +1,437 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
+
+use crate::{
+    json,
+    parsers::{
+        common::{zstring, FormId, Subrecord, TypeCode},
+        plugin::Plugin,
+        records::{
+            condition::Condition,
+            file_header::FileHeaderData,
+            flags::{PluginFlags, RecordFlags},
+            reference::{self, Placement},
+            vmad::Vmad,
+            FileHeaderRecord, Record, RecordData,
+        },
+    },
+};
+
+/// Differences between two versions of the same plugin
+#[derive(Debug, Default)]
+pub struct PluginDiff {
+    pub header: Vec<HeaderChange>,
+    pub added: Vec<RecordSummary>,
+    pub removed: Vec<RecordSummary>,
+    pub modified: Vec<RecordDiff>,
+}
+
+#[derive(Debug)]
+pub struct HeaderChange {
+    pub field: &'static str,
+    pub old: String,
+    pub new: String,
+}
+
+#[derive(Debug)]
+pub struct RecordSummary {
+    pub code: TypeCode,
+    pub id: FormId,
+    pub editor_id: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct RecordDiff {
+    pub record: RecordSummary,
+    pub flags: Option<(RecordFlags, RecordFlags)>,
+    pub subrecords: Vec<SubrecordDiff>,
+}
+
+/// A single subrecord change. Repeated subrecords are paired up by their occurrence `index`
+#[derive(Debug)]
+pub struct SubrecordDiff {
+    pub code: TypeCode,
+    pub index: usize,
+    pub old: Option<SubrecordValue>,
+    pub new: Option<SubrecordValue>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SubrecordValue {
+    Decoded(String),
+    Raw(Vec<u8>),
+}
+
+/// Compare two plugins record by record, matching records by FormID
+pub fn diff(old: &Plugin, new: &Plugin) -> PluginDiff {
+    let old_records = records_by_id(old);
+    let new_records = records_by_id(new);
+    let old_localized = old.tes4.header.flags.contains(PluginFlags::LOCALIZED);
+    let new_localized = new.tes4.header.flags.contains(PluginFlags::LOCALIZED);
+
+    let mut diff = PluginDiff {
+        header: header_changes(&old.tes4, &new.tes4),
+        ..PluginDiff::default()
+    };
+
+    for (id, old_record) in &old_records {
+        match new_records.get(id) {
+            Some(new_record) => {
+                if let Some(record_diff) = record_diff(old_record, new_record, old_localized, new_localized) {
+                    diff.modified.push(record_diff);
+                }
+            }
+            None => diff.removed.push(summary(old_record)),
+        }
+    }
+
+    for (id, new_record) in &new_records {
+        if !old_records.contains_key(id) {
+            diff.added.push(summary(new_record));
+        }
+    }
+
+    diff
+}
+
+impl PluginDiff {
+    pub fn is_empty(&self) -> bool {
+        self.header.is_empty() && self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+
+    pub fn to_json(&self) -> String {
+        let header = self
+            .header
+            .iter()
+            .map(|change| {
+                format!(
+                    "{{\"field\":{},\"old\":{},\"new\":{}}}",
+                    json::string(change.field),
+                    json::string(&change.old),
+                    json::string(&change.new)
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let added = self.added.iter().map(RecordSummary::to_json).collect::<Vec<_>>();
+        let removed = self.removed.iter().map(RecordSummary::to_json).collect::<Vec<_>>();
+
+        let modified = self
+            .modified
+            .iter()
+            .map(|record| {
+                let flags = match &record.flags {
+                    Some((old, new)) => format!(
+                        "{{\"old\":{},\"new\":{}}}",
+                        json::string(&format!("{:?}", old)),
+                        json::string(&format!("{:?}", new))
+                    ),
+                    None => String::from("null"),
+                };
+
+                let subrecords = record
+                    .subrecords
+                    .iter()
+                    .map(|subrecord| {
+                        format!(
+                            "{{\"type\":{},\"index\":{},\"old\":{},\"new\":{}}}",
+                            json::string(&subrecord.code.to_string()),
+                            subrecord.index,
+                            value_json(subrecord.old.as_ref()),
+                            value_json(subrecord.new.as_ref())
+                        )
+                    })
+                    .collect::<Vec<_>>();
+
+                format!(
+                    "{{\"record\":{},\"flags\":{},\"subrecords\":[{}]}}",
+                    record.record.to_json(),
+                    flags,
+                    subrecords.join(",")
+                )
+            })
+            .collect::<Vec<_>>();
+
+        format!(
+            "{{\"header\":[{}],\"added\":[{}],\"removed\":[{}],\"modified\":[{}]}}",
+            header.join(","),
+            added.join(","),
+            removed.join(","),
+            modified.join(",")
+        )
+    }
+}
+
+impl fmt::Display for PluginDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for change in &self.header {
+            writeln!(f, "~ TES4 {}: {} -> {}", change.field, change.old, change.new)?;
+        }
+
+        for record in &self.added {
+            writeln!(f, "+ {}", record)?;
+        }
+
+        for record in &self.removed {
+            writeln!(f, "- {}", record)?;
+        }
+
+        for record in &self.modified {
+            writeln!(f, "~ {}", record.record)?;
+
+            if let Some((old, new)) = &record.flags {
+                writeln!(f, "    flags: {:?} -> {:?}", old, new)?;
+            }
+
+            for subrecord in &record.subrecords {
+                match (&subrecord.old, &subrecord.new) {
+                    (Some(old), Some(new)) => {
+                        writeln!(f, "    {}[{}]: {} -> {}", subrecord.code, subrecord.index, old, new)?
+                    }
+                    (None, Some(new)) => writeln!(f, "    + {}[{}]: {}", subrecord.code, subrecord.index, new)?,
+                    (Some(old), None) => writeln!(f, "    - {}[{}]: {}", subrecord.code, subrecord.index, old)?,
+                    (None, None) => (),
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl RecordSummary {
+    fn to_json(&self) -> String {
+        format!(
+            "{{\"type\":{},\"form_id\":{},\"editor_id\":{}}}",
+            json::string(&self.code.to_string()),
+            json::string(&self.id.to_string()),
+            json::optional_string(self.editor_id.as_deref())
+        )
+    }
+}
+
+impl fmt::Display for RecordSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.editor_id {
+            Some(editor_id) => write!(f, "{} {} \"{}\"", self.code, self.id, editor_id),
+            None => write!(f, "{} {}", self.code, self.id),
+        }
+    }
+}
+
+impl fmt::Display for SubrecordValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SubrecordValue::Decoded(value) => write!(f, "{}", value),
+            SubrecordValue::Raw(bytes) => write!(f, "{}", hex(bytes)),
+        }
+    }
+}
+
+fn value_json(value: Option<&SubrecordValue>) -> String {
+    match value {
+        Some(SubrecordValue::Decoded(value)) => format!("{{\"value\":{}}}", json::string(value)),
+        Some(SubrecordValue::Raw(bytes)) => format!("{{\"hex\":{}}}", json::string(&hex(bytes))),
+        None => String::from("null"),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" ")
+}
+
+fn records_by_id(plugin: &Plugin) -> BTreeMap<FormId, &Record> {
+    plugin
+        .records()
+        .into_iter()
+        .map(|record| (record.header.id, record))
+        .collect()
+}
+
+fn summary(record: &Record) -> RecordSummary {
+    RecordSummary {
+        code: record.header.code.clone(),
+        id: record.header.id,
+        editor_id: record.header.editor_id.clone(),
+    }
+}
+
+fn record_diff(old: &Record, new: &Record, old_localized: bool, new_localized: bool) -> Option<RecordDiff> {
+    let flags = if old.header.flags != new.header.flags {
+        Some((old.header.flags, new.header.flags))
+    } else {
+        None
+    };
+
+    let old_subrecords = keyed(old.subrecords());
+    let new_subrecords = keyed(new.subrecords());
+    let mut subrecords = vec![];
+
+    for (key, old_subrecord) in &old_subrecords {
+        match new_subrecords.iter().find(|(new_key, _)| new_key == key) {
+            Some((_, new_subrecord)) if new_subrecord.data == old_subrecord.data => (),
+            Some((_, new_subrecord)) => subrecords.push(SubrecordDiff {
+                code: key.0.clone(),
+                index: key.1,
+                old: Some(value(&old.header.code, old_subrecord, old_localized)),
+                new: Some(value(&new.header.code, new_subrecord, new_localized)),
+            }),
+            None => subrecords.push(SubrecordDiff {
+                code: key.0.clone(),
+                index: key.1,
+                old: Some(value(&old.header.code, old_subrecord, old_localized)),
+                new: None,
+            }),
+        }
+    }
+
+    for (key, new_subrecord) in &new_subrecords {
+        if !old_subrecords.iter().any(|(old_key, _)| old_key == key) {
+            subrecords.push(SubrecordDiff {
+                code: key.0.clone(),
+                index: key.1,
+                old: None,
+                new: Some(value(&new.header.code, new_subrecord, new_localized)),
+            });
+        }
+    }
+
+    if flags.is_none() && subrecords.is_empty() {
+        None
+    } else {
+        Some(RecordDiff {
+            record: summary(new),
+            flags,
+            subrecords,
+        })
+    }
+}
+
+/// Key each subrecord by its code and the number of times that code has already appeared in the record
+fn keyed(subrecords: Vec<Subrecord>) -> Vec<((TypeCode, usize), Subrecord)> {
+    let mut occurrences = HashMap::new();
+
+    subrecords
+        .into_iter()
+        .map(|subrecord| {
+            let count = occurrences.entry(subrecord.code.clone()).or_insert(0usize);
+            let key = (subrecord.code.clone(), *count);
+            *count += 1;
+            (key, subrecord)
+        })
+        .collect()
+}
+
+/// Decode strings, counts, keywords, conditions, script attachments and reference placements, falling back to the
+/// raw bytes for anything else
+fn value(record: &TypeCode, subrecord: &Subrecord, localized: bool) -> SubrecordValue {
+    let decoded = match subrecord.code.to_string().as_str() {
+        "EDID" | "MODL" | "MOD2" | "MOD3" | "MOD4" | "MOD5" | "ICON" | "MICO" => zstring_value(subrecord.data),
+        "FULL" | "DESC" if localized && subrecord.data.len() == 4 => {
+            Some(format!("string {:#010X}", u32_at(subrecord.data, 0)))
+        }
+        "FULL" | "DESC" => zstring_value(subrecord.data),
+        "KSIZ" | "COCT" | "SPCT" if subrecord.data.len() == 4 => Some(u32_at(subrecord.data, 0).to_string()),
+        "KWDA" => Some(
+            subrecord
+                .data
+                .chunks_exact(4)
+                .map(|chunk| FormId::from(u32_at(chunk, 0)).to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+        ),
+        "CTDA" => Condition::decode(subrecord.data)
+            .ok()
+            .map(|condition| condition.to_string()),
+        "VMAD" => Vmad::parse(subrecord.data, record)
+            .ok()
+            .map(|vmad| scripts_value(&vmad)),
+        "DATA" if reference::is_placed_reference(record) => Placement::decode(subrecord.data)
+            .ok()
+            .map(|placement| format!("position {:?}, rotation {:?}", placement.position, placement.rotation)),
+        _ => None,
+    };
+
+    decoded
+        .map(SubrecordValue::Decoded)
+        .unwrap_or_else(|| SubrecordValue::Raw(subrecord.data.to_vec()))
+}
+
+/// Each attached script with its properties
+fn scripts_value(vmad: &Vmad) -> String {
+    vmad.all_scripts()
+        .into_iter()
+        .map(|(_, script)| {
+            let properties = script
+                .properties
+                .iter()
+                .map(|property| format!("{} = {:?}", property.name, property.value))
+                .collect::<Vec<_>>();
+
+            format!("{}({})", script.name, properties.join(", "))
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn zstring_value(bytes: &[u8]) -> Option<String> {
+    zstring(bytes).ok().map(|(_, value)| value)
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+}
+
+fn header_changes(old: &FileHeaderRecord, new: &FileHeaderRecord) -> Vec<HeaderChange> {
+    let mut changes = vec![];
+    let mut compare = |field: &'static str, old: String, new: String| {
+        if old != new {
+            changes.push(HeaderChange { field, old, new });
+        }
+    };
+
+    compare(
+        "flags",
+        format!("{:?}", old.header.flags),
+        format!("{:?}", new.header.flags),
+    );
+
+    if let (RecordData::FileHeader(old), RecordData::FileHeader(new)) = (&old.data, &new.data) {
+        compare("version", old.hedr.version.to_string(), new.hedr.version.to_string());
+        compare(
+            "num_records",
+            old.hedr.num_records.to_string(),
+            new.hedr.num_records.to_string(),
+        );
+        compare("next_id", old.hedr.next_id.to_string(), new.hedr.next_id.to_string());
+        compare(
+            "author",
+            old.author.clone().unwrap_or_default(),
+            new.author.clone().unwrap_or_default(),
+        );
+        compare(
+            "description",
+            old.description.clone().unwrap_or_default(),
+            new.description.clone().unwrap_or_default(),
+        );
+        compare("masters", master_names(old), master_names(new));
+        compare("overrides", form_ids(&old.overrides), form_ids(&new.overrides));
+        compare("intv", old.intv.to_string(), new.intv.to_string());
+    }
+
+    changes
+}
+
+fn master_names(data: &FileHeaderData) -> String {
+    data.masters
+        .iter()
+        .map(|master| master.name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn form_ids(ids: &[FormId]) -> String {
+    ids.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+}
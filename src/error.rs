@@ -11,15 +11,27 @@ pub enum Error {
     /// Invalid flags
     #[error("Could not parse flags {0:010X}")]
     InvalidFlags(u32),
+    /// Master list changes that would leave the plugin inconsistent
+    #[error("Invalid master list: {0}")]
+    InvalidMasters(String),
+    /// Query strings that can't be parsed or resolved
+    #[error("Invalid query: {0}")]
+    InvalidQuery(String),
     /// Forward an IO Error from std
     #[error("IOError: {0}")]
     IoError(#[from] std::io::Error),
+    /// Removing a master that records still depend on
+    #[error("Master {0} is still referenced by {1} records")]
+    MasterInUse(String, usize),
     /// Unknown parsing error
     #[error("Unknown error while parsing {0:?}")]
     NomError(nom::error::ErrorKind),
     /// Unconsumed bytes after file fully parsed
     #[error("{0} unconsumed bytes")]
     UnconsumedBytes(usize),
+    /// Records holding subrecords whose FormIDs can't be located, which rewriting FormIDs would leave stale
+    #[error("FormID layout unknown for {0}")]
+    UnknownFormIdLayout(String),
     /// Forward a Utf8Error from std
     #[error("FromUtf8Error: {0}")]
     Utf8Error(#[from] std::string::FromUtf8Error),
@@ -37,3 +49,12 @@ impl<I> ParseError<I> for Error {
         other
     }
 }
+
+impl From<nom::Err<Error>> for Error {
+    fn from(err: nom::Err<Error>) -> Self {
+        match err {
+            nom::Err::Incomplete(_) => Error::NomError(ErrorKind::Eof),
+            nom::Err::Error(e) | nom::Err::Failure(e) => e,
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use std::collections::{BTreeMap, HashSet};
+
+use crate::{
+    load_order::LoadedPlugin,
+    merge::remap_group,
+    parsers::{common::FormId, records::flags::PluginFlags},
+    schema,
+};
+
+/// First object ID available to light plugins
+pub const LIGHT_FIRST_OBJECT_ID: u32 = 0x800;
+/// First object ID available to light plugins with a HEDR version of at least `EXTENDED_RANGE_VERSION`
+pub const LIGHT_EXTENDED_FIRST_OBJECT_ID: u32 = 0x000;
+pub const LIGHT_LAST_OBJECT_ID: u32 = 0xFFF;
+pub const EXTENDED_RANGE_VERSION: f32 = 1.71;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EslIssue {
+    /// More new records than there are object IDs in the light plugin range
+    TooManyRecords { count: usize, limit: usize },
+    /// New records with object IDs outside the light plugin range, which `compact` can renumber
+    OutOfRange(Vec<FormId>),
+    /// Records holding subrecords whose FormIDs can't be located, which stop `compact` from rewriting references
+    UnknownFormIdLayout(Vec<FormId>),
+    /// New cells, which the game doesn't reliably keep edits to in light plugins. A warning only, it doesn't stop
+    /// the plugin being flagged
+    NewCells(Vec<FormId>),
+}
+
+#[derive(Debug)]
+pub struct EslCheck {
+    pub new_records: usize,
+    pub first_object_id: u32,
+    pub issues: Vec<EslIssue>,
+}
+
+impl EslCheck {
+    /// Whether the plugin can be flagged as light as it is
+    pub fn can_flag(&self) -> bool {
+        self.issues.iter().all(|issue| matches!(issue, EslIssue::NewCells(_)))
+    }
+
+    /// Whether compacting FormIDs would make the plugin eligible
+    pub fn can_compact(&self) -> bool {
+        !self.issues.iter().any(|issue| {
+            matches!(
+                issue,
+                EslIssue::TooManyRecords { .. } | EslIssue::UnknownFormIdLayout(_)
+            )
+        })
+    }
+}
+
+fn first_object_id(plugin: &LoadedPlugin) -> u32 {
+    if plugin.plugin.file_header().hedr.version >= EXTENDED_RANGE_VERSION {
+        LIGHT_EXTENDED_FIRST_OBJECT_ID
+    } else {
+        LIGHT_FIRST_OBJECT_ID
+    }
+}
+
+fn in_range(object_id: u32, first_object_id: u32) -> bool {
+    (first_object_id..=LIGHT_LAST_OBJECT_ID).contains(&object_id)
+}
+
+/// Check whether a plugin can be flagged `PluginFlags::LIGHT`
+pub fn check(plugin: &LoadedPlugin) -> EslCheck {
+    let first_object_id = first_object_id(plugin);
+    let limit = (LIGHT_LAST_OBJECT_ID - first_object_id + 1) as usize;
+
+    let records = plugin.plugin.records();
+
+    let mut new_records = records
+        .iter()
+        .map(|record| record.header.id)
+        .filter(|id| plugin.is_new_record(*id))
+        .collect::<Vec<_>>();
+    new_records.sort();
+
+    let mut new_cells = records
+        .iter()
+        .filter(|record| &*record.header.code == b"CELL" && plugin.is_new_record(record.header.id))
+        .map(|record| record.header.id)
+        .collect::<Vec<_>>();
+    new_cells.sort();
+
+    let out_of_range = new_records
+        .iter()
+        .copied()
+        .filter(|id| !in_range(id.object_id(), first_object_id))
+        .collect::<Vec<_>>();
+
+    let mut issues = vec![];
+
+    if new_records.len() > limit {
+        issues.push(EslIssue::TooManyRecords {
+            count: new_records.len(),
+            limit,
+        });
+    }
+
+    if !out_of_range.is_empty() {
+        let mut unmapped = records
+            .iter()
+            .filter(|record| !schema::unmapped_subrecords(record).is_empty())
+            .map(|record| record.header.id)
+            .collect::<Vec<_>>();
+        unmapped.sort();
+
+        issues.push(EslIssue::OutOfRange(out_of_range));
+
+        if !unmapped.is_empty() {
+            issues.push(EslIssue::UnknownFormIdLayout(unmapped));
+        }
+    }
+
+    if !new_cells.is_empty() {
+        issues.push(EslIssue::NewCells(new_cells));
+    }
+
+    EslCheck {
+        new_records: new_records.len(),
+        first_object_id,
+        issues,
+    }
+}
+
+/// Renumber new records into the light plugin range, rewrite every reference to them within the plugin and
+/// flag the plugin as light. Returns the FormIDs that changed.
+///
+/// Plugins that depend on this one still refer to the old FormIDs and need their references remapped too.
+pub fn compact(plugin: &mut LoadedPlugin) -> Result<BTreeMap<FormId, FormId>, crate::Error> {
+    let check = check(plugin);
+
+    if let Some(EslIssue::TooManyRecords { count, limit }) = check
+        .issues
+        .iter()
+        .find(|issue| matches!(issue, EslIssue::TooManyRecords { .. }))
+    {
+        return Err(crate::Error::CorruptOrInvalidFile(format!(
+            "{} has {} new records, light plugins are limited to {}",
+            plugin.name, count, limit
+        )));
+    }
+
+    let index = plugin.plugin.masters().len() as u8;
+    let used = plugin
+        .plugin
+        .records()
+        .into_iter()
+        .map(|record| record.header.id)
+        .filter(|id| plugin.is_new_record(*id))
+        .map(|id| id.object_id())
+        .collect::<HashSet<_>>();
+
+    let mut free = (check.first_object_id..=LIGHT_LAST_OBJECT_ID).filter(|object_id| !used.contains(object_id));
+    let mut renumbered = BTreeMap::new();
+
+    for issue in &check.issues {
+        if let EslIssue::OutOfRange(ids) = issue {
+            for id in ids {
+                let object_id = free.next().ok_or(crate::Error::Unexpected)?;
+                renumbered.insert(*id, FormId::from(object_id).with_index(index));
+            }
+        }
+    }
+
+    if !renumbered.is_empty() {
+        schema::check_mapped(plugin.plugin.records())?;
+
+        let mut remap = |id: FormId| renumbered.get(&id).copied().unwrap_or(id);
+
+        for group in plugin.plugin.groups.values_mut() {
+            remap_group(group, &mut remap)?;
+        }
+    }
+
+    let next_id = plugin
+        .plugin
+        .records()
+        .into_iter()
+        .map(|record| record.header.id)
+        .filter(|id| plugin.is_new_record(*id))
+        .map(|id| id.object_id() + 1)
+        .max()
+        .unwrap_or(check.first_object_id);
+
+    plugin.plugin.file_header_mut().hedr.next_id = FormId::from(next_id);
+    plugin.plugin.tes4.header.flags.insert(PluginFlags::LIGHT);
+    plugin.plugin.update_overrides();
+
+    Ok(renumbered)
+}
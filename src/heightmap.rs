@@ -0,0 +1,158 @@
+use std::io::Write;
+
+use crate::{
+    index::PluginIndex,
+    parsers::{
+        common::GridCoord,
+        plugin::Plugin,
+        records::land::{Landscape, GRID_SIZE},
+    },
+    spatial::WorldspaceIndex,
+};
+
+use flate2::{write::ZlibEncoder, Compression, Crc};
+
+/// Vertices each cell adds to a side of the heightmap, after sharing an edge with its neighbour
+const CELL_VERTICES: usize = GRID_SIZE - 1;
+
+/// Terrain heights of a worldspace, one sample per landscape vertex. Rows run from north to south, so the image
+/// exports come out the right way up
+#[derive(Debug, Clone)]
+pub struct Heightmap {
+    /// South-west cell covered
+    pub origin: GridCoord,
+    pub width: usize,
+    pub height: usize,
+    /// Heights in game units. Cells without landscape are filled with `min_height`
+    pub heights: Vec<f32>,
+    pub min_height: f32,
+    pub max_height: f32,
+}
+
+impl Heightmap {
+    /// Stitch together the LAND records of a worldspace's exterior cells. Returns `None` when none of its cells
+    /// have heights
+    pub fn build(plugin: &Plugin, world: &WorldspaceIndex) -> Result<Option<Self>, crate::Error> {
+        let index = PluginIndex::build(plugin);
+        let mut cells = vec![];
+
+        for cell in world.cells() {
+            let land = match cell.land.and_then(|id| index.record(plugin, id)) {
+                Some(land) => Landscape::decode(land)?,
+                None => continue,
+            };
+
+            if let Some(heights) = land.heights {
+                cells.push((cell.grid, heights));
+            }
+        }
+
+        let (first, rest) = match cells.split_first() {
+            Some(split) => split,
+            None => return Ok(None),
+        };
+
+        let (min, max) = rest.iter().fold((first.0, first.0), |(min, max), (grid, _)| {
+            (
+                GridCoord::new(min.x.min(grid.x), min.y.min(grid.y)),
+                GridCoord::new(max.x.max(grid.x), max.y.max(grid.y)),
+            )
+        });
+
+        let min_height = cells
+            .iter()
+            .flat_map(|(_, heights)| heights)
+            .copied()
+            .fold(f32::INFINITY, f32::min);
+        let max_height = cells
+            .iter()
+            .flat_map(|(_, heights)| heights)
+            .copied()
+            .fold(f32::NEG_INFINITY, f32::max);
+
+        let width = (max.x - min.x + 1) as usize * CELL_VERTICES + 1;
+        let height = (max.y - min.y + 1) as usize * CELL_VERTICES + 1;
+        let mut samples = vec![min_height; width * height];
+
+        for (grid, heights) in &cells {
+            let left = (grid.x - min.x) as usize * CELL_VERTICES;
+            let top = (max.y - grid.y) as usize * CELL_VERTICES;
+
+            for (y, row) in heights.chunks_exact(GRID_SIZE).enumerate() {
+                let start = (top + CELL_VERTICES - y) * width + left;
+                samples[start..start + GRID_SIZE].copy_from_slice(row);
+            }
+        }
+
+        Ok(Some(Heightmap {
+            origin: min,
+            width,
+            height,
+            heights: samples,
+            min_height,
+            max_height,
+        }))
+    }
+
+    /// Heights scaled from `min_height` to `max_height` onto the full range of a u16
+    pub fn normalized(&self) -> Vec<u16> {
+        let range = self.max_height - self.min_height;
+
+        self.heights
+            .iter()
+            .map(|height| {
+                if range > 0.0 {
+                    ((height - self.min_height) / range * u16::MAX as f32).round() as u16
+                } else {
+                    0
+                }
+            })
+            .collect()
+    }
+
+    /// Headerless little-endian 16-bit samples
+    pub fn to_raw16(&self) -> Vec<u8> {
+        self.normalized()
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect()
+    }
+
+    /// A 16-bit grayscale PNG
+    pub fn to_png(&self) -> Result<Vec<u8>, crate::Error> {
+        let mut scanlines = Vec::with_capacity(self.height * (self.width * 2 + 1));
+
+        for row in self.normalized().chunks_exact(self.width) {
+            // No filtering
+            scanlines.push(0);
+            scanlines.extend(row.iter().flat_map(|sample| sample.to_be_bytes()));
+        }
+
+        let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+        encoder.write_all(&scanlines)?;
+
+        let mut header = vec![];
+        header.extend_from_slice(&(self.width as u32).to_be_bytes());
+        header.extend_from_slice(&(self.height as u32).to_be_bytes());
+        // Bit depth, grayscale, deflate, adaptive filtering, no interlacing
+        header.extend_from_slice(&[16, 0, 0, 0, 0]);
+
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png_chunk(&mut png, b"IHDR", &header);
+        png_chunk(&mut png, b"IDAT", &encoder.finish()?);
+        png_chunk(&mut png, b"IEND", &[]);
+
+        Ok(png)
+    }
+}
+
+fn png_chunk(out: &mut Vec<u8>, code: &[u8; 4], data: &[u8]) {
+    let mut crc = Crc::new();
+    crc.update(code);
+    crc.update(data);
+
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(code);
+    out.extend_from_slice(data);
+    out.extend_from_slice(&crc.sum().to_be_bytes());
+}
@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+
+use crate::parsers::{
+    common::{FormId, TypeCode},
+    group::{Group, GroupChild, GroupData, GroupType, Label},
+    plugin::Plugin,
+    records::Record,
+};
+
+/// Where a record sits in a plugin: its top group, followed by each nested group enclosing it
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordLocation {
+    pub top_group: TypeCode,
+    pub groups: Vec<(GroupType, Label)>,
+}
+
+/// Lookups by FormID and editor ID across every group of a plugin, including the nested CELL, WRLD and DIAL
+/// children. Editor IDs are matched case-insensitively, as the engine does.
+///
+/// The index holds no references into the plugin, so it must be rebuilt after records are added, removed or
+/// renumbered.
+#[derive(Debug, Default)]
+pub struct PluginIndex {
+    locations: HashMap<FormId, RecordLocation>,
+    editor_ids: HashMap<String, FormId>,
+}
+
+impl PluginIndex {
+    pub fn build(plugin: &Plugin) -> Self {
+        let mut index = Self::default();
+
+        for (code, group) in &plugin.groups {
+            index.add_group(code, group, &mut vec![]);
+        }
+
+        index
+    }
+
+    fn add_group(&mut self, top_group: &TypeCode, group: &Group, path: &mut Vec<(GroupType, Label)>) {
+        match &group.data {
+            GroupData::Records(records) => {
+                for record in records.values() {
+                    self.add_record(top_group, record, path);
+                }
+            }
+            GroupData::Children(children) => {
+                for child in children {
+                    match child {
+                        GroupChild::Record(record) => self.add_record(top_group, record, path),
+                        GroupChild::Group(child) => {
+                            path.push((child.group_type, child.label.clone()));
+                            self.add_group(top_group, child, path);
+                            path.pop();
+                        }
+                    }
+                }
+            }
+            GroupData::Unimplemented(_) => (),
+        }
+    }
+
+    fn add_record(&mut self, top_group: &TypeCode, record: &Record, path: &[(GroupType, Label)]) {
+        self.locations.insert(
+            record.header.id,
+            RecordLocation {
+                top_group: top_group.clone(),
+                groups: path.to_vec(),
+            },
+        );
+
+        if let Some(editor_id) = &record.header.editor_id {
+            self.editor_ids.insert(editor_id.to_lowercase(), record.header.id);
+        }
+    }
+
+    pub fn location(&self, id: FormId) -> Option<&RecordLocation> {
+        self.locations.get(&id)
+    }
+
+    pub fn form_id(&self, editor_id: &str) -> Option<FormId> {
+        self.editor_ids.get(&editor_id.to_lowercase()).copied()
+    }
+
+    pub fn contains(&self, id: FormId) -> bool {
+        self.locations.contains_key(&id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.locations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.locations.is_empty()
+    }
+
+    /// Find a record by walking straight down to the group holding it
+    pub fn record<'a>(&self, plugin: &'a Plugin, id: FormId) -> Option<&'a Record> {
+        let location = self.locations.get(&id)?;
+        let mut group = plugin.groups.get(&location.top_group)?;
+
+        for (group_type, label) in &location.groups {
+            group = match &group.data {
+                GroupData::Children(children) => children.iter().find_map(|child| match child {
+                    GroupChild::Group(child) if child.group_type == *group_type && child.label == *label => Some(child),
+                    _ => None,
+                })?,
+                _ => return None,
+            };
+        }
+
+        match &group.data {
+            GroupData::Records(records) => records.get(&id),
+            GroupData::Children(children) => children.iter().find_map(|child| match child {
+                GroupChild::Record(record) if record.header.id == id => Some(record),
+                _ => None,
+            }),
+            GroupData::Unimplemented(_) => None,
+        }
+    }
+
+    pub fn record_by_editor_id<'a>(&self, plugin: &'a Plugin, editor_id: &str) -> Option<&'a Record> {
+        self.record(plugin, self.form_id(editor_id)?)
+    }
+}
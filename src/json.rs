@@ -0,0 +1,27 @@
+use std::fmt::Write;
+
+/// Quote and escape a string for inclusion in JSON output
+pub(crate) fn string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+
+    out.push('"');
+    out
+}
+
+/// JSON string, or `null` when absent
+pub(crate) fn optional_string(value: Option<&str>) -> String {
+    value.map(string).unwrap_or_else(|| String::from("null"))
+}
@@ -0,0 +1,146 @@
+//! Combining the edits several plugins make to the same leveled list into one list, as a bashed patch does
+
+use crate::{
+    load_order::{GlobalFormId, LoadOrder},
+    parsers::records::Record,
+};
+
+pub use crate::parsers::records::leveled::{flags, is_leveled_list, Entry, ExtraData, LeveledList, CODES};
+
+/// Apply the changes `version` makes to `parent` onto `merged`. Changing an entry's level or count removes the old
+/// entry and adds the new one
+fn apply_edits(merged: &mut LeveledList, parent: &LeveledList, version: &LeveledList) {
+    if version.chance_none != parent.chance_none {
+        merged.chance_none = version.chance_none;
+    }
+    if version.flags != parent.flags {
+        merged.flags = version.flags;
+    }
+    if version.global != parent.global {
+        merged.global = version.global;
+    }
+
+    apply_list_edits(&mut merged.entries, &parent.entries, &version.entries);
+}
+
+/// Remove from `merged` the items `version` removes from `parent`, and add those it adds. Items are compared whole,
+/// and an item is only added as many times as `version` holds it
+pub(crate) fn apply_list_edits<T>(merged: &mut Vec<T>, parent: &[T], version: &[T])
+where
+    T: Clone + PartialEq,
+{
+    let mut removed = parent.to_vec();
+    let mut added = vec![];
+
+    for item in version {
+        match removed.iter().position(|other| other == item) {
+            Some(position) => {
+                removed.remove(position);
+            }
+            None => added.push(item.clone()),
+        }
+    }
+
+    for item in removed {
+        if let Some(position) = merged.iter().position(|other| *other == item) {
+            merged.remove(position);
+        }
+    }
+
+    for item in added {
+        let wanted = version.iter().filter(|other| **other == item).count();
+        let present = merged.iter().filter(|other| **other == item).count();
+
+        if present < wanted {
+            merged.push(item);
+        }
+    }
+}
+
+/// Combine edits to `base`, given as each edited version along with the version it was made from, in load order.
+/// Edits to chance none, flags and the global apply in turn, so the last plugin to change one wins. Entries any
+/// plugin adds are added and entries any plugin removes are removed. The result is sorted by level, as the
+/// Creation Kit keeps lists
+pub fn merge_lists(base: &LeveledList, edits: &[(&LeveledList, &LeveledList)]) -> LeveledList {
+    let mut merged = base.clone();
+
+    for (parent, version) in edits {
+        apply_edits(&mut merged, parent, version);
+    }
+
+    if let Some((_, last)) = edits.last() {
+        merged.editor_id = last.editor_id.clone();
+    }
+
+    merged.entries.sort_by_key(|entry| entry.level);
+
+    merged
+}
+
+/// Whether two lists give the same things, whatever the order of their entries
+fn is_equivalent(a: &LeveledList, b: &LeveledList) -> bool {
+    let mut remaining = b.entries.clone();
+
+    let same_entries = a.entries.len() == b.entries.len()
+        && a.entries
+            .iter()
+            .all(|entry| match remaining.iter().position(|other| other == entry) {
+                Some(position) => {
+                    remaining.remove(position);
+                    true
+                }
+                None => false,
+            });
+
+    same_entries && a.chance_none == b.chance_none && a.flags == b.flags && a.global == b.global
+}
+
+/// Merge every plugin's edits to a leveled list. Each overriding plugin's edits are taken relative to the version
+/// in the last of its own masters to have one, so a plugin only undoes what it meant to.
+///
+/// Returns a copy of the winning record holding the merged list, with FormIDs in load order terms, ready for a
+/// patch plugin that has the whole load order as its masters. `None` if the form isn't a leveled list or the
+/// winning version already holds every edit
+pub fn merge(load_order: &LoadOrder, id: &GlobalFormId) -> Result<Option<Record>, crate::Error> {
+    let mut versions = vec![];
+
+    for (plugin, record) in load_order.record_versions(id) {
+        if !is_leveled_list(&record.header.code) {
+            return Ok(None);
+        }
+
+        let record = load_order.absolute_record(plugin, record)?;
+        let list = LeveledList::decode(&record)?;
+        versions.push((plugin, record, list));
+    }
+
+    if versions.len() < 3 {
+        return Ok(None);
+    }
+
+    let base = &versions[0].2;
+    let mut edits = vec![];
+
+    for (position, (plugin, _, list)) in versions.iter().enumerate().skip(1) {
+        let masters = plugin.plugin.masters();
+        let parent = versions[..position]
+            .iter()
+            .rev()
+            .find(|(earlier, _, _)| masters.iter().any(|master| master.eq_ignore_ascii_case(&earlier.name)))
+            .map_or(base, |(_, _, parent)| parent);
+
+        edits.push((parent, list));
+    }
+
+    let merged = merge_lists(base, &edits);
+    let (_, winning, winning_list) = versions.last().ok_or(crate::Error::Unexpected)?;
+
+    if is_equivalent(&merged, winning_list) {
+        return Ok(None);
+    }
+
+    let mut record = winning.clone();
+    merged.write(&mut record)?;
+
+    Ok(Some(record))
+}
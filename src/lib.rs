@@ -1,5 +1,30 @@
+pub mod assets;
+pub mod bsa;
+pub mod builder;
+pub mod clean;
+pub mod dialogue;
+pub mod diff;
 mod error;
+pub mod esl;
+pub mod heightmap;
+pub mod index;
+mod json;
+pub mod leveled;
+pub mod load_order;
+pub mod masters;
+pub mod merge;
+pub mod navmesh;
+pub mod nif;
 mod parsers;
+pub mod patch;
+pub mod pex;
+pub mod query;
+pub mod references;
+pub mod schema;
+pub mod scripts;
+pub mod seq;
+pub mod spatial;
+pub mod writer;
 
 use std::{
     io::{BufReader, Read},
@@ -10,7 +35,14 @@ use crate::parsers::plugin::plugin;
 
 use nom::combinator::all_consuming;
 
-pub use crate::{error::Error, parsers::plugin::Plugin};
+pub use crate::{
+    error::Error,
+    parsers::{
+        common::{interior_block, FormId, GridCoord, Subrecord, TypeCode, CELLS_PER_BLOCK, CELLS_PER_SUB_BLOCK},
+        plugin::Plugin,
+        records::{condition, land, reference, Record},
+    },
+};
 
 type IResult<I, T> = nom::IResult<I, T, crate::Error>;
 
@@ -28,7 +60,7 @@ where
         nom::Err::Failure(e) => e,
     })?;
 
-    let bytes_remaining = remaining.iter().cloned().collect::<Vec<u8>>().len();
+    let bytes_remaining = remaining.len();
 
     if bytes_remaining > 0 {
         Err(Error::UnconsumedBytes(bytes_remaining))
@@ -39,14 +71,25 @@ where
 
 #[cfg(test)]
 mod tests {
-    use super::{read_plugin, Plugin};
+    use super::parsers::{
+        group::{Group, GroupChild, GroupData, GroupType, Label},
+        records::{
+            file_header::{FileHeaderData, Hedr, MasterFile},
+            flags::{PluginFlags, RecordFlags},
+            FileHeaderRecord, Record, RecordData, RecordHeader,
+        },
+    };
+    use super::{
+        assets, builder, clean, condition, dialogue, diff, esl, heightmap, index, land, leveled, load_order, masters,
+        navmesh, nif, parsers, patch, pex, query, read_plugin, reference, references, schema, scripts, seq, spatial,
+        writer, FormId, GridCoord, Plugin, TypeCode,
+    };
 
     use ctor::ctor;
-    use env_logger;
     use lazy_static::lazy_static;
     use log::info;
 
-    use std::fs::File;
+    use std::{collections::HashMap, fs::File};
 
     lazy_static! {
         static ref SKYRIM_PLUGIN: Plugin = {
@@ -75,9 +118,1991 @@ mod tests {
         env_logger::init();
     }
 
+    /// A plugin built in memory, holding the given records in top groups
+    fn test_plugin(masters: &[&str], records: Vec<Record>) -> Plugin {
+        let data = FileHeaderData {
+            hedr: Hedr {
+                version: 1.7,
+                num_records: 0,
+                next_id: FormId::from(0x800),
+            },
+            masters: masters
+                .iter()
+                .map(|name| MasterFile {
+                    name: String::from(*name),
+                    tag: 0,
+                })
+                .collect(),
+            ..FileHeaderData::default()
+        };
+
+        let mut plugin = Plugin {
+            tes4: FileHeaderRecord {
+                header: RecordHeader {
+                    code: TypeCode::from(*b"TES4"),
+                    size: 0,
+                    flags: PluginFlags::empty(),
+                    unknown_flags: 0,
+                    id: FormId::from(0),
+                    timestamp: 0,
+                    vc_info: 0,
+                    version: 44,
+                    unknown: 0,
+                    editor_id: None,
+                },
+                data: RecordData::FileHeader(data),
+                compressed: None,
+            },
+            groups: HashMap::new(),
+        };
+
+        for record in records {
+            let code = record.header.code.clone();
+            let group = plugin.groups.entry(code.clone()).or_insert_with(|| Group {
+                size: 0,
+                label: Label::RecordType(code),
+                group_type: GroupType::Top,
+                timestamp: 0,
+                vc_info: 0,
+                unknown: 0,
+                data: GroupData::Records(HashMap::new()),
+            });
+
+            if let GroupData::Records(records) = &mut group.data {
+                records.insert(record.header.id, record);
+            }
+        }
+
+        plugin.update_num_records();
+        plugin
+    }
+
+    /// A record holding the given subrecords in order, its header's editor ID taken from any EDID
+    fn test_record(code: &[u8; 4], id: FormId, subrecords: &[(&[u8; 4], &[u8])]) -> Record {
+        let data = subrecords
+            .iter()
+            .flat_map(|(code, bytes)| [&code[..], &(bytes.len() as u16).to_le_bytes(), bytes].concat())
+            .collect::<Vec<_>>();
+        let editor_id = subrecords
+            .iter()
+            .find(|(code, _)| *code == b"EDID")
+            .map(|(_, bytes)| parsers::common::zstring(bytes).unwrap().1);
+
+        Record {
+            header: RecordHeader {
+                code: TypeCode::from(*code),
+                size: data.len() as u32,
+                flags: RecordFlags::empty(),
+                unknown_flags: 0,
+                id,
+                timestamp: 0,
+                vc_info: 0,
+                version: 44,
+                unknown: 0,
+                editor_id,
+            },
+            data: RecordData::Unknown(data),
+            compressed: None,
+        }
+    }
+
+    /// Null-terminated string data, as in EDID
+    fn zstring_data(value: &str) -> Vec<u8> {
+        [value.as_bytes(), &[0]].concat()
+    }
+
+    /// A group holding records and child groups in file order
+    fn test_group(group_type: GroupType, label: Label, children: Vec<GroupChild>) -> Group {
+        Group {
+            size: 0,
+            label,
+            group_type,
+            timestamp: 0,
+            vc_info: 0,
+            unknown: 0,
+            data: GroupData::Children(children),
+        }
+    }
+
+    /// Add a top group of records with child groups, such as CELL or WRLD, to the plugin
+    fn add_top_group(plugin: &mut Plugin, code: &[u8; 4], children: Vec<GroupChild>) {
+        let code = TypeCode::from(*code);
+        let group = test_group(GroupType::Top, Label::RecordType(code.clone()), children);
+
+        plugin.groups.insert(code, group);
+        plugin.update_num_records();
+    }
+
     #[test]
     fn test_header_magic() {
         assert_eq!(&SKYRIM_PLUGIN.tes4.header.code.to_string(), "TES4");
         assert_eq!(&DAWNGUARD_PLUGIN.tes4.header.code.to_string(), "TES4");
     }
+
+    #[test]
+    fn test_zstring_windows_1252() {
+        let bytes = b"Caf\xe9 \x93Dw\xe4rven\x94\x00";
+        let (_, value) = parsers::common::zstring(bytes).unwrap();
+
+        assert_eq!(value, "Café “Dwärven”");
+        assert_eq!(
+            parsers::common::encode_string(&value),
+            bytes[..bytes.len() - 1].to_vec()
+        );
+        assert_eq!(parsers::common::encode_string("日本"), b"??".to_vec());
+    }
+
+    #[test]
+    fn test_diff_identical() {
+        assert!(diff::diff(&DAWNGUARD_PLUGIN, &DAWNGUARD_PLUGIN).is_empty());
+    }
+
+    #[test]
+    fn test_diff_changes() {
+        let old = test_plugin(
+            &["Skyrim.esm"],
+            vec![
+                test_record(
+                    b"MISC",
+                    FormId::from(0x0100_0800),
+                    &[
+                        (b"EDID", &zstring_data("Kept")),
+                        (b"FULL", &zstring_data("Gem")),
+                        (b"DATA", &10u32.to_le_bytes()),
+                    ],
+                ),
+                test_record(
+                    b"MISC",
+                    FormId::from(0x0100_0801),
+                    &[(b"EDID", &zstring_data("Removed"))],
+                ),
+            ],
+        );
+        let new = test_plugin(
+            &["Skyrim.esm"],
+            vec![
+                test_record(
+                    b"MISC",
+                    FormId::from(0x0100_0800),
+                    &[
+                        (b"EDID", &zstring_data("Kept")),
+                        (b"FULL", &zstring_data("Flawless Gem")),
+                        (b"KSIZ", &1u32.to_le_bytes()),
+                        (b"KWDA", &0x0009_14E9u32.to_le_bytes()),
+                    ],
+                ),
+                test_record(b"MISC", FormId::from(0x0100_0802), &[(b"EDID", &zstring_data("Added"))]),
+            ],
+        );
+
+        let changes = diff::diff(&old, &new);
+
+        assert!(changes.header.is_empty());
+        assert_eq!(changes.added.len(), 1);
+        assert_eq!(changes.added[0].editor_id.as_deref(), Some("Added"));
+        assert_eq!(changes.removed.len(), 1);
+        assert_eq!(changes.removed[0].id, FormId::from(0x0100_0801));
+        assert_eq!(changes.modified.len(), 1);
+
+        let subrecords = changes.modified[0]
+            .subrecords
+            .iter()
+            .map(|subrecord| {
+                (
+                    subrecord.code.to_string(),
+                    subrecord.old.as_ref().map(ToString::to_string),
+                    subrecord.new.as_ref().map(ToString::to_string),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        assert!(subrecords.contains(&(
+            String::from("FULL"),
+            Some(String::from("Gem")),
+            Some(String::from("Flawless Gem"))
+        )));
+        assert!(subrecords.contains(&(String::from("DATA"), Some(String::from("0A 00 00 00")), None)));
+        assert!(subrecords.contains(&(String::from("KSIZ"), None, Some(String::from("1")))));
+        assert!(subrecords.iter().all(|(code, _, _)| code != "EDID"));
+
+        let mut changed = test_plugin(&["Skyrim.esm", "Update.esm"], vec![]);
+        if let RecordData::FileHeader(header) = &mut changed.tes4.data {
+            header.overrides = vec![FormId::from(0x0000_0D62)];
+        }
+        let header = diff::diff(&test_plugin(&["Skyrim.esm"], vec![]), &changed).header;
+
+        assert_eq!(
+            header.iter().map(|change| change.field).collect::<Vec<_>>(),
+            vec!["masters", "overrides"]
+        );
+    }
+
+    #[test]
+    fn test_clean() {
+        let gem = |id| {
+            test_record(
+                b"MISC",
+                FormId::from(id),
+                &[(b"EDID", &zstring_data("Gem")), (b"DATA", &10u32.to_le_bytes())],
+            )
+        };
+        let ore = |id, value: u32| {
+            test_record(
+                b"MISC",
+                FormId::from(id),
+                &[(b"EDID", &zstring_data("Ore")), (b"DATA", &value.to_le_bytes())],
+            )
+        };
+        let master = test_plugin(&[], vec![gem(0x800), ore(0x801, 5)]);
+
+        let mut reference = test_record(
+            b"REFR",
+            FormId::from(0x900),
+            &[(b"NAME", &0x800u32.to_le_bytes()), (b"DATA", &[0; 24])],
+        );
+        reference.header.flags = RecordFlags::DELETED;
+
+        let mut plugin = test_plugin(&["Master.esm"], vec![gem(0x800), ore(0x801, 6), gem(0x0100_0800)]);
+        add_top_group(&mut plugin, b"CELL", vec![GroupChild::Record(reference)]);
+
+        let mut load_order = load_order::LoadOrder::new();
+        load_order.push("Master.esm", master);
+        let mut plugin = load_order::LoadedPlugin {
+            name: String::from("Plugin.esp"),
+            plugin,
+        };
+
+        let report = clean::check(&plugin, &load_order);
+
+        assert_eq!(report.identical_to_master, vec![FormId::from(0x800)]);
+        assert_eq!(report.deleted_references, vec![FormId::from(0x900)]);
+
+        clean::clean(&mut plugin.plugin, &report);
+
+        assert!(plugin.plugin.record(FormId::from(0x800)).is_none());
+        assert!(plugin.plugin.record(FormId::from(0x801)).is_some());
+        assert_eq!(plugin.plugin.file_header().hedr.num_records, 5);
+
+        let reference = plugin.plugin.record(FormId::from(0x900)).unwrap();
+
+        assert!(!reference.header.flags.contains(RecordFlags::DELETED));
+        assert!(reference.header.flags.contains(RecordFlags::INITIALLY_DISABLED));
+
+        let subrecords = reference.subrecords();
+        let codes = subrecords
+            .iter()
+            .map(|subrecord| subrecord.code.to_string())
+            .collect::<Vec<_>>();
+
+        assert_eq!(codes, vec!["NAME", "XESP", "DATA"]);
+        assert_eq!(subrecords[1].data, &[0x14, 0, 0, 0, 1, 0, 0, 0]);
+        assert_eq!(subrecords[2].data[8..12], clean::DISABLED_REFERENCE_Z.to_le_bytes());
+        assert!(clean::check(&plugin, &load_order).is_clean());
+    }
+
+    #[test]
+    fn test_clean_reordered_masters() {
+        let keyworded = |id, keyword: u32| {
+            test_record(
+                b"MISC",
+                FormId::from(id),
+                &[(b"KSIZ", &1u32.to_le_bytes()), (b"KWDA", &keyword.to_le_bytes())],
+            )
+        };
+
+        let mut load_order = load_order::LoadOrder::new();
+        load_order.push(
+            "A.esm",
+            test_plugin(&[], vec![test_record(b"KYWD", FormId::from(0x800), &[])]),
+        );
+        load_order.push(
+            "B.esm",
+            test_plugin(&[], vec![test_record(b"KYWD", FormId::from(0x800), &[])]),
+        );
+        load_order.push(
+            "C.esm",
+            test_plugin(
+                &["A.esm", "B.esm"],
+                vec![keyworded(0x0200_0800, 0x0100_0800), keyworded(0x0200_0801, 0x0000_0800)],
+            ),
+        );
+
+        // With A.esm and B.esm swapped, the first override keeps B.esm's keyword under a different FormID, while the
+        // second has C.esm's bytes but now points at B.esm's keyword rather than A.esm's
+        let plugin = load_order::LoadedPlugin {
+            name: String::from("D.esp"),
+            plugin: test_plugin(
+                &["B.esm", "A.esm", "C.esm"],
+                vec![keyworded(0x0200_0800, 0x0000_0800), keyworded(0x0200_0801, 0x0000_0800)],
+            ),
+        };
+
+        assert_eq!(
+            clean::check(&plugin, &load_order).identical_to_master,
+            vec![FormId::from(0x0200_0800)]
+        );
+    }
+
+    #[test]
+    fn test_update_overrides() {
+        let cell_id = FormId::from(0x0000_003C);
+        let children = |group_type, id| {
+            test_group(
+                group_type,
+                Label::ParentCell(cell_id),
+                vec![GroupChild::Record(test_record(b"REFR", FormId::from(id), &[]))],
+            )
+        };
+
+        let mut plugin = test_plugin(&["Skyrim.esm"], vec![]);
+        add_top_group(
+            &mut plugin,
+            b"CELL",
+            vec![
+                GroupChild::Record(test_record(b"CELL", cell_id, &[])),
+                GroupChild::Group(test_group(
+                    GroupType::CellChildren,
+                    Label::ParentCell(cell_id),
+                    vec![
+                        GroupChild::Group(children(GroupType::CellPersistenChildren, 0x0000_0100)),
+                        GroupChild::Group(children(GroupType::CellTemporaryChildren, 0x0000_0200)),
+                    ],
+                )),
+            ],
+        );
+        plugin.update_overrides();
+
+        assert_eq!(plugin.file_header().overrides, vec![FormId::from(0x0000_0200)]);
+    }
+
+    #[test]
+    fn test_write_round_trip() {
+        let bytes = writer::plugin_bytes(&DAWNGUARD_PLUGIN).unwrap();
+        let plugin = read_plugin(bytes.as_slice()).unwrap();
+
+        assert!(diff::diff(&DAWNGUARD_PLUGIN, &plugin).modified.is_empty());
+        assert_eq!(DAWNGUARD_PLUGIN.records().len(), plugin.records().len());
+    }
+
+    #[test]
+    fn test_schema_remap() {
+        let keyword = 0x0009_14E9u32;
+        let remap = |id: FormId| id.with_index(2);
+
+        let mut record = test_record(
+            b"MISC",
+            FormId::from(0x0100_0800),
+            &[
+                (b"EDID", &zstring_data("Gem")),
+                (b"KSIZ", &1u32.to_le_bytes()),
+                (b"KWDA", &keyword.to_le_bytes()),
+            ],
+        );
+
+        schema::remap_form_ids(&mut record, remap).unwrap();
+
+        assert_eq!(record.header.id, FormId::from(0x0200_0800));
+        assert_eq!(schema::referenced_form_ids(&record), vec![FormId::from(0x0209_14E9)]);
+
+        let mut record = test_record(
+            b"MISC",
+            FormId::from(0x0100_0800),
+            &[
+                (b"KSIZ", &1u32.to_le_bytes()),
+                (b"KWDA", &keyword.to_le_bytes()),
+                (b"XQQQ", &[0; 4]),
+            ],
+        );
+
+        assert_eq!(schema::unmapped_subrecords(&record), vec![TypeCode::from(*b"XQQQ")]);
+        assert!(schema::remap_form_ids(&mut record, remap).is_err());
+        assert_eq!(record.header.id, FormId::from(0x0100_0800));
+        assert_eq!(schema::referenced_form_ids(&record), vec![FormId::from(keyword)]);
+
+        // An INFO's response sound
+        let trdt = [&[0; 16][..], &0x0000_0D62u32.to_le_bytes(), &[0; 4]].concat();
+        let info = test_record(b"INFO", FormId::from(0x0100_0801), &[(b"TRDT", &trdt)]);
+
+        assert_eq!(schema::referenced_form_ids(&info), vec![FormId::from(0x0000_0D62)]);
+    }
+
+    #[test]
+    fn test_write_unknown_flags() {
+        let id = FormId::from(0x0000_0800);
+        let mut record = test_record(b"MISC", id, &[(b"EDID", &zstring_data("Gem"))]);
+        record.header.flags = RecordFlags::INITIALLY_DISABLED;
+        record.header.unknown_flags = 0x0000_0002;
+
+        let mut plugin = test_plugin(&[], vec![record]);
+        plugin.tes4.header.unknown_flags = 0x0000_0100;
+
+        let bytes = writer::plugin_bytes(&plugin).unwrap();
+        let plugin = read_plugin(bytes.as_slice()).unwrap();
+        let record = plugin.record(id).unwrap();
+
+        assert_eq!(plugin.tes4.header.unknown_flags, 0x0000_0100);
+        assert_eq!(record.header.flags, RecordFlags::INITIALLY_DISABLED);
+        assert_eq!(record.header.unknown_flags, 0x0000_0002);
+        assert_eq!(writer::plugin_bytes(&plugin).unwrap(), bytes);
+    }
+
+    #[test]
+    fn test_esl_compact() {
+        use esl::EslIssue;
+
+        let (inside, outside) = (FormId::from(0x0100_0800), FormId::from(0x0100_1000));
+        let records = || {
+            vec![
+                test_record(b"KYWD", outside, &[(b"EDID", &zstring_data("Gems"))]),
+                test_record(
+                    b"MISC",
+                    inside,
+                    &[(b"KSIZ", &1u32.to_le_bytes()), (b"KWDA", &outside.to_le_bytes())],
+                ),
+            ]
+        };
+
+        let mut plugin = load_order::LoadedPlugin {
+            name: String::from("Gems.esp"),
+            plugin: test_plugin(&["Skyrim.esm"], records()),
+        };
+
+        let check = esl::check(&plugin);
+        assert_eq!(check.first_object_id, esl::LIGHT_FIRST_OBJECT_ID);
+        assert_eq!(check.issues, vec![EslIssue::OutOfRange(vec![outside])]);
+        assert!(!check.can_flag() && check.can_compact());
+
+        let renumbered = esl::compact(&mut plugin).unwrap();
+        let compacted = FormId::from(0x0100_0801);
+
+        assert_eq!(renumbered.get(&outside), Some(&compacted));
+        assert!(plugin.plugin.record(compacted).is_some());
+        assert_eq!(
+            schema::referenced_form_ids(plugin.plugin.record(inside).unwrap()),
+            vec![compacted]
+        );
+        assert_eq!(plugin.plugin.file_header().hedr.next_id, FormId::from(0x802));
+        assert!(plugin.plugin.tes4.header.flags.contains(PluginFlags::LIGHT));
+        assert!(esl::check(&plugin).can_flag());
+
+        // Newer plugins can use object IDs from 0, and new cells are only a warning
+        let mut plugin = load_order::LoadedPlugin {
+            name: String::from("Gems.esp"),
+            plugin: test_plugin(&["Skyrim.esm"], records()),
+        };
+        plugin.plugin.file_header_mut().hedr.version = esl::EXTENDED_RANGE_VERSION;
+
+        let cell_id = FormId::from(0x0100_0000);
+        add_top_group(
+            &mut plugin.plugin,
+            b"CELL",
+            vec![GroupChild::Record(test_record(b"CELL", cell_id, &[]))],
+        );
+
+        let check = esl::check(&plugin);
+        assert_eq!(check.first_object_id, 0x000);
+        assert_eq!(
+            check.issues,
+            vec![EslIssue::OutOfRange(vec![outside]), EslIssue::NewCells(vec![cell_id])]
+        );
+
+        esl::compact(&mut plugin).unwrap();
+        assert!(plugin.plugin.record(FormId::from(0x0100_0001)).is_some());
+        assert!(esl::check(&plugin).can_flag());
+
+        // References in subrecords of unknown layout can't be rewritten
+        let unknown = test_record(b"MISC", FormId::from(0x0100_0900), &[(b"XQQQ", &outside.to_le_bytes())]);
+        let mut plugin = load_order::LoadedPlugin {
+            name: String::from("Gems.esp"),
+            plugin: test_plugin(&["Skyrim.esm"], [records(), vec![unknown]].concat()),
+        };
+
+        let check = esl::check(&plugin);
+        assert!(check
+            .issues
+            .contains(&EslIssue::UnknownFormIdLayout(vec![FormId::from(0x0100_0900)])));
+        assert!(!check.can_compact());
+        assert!(esl::compact(&mut plugin).is_err());
+        assert!(plugin.plugin.record(outside).is_some());
+    }
+
+    #[test]
+    fn test_masters() {
+        let keyworded = |id, keyword: u32| {
+            test_record(
+                b"MISC",
+                FormId::from(id),
+                &[(b"KSIZ", &1u32.to_le_bytes()), (b"KWDA", &keyword.to_le_bytes())],
+            )
+        };
+        let new_plugin = || {
+            test_plugin(
+                &["A.esm", "B.esm", "C.esm"],
+                vec![keyworded(0x0300_0800, 0x0000_0123), keyworded(0x0100_0456, 0x0300_0800)],
+            )
+        };
+        let keywords = |plugin: &Plugin, id: u32| schema::referenced_form_ids(plugin.record(FormId::from(id)).unwrap());
+
+        // Removing an unreferenced master moves the plugin's own records down
+        let mut plugin = new_plugin();
+        masters::remove_master(&mut plugin, "C.esm").unwrap();
+        assert_eq!(plugin.masters(), vec!["A.esm", "B.esm"]);
+        assert_eq!(keywords(&plugin, 0x0200_0800), vec![FormId::from(0x0000_0123)]);
+        assert_eq!(keywords(&plugin, 0x0100_0456), vec![FormId::from(0x0200_0800)]);
+
+        // Referenced masters can't be removed
+        assert!(masters::remove_master(&mut plugin, "A.esm").is_err());
+        assert!(masters::remove_master(&mut plugin, "B.esm").is_err());
+        assert_eq!(plugin.masters(), vec!["A.esm", "B.esm"]);
+        assert_eq!(
+            masters::master_references(&plugin, "B.esm").unwrap(),
+            vec![FormId::from(0x0100_0456)]
+        );
+
+        // Adding a master moves the plugin's own records up
+        masters::add_master(&mut plugin, "D.esm").unwrap();
+        assert_eq!(plugin.masters(), vec!["A.esm", "B.esm", "D.esm"]);
+        assert_eq!(keywords(&plugin, 0x0100_0456), vec![FormId::from(0x0300_0800)]);
+        assert!(plugin.record(FormId::from(0x0300_0800)).is_some());
+
+        // Reordering swaps indices
+        masters::reorder_masters(&mut plugin, &["B.esm", "A.esm", "D.esm"]).unwrap();
+        assert_eq!(plugin.masters(), vec!["B.esm", "A.esm", "D.esm"]);
+        assert_eq!(keywords(&plugin, 0x0300_0800), vec![FormId::from(0x0100_0123)]);
+        assert_eq!(keywords(&plugin, 0x0000_0456), vec![FormId::from(0x0300_0800)]);
+        assert!(masters::reorder_masters(&mut plugin, &["B.esm", "B.esm", "D.esm"]).is_err());
+
+        // ONAM is rebuilt rather than remapped, so an entry left over from a removed reference is dropped
+        let mut plugin = new_plugin();
+        plugin.file_header_mut().overrides = vec![FormId::from(0x0200_0900)];
+        masters::remove_master(&mut plugin, "C.esm").unwrap();
+        assert!(plugin.file_header().overrides.is_empty());
+
+        // Nothing is rewritten while a record holds subrecords of unknown layout
+        let unknown = test_record(
+            b"MISC",
+            FormId::from(0x0300_0801),
+            &[(b"XQQQ", &0x0200_0123u32.to_le_bytes())],
+        );
+        let mut plugin = test_plugin(
+            &["A.esm", "B.esm", "C.esm"],
+            vec![
+                keyworded(0x0300_0800, 0x0000_0123),
+                keyworded(0x0100_0456, 0x0300_0800),
+                unknown,
+            ],
+        );
+
+        assert!(masters::master_references(&plugin, "C.esm").is_err());
+        assert!(masters::remove_master(&mut plugin, "C.esm").is_err());
+        assert!(masters::add_master(&mut plugin, "D.esm").is_err());
+        assert_eq!(plugin.masters(), vec!["A.esm", "B.esm", "C.esm"]);
+        assert!(plugin.record(FormId::from(0x0300_0800)).is_some());
+    }
+
+    #[test]
+    fn test_reference_index() {
+        use load_order::{GlobalFormId, LoadOrder};
+
+        let (keyword, gem) = (FormId::from(0x0000_0800), FormId::from(0x0000_0801));
+        let keyworded = |id| {
+            test_record(
+                b"MISC",
+                id,
+                &[(b"KSIZ", &1u32.to_le_bytes()), (b"KWDA", &keyword.to_le_bytes())],
+            )
+        };
+
+        let mut load_order = LoadOrder::new();
+        load_order.push(
+            "A.esm",
+            test_plugin(
+                &[],
+                vec![
+                    test_record(b"KYWD", keyword, &[(b"EDID", &zstring_data("Gems"))]),
+                    keyworded(gem),
+                ],
+            ),
+        );
+        load_order.push(
+            "B.esp",
+            test_plugin(
+                &["A.esm"],
+                vec![test_record(b"MISC", gem, &[]), keyworded(FormId::from(0x0100_0800))],
+            ),
+        );
+
+        let index = references::ReferenceIndex::build(&load_order);
+        let a = |object_id| GlobalFormId::new("A.esm", object_id);
+        let b = |object_id| GlobalFormId::new("B.esp", object_id);
+
+        assert_eq!(index.len(), 2);
+        assert_eq!(
+            index
+                .references_to(&a(0x800))
+                .iter()
+                .map(|reference| (reference.from.clone(), reference.plugin.as_str()))
+                .collect::<Vec<_>>(),
+            vec![(a(0x801), "A.esm"), (b(0x800), "B.esp")]
+        );
+        assert_eq!(index.references_from(&b(0x800))[0].to, a(0x800));
+        assert!(index.is_referenced(&a(0x800)));
+        assert!(!index.is_referenced(&a(0x801)));
+        assert!(index
+            .references_from(&a(0x801))
+            .iter()
+            .all(|reference| reference.plugin == "A.esm"));
+    }
+
+    #[test]
+    fn test_plugin_index() {
+        let cell_id = FormId::from(0x0000_0801);
+        let reference_id = FormId::from(0x0000_0802);
+
+        let mut plugin = test_plugin(
+            &[],
+            vec![test_record(
+                b"MISC",
+                FormId::from(0x0000_0800),
+                &[(b"EDID", &zstring_data("Gem"))],
+            )],
+        );
+        add_top_group(
+            &mut plugin,
+            b"CELL",
+            vec![
+                GroupChild::Record(test_record(b"CELL", cell_id, &[(b"EDID", &zstring_data("GemCave"))])),
+                GroupChild::Group(test_group(
+                    GroupType::CellTemporaryChildren,
+                    Label::ParentCell(cell_id),
+                    vec![GroupChild::Record(test_record(
+                        b"REFR",
+                        reference_id,
+                        &[(b"EDID", &zstring_data("GemRef"))],
+                    ))],
+                )),
+            ],
+        );
+
+        let index = index::PluginIndex::build(&plugin);
+
+        assert_eq!(index.len(), 3);
+        assert_eq!(index.form_id("GEMREF"), Some(reference_id));
+        assert_eq!(
+            index.location(reference_id).unwrap().groups,
+            vec![(GroupType::CellTemporaryChildren, Label::ParentCell(cell_id))]
+        );
+        assert!(index.location(cell_id).unwrap().groups.is_empty());
+        assert_eq!(
+            index.record(&plugin, reference_id).unwrap().header.editor_id.as_deref(),
+            Some("GemRef")
+        );
+        assert_eq!(
+            index.record_by_editor_id(&plugin, "gem").unwrap().header.code,
+            TypeCode::from(*b"MISC")
+        );
+        assert!(index.record_by_editor_id(&plugin, "Missing").is_none());
+    }
+
+    #[test]
+    fn test_query_parse() {
+        let filter = "WEAP where damage > 20 and not keyword WeapTypeSword"
+            .parse::<query::Filter>()
+            .unwrap();
+        let expected = query::Filter::Code(TypeCode::from(*b"WEAP")).and(
+            query::Filter::Compare(
+                query::FieldRef::Named(String::from("damage")),
+                query::Comparison::Greater,
+                20.0,
+            )
+            .and(!query::Filter::Keyword(query::Keyword::EditorId(String::from(
+                "WeapTypeSword",
+            )))),
+        );
+
+        assert_eq!(filter, expected);
+    }
+
+    #[test]
+    fn test_query_select() {
+        use load_order::{LoadOrder, LoadedPlugin};
+        use query::{Filter, Keyword};
+
+        let weapon = |id, damage: u16, keyword: u32| {
+            let data = [&25u32.to_le_bytes()[..], &9.0f32.to_le_bytes(), &damage.to_le_bytes()].concat();
+            test_record(
+                b"WEAP",
+                FormId::from(id),
+                &[
+                    (b"KSIZ", &1u32.to_le_bytes()),
+                    (b"KWDA", &keyword.to_le_bytes()),
+                    (b"DATA", &data),
+                ],
+            )
+        };
+
+        let mut load_order = LoadOrder::new();
+        load_order.push(
+            "Skyrim.esm",
+            test_plugin(
+                &[],
+                vec![test_record(
+                    b"KYWD",
+                    FormId::from(0x0001_E711),
+                    &[(b"EDID", &zstring_data("WeapTypeSword"))],
+                )],
+            ),
+        );
+        let plugin = LoadedPlugin {
+            name: String::from("Swords.esp"),
+            plugin: test_plugin(
+                &["Skyrim.esm"],
+                vec![
+                    weapon(0x0100_0802, 30, 0x0001_E711),
+                    weapon(0x0100_0801, 30, 0x0001_E712),
+                    weapon(0x0100_0800, 10, 0x0001_E712),
+                ],
+            ),
+        };
+
+        let select = |query: &str| {
+            query
+                .parse::<Filter>()
+                .unwrap()
+                .select(&plugin, &load_order)
+                .map(|records| records.iter().map(|record| record.header.id).collect::<Vec<_>>())
+        };
+
+        assert_eq!(
+            select("WEAP where damage > 20").unwrap(),
+            vec![FormId::from(0x0100_0801), FormId::from(0x0100_0802)]
+        );
+        assert_eq!(
+            select("WEAP where damage > 20 and not keyword WeapTypeSword").unwrap(),
+            vec![FormId::from(0x0100_0801)]
+        );
+        assert!(select("WEAP where keyword WeapTypeAxe").is_err());
+
+        // A single record can't resolve keywords by editor ID
+        let sword = plugin.plugin.record(FormId::from(0x0100_0802)).unwrap();
+
+        assert!(Filter::Keyword(Keyword::Id(FormId::from(0x0001_E711))).matches(sword));
+        assert!(!Filter::Keyword(Keyword::EditorId(String::from("WeapTypeSword"))).matches(sword));
+    }
+
+    #[test]
+    fn test_reference_decode() {
+        use reference::{EnableParent, LinkedReference, Lock, Ownership, PlacedReference, Placement};
+
+        let floats = |values: &[f32]| values.iter().flat_map(|value| value.to_le_bytes()).collect::<Vec<_>>();
+        let record = test_record(
+            b"REFR",
+            FormId::from(0x0000_0D00),
+            &[
+                (b"EDID", &zstring_data("GemRef")),
+                (b"NAME", &0x0000_0800u32.to_le_bytes()),
+                (b"XSCL", &floats(&[1.5])),
+                (b"XESP", &[&0x0000_0900u32.to_le_bytes()[..], &[0x01, 0, 0, 0]].concat()),
+                (
+                    b"XLKR",
+                    &[0x0000_0A00u32.to_le_bytes(), 0x0000_0A01u32.to_le_bytes()].concat(),
+                ),
+                (b"XLKR", &0x0000_0A02u32.to_le_bytes()),
+                (
+                    b"XLOC",
+                    &[&[50, 0, 0, 0][..], &0x0000_0B00u32.to_le_bytes(), &[0x04]].concat(),
+                ),
+                (b"XOWN", &0x0000_0C00u32.to_le_bytes()),
+                (b"XRNK", &2i32.to_le_bytes()),
+                (b"DATA", &floats(&[1024.0, -2048.0, 64.0, 0.0, 0.0, 1.5])),
+            ],
+        );
+
+        let reference = PlacedReference::decode(&record).unwrap();
+
+        assert_eq!(reference.base, FormId::from(0x0000_0800));
+        assert_eq!(reference.editor_id.as_deref(), Some("GemRef"));
+        assert_eq!(
+            reference.placement,
+            Placement {
+                position: [1024.0, -2048.0, 64.0],
+                rotation: [0.0, 0.0, 1.5],
+            }
+        );
+        assert_eq!(reference.scale(), 1.5);
+        assert_eq!(
+            reference.enable_parent,
+            Some(EnableParent {
+                reference: FormId::from(0x0000_0900),
+                opposite: true,
+                pop_in: false,
+            })
+        );
+        assert_eq!(
+            reference.linked_references,
+            vec![
+                LinkedReference {
+                    keyword: FormId::from(0x0000_0A00),
+                    reference: FormId::from(0x0000_0A01),
+                },
+                LinkedReference {
+                    keyword: FormId::from(0),
+                    reference: FormId::from(0x0000_0A02),
+                },
+            ]
+        );
+        assert_eq!(
+            reference.lock,
+            Some(Lock {
+                level: 50,
+                key: FormId::from(0x0000_0B00),
+                flags: 0x04,
+            })
+        );
+        assert_eq!(
+            reference.ownership,
+            Some(Ownership {
+                owner: FormId::from(0x0000_0C00),
+                rank: Some(2),
+            })
+        );
+        assert!(reference.teleport.is_none());
+
+        let unplaced = test_record(
+            b"REFR",
+            FormId::from(0x0000_0D01),
+            &[(b"NAME", &0x0000_0800u32.to_le_bytes())],
+        );
+        assert!(PlacedReference::decode(&unplaced).is_err());
+        assert!(PlacedReference::decode(&test_record(b"MISC", FormId::from(0x0000_0D02), &[])).is_err());
+    }
+
+    #[test]
+    fn test_spatial_index() {
+        use spatial::{cell_grid, Bounds, SpatialIndex};
+
+        let (world_id, cell_id, land_id) = (
+            FormId::from(0x0000_0800),
+            FormId::from(0x0000_0801),
+            FormId::from(0x0000_0802),
+        );
+        let placed = |id: u32, x: f32, y: f32| {
+            let position = [x, y, 0.0, 0.0, 0.0, 0.0]
+                .iter()
+                .flat_map(|value| value.to_le_bytes())
+                .collect::<Vec<_>>();
+            GroupChild::Record(test_record(
+                b"REFR",
+                FormId::from(id),
+                &[(b"NAME", &0x0000_0900u32.to_le_bytes()), (b"DATA", &position)],
+            ))
+        };
+
+        let cell = test_record(
+            b"CELL",
+            cell_id,
+            &[
+                (b"EDID", &zstring_data("GemField")),
+                (b"XCLC", &[(-1i32).to_le_bytes(), 2i32.to_le_bytes()].concat()),
+            ],
+        );
+        let temporary = test_group(
+            GroupType::CellTemporaryChildren,
+            Label::ParentCell(cell_id),
+            vec![
+                GroupChild::Record(test_record(b"LAND", land_id, &[])),
+                placed(0x0000_0A00, -2000.0, 9000.0),
+                placed(0x0000_0A01, -100.0, 8500.0),
+                placed(0x0000_0A02, 5000.0, 100.0),
+            ],
+        );
+        let world_children = test_group(
+            GroupType::WorldChildren,
+            Label::ParentWorld(world_id),
+            vec![
+                GroupChild::Record(cell),
+                GroupChild::Group(test_group(
+                    GroupType::CellChildren,
+                    Label::ParentCell(cell_id),
+                    vec![GroupChild::Group(temporary)],
+                )),
+            ],
+        );
+
+        let mut plugin = test_plugin(&[], vec![]);
+        add_top_group(
+            &mut plugin,
+            b"WRLD",
+            vec![
+                GroupChild::Record(test_record(b"WRLD", world_id, &[(b"EDID", &zstring_data("Tamriel"))])),
+                GroupChild::Group(world_children),
+            ],
+        );
+
+        let index = SpatialIndex::build(&plugin);
+        let world = index.world_by_editor_id("TAMRIEL").unwrap();
+        let ids = |references: Vec<_>| {
+            references
+                .into_iter()
+                .map(|indexed: &spatial::IndexedReference| indexed.reference.id)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(cell_grid(-0.5, 4096.0), GridCoord::new(-1, 1));
+        assert_eq!(world.world, world_id);
+
+        let cell = world.cell_at(-10.0, 8200.0).unwrap();
+        assert_eq!(cell.id, cell_id);
+        assert_eq!(cell.land, Some(land_id));
+        assert!(world.cell(GridCoord::new(0, 0)).is_none());
+        assert_eq!(world.cells_in(&Bounds::new([-5000.0, 0.0], [5000.0, 10000.0])).len(), 1);
+
+        assert_eq!(world.references().len(), 3);
+        assert!(world
+            .references()
+            .iter()
+            .all(|indexed| indexed.cell == cell_id && !indexed.persistent));
+        assert_eq!(
+            ids(world.references_in(&Bounds::new([-4096.0, 8192.0], [0.0, 12288.0]))),
+            vec![FormId::from(0x0000_0A00), FormId::from(0x0000_0A01)]
+        );
+        assert_eq!(
+            world
+                .references_near(-100.0, 8500.0, 2000.0)
+                .into_iter()
+                .map(|(indexed, distance)| (indexed.reference.id, distance.round()))
+                .collect::<Vec<_>>(),
+            vec![(FormId::from(0x0000_0A01), 0.0), (FormId::from(0x0000_0A00), 1965.0)]
+        );
+    }
+
+    #[test]
+    fn test_grid_coord() {
+        let cell = GridCoord::new(-1, 33);
+
+        assert_eq!(GridCoord::from_label(cell.to_label()), cell);
+        assert_eq!(GridCoord::from_label([0xFF, 0xFF, 0x02, 0x00]), GridCoord::new(2, -1));
+        assert_eq!(cell.block(), GridCoord::new(-1, 1));
+        assert_eq!(cell.sub_block(), GridCoord::new(-1, 4));
+        assert_eq!(
+            GridCoord::new(-1, 1).block_cells(),
+            (GridCoord::new(-32, 32), GridCoord::new(-1, 63))
+        );
+    }
+
+    #[test]
+    fn test_landscape_heightmap() {
+        use heightmap::Heightmap;
+        use land::{Landscape, TextureLayer, GRID_SIZE, HEIGHT_SCALE};
+
+        // Each row starts one unit above the last, and the second vertex of the first row two above the first
+        let mut gradients = vec![0i8; GRID_SIZE * GRID_SIZE];
+        gradients
+            .iter_mut()
+            .step_by(GRID_SIZE)
+            .for_each(|gradient| *gradient = 1);
+        gradients[1] = 2;
+        let vhgt = [
+            &10.0f32.to_le_bytes()[..],
+            &gradients.iter().map(|gradient| *gradient as u8).collect::<Vec<_>>(),
+            &[0; 3],
+        ]
+        .concat();
+
+        let (world_id, cell_id, land_id) = (
+            FormId::from(0x0000_0800),
+            FormId::from(0x0000_0801),
+            FormId::from(0x0000_0802),
+        );
+        let land = test_record(
+            b"LAND",
+            land_id,
+            &[
+                (b"DATA", &0x01u32.to_le_bytes()),
+                (b"VHGT", &vhgt),
+                (
+                    b"ATXT",
+                    &[&0x0000_0900u32.to_le_bytes()[..], &[2, 0], &1u16.to_le_bytes()].concat(),
+                ),
+                (
+                    b"VTXT",
+                    &[&5u16.to_le_bytes()[..], &[0, 0], &0.5f32.to_le_bytes()].concat(),
+                ),
+            ],
+        );
+
+        let landscape = Landscape::decode(&land).unwrap();
+        let top = (10.0 + GRID_SIZE as f32) * HEIGHT_SCALE;
+
+        assert_eq!(landscape.flags, 0x01);
+        assert_eq!(landscape.height(0, 0), Some(11.0 * HEIGHT_SCALE));
+        assert_eq!(landscape.height(1, 0), Some(13.0 * HEIGHT_SCALE));
+        assert_eq!(landscape.height(GRID_SIZE - 1, 0), Some(13.0 * HEIGHT_SCALE));
+        assert_eq!(landscape.height(0, 1), Some(12.0 * HEIGHT_SCALE));
+        assert_eq!(landscape.height(0, GRID_SIZE - 1), Some(top));
+        assert_eq!(landscape.height(0, GRID_SIZE), None);
+        assert_eq!(
+            landscape.layers,
+            vec![TextureLayer {
+                texture: FormId::from(0x0000_0900),
+                quadrant: 2,
+                layer: 1,
+                opacities: vec![(5, 0.5)],
+            }]
+        );
+
+        let cell = test_record(
+            b"CELL",
+            cell_id,
+            &[(b"XCLC", &[3i32.to_le_bytes(), (-4i32).to_le_bytes()].concat())],
+        );
+        let temporary = test_group(
+            GroupType::CellTemporaryChildren,
+            Label::ParentCell(cell_id),
+            vec![GroupChild::Record(land)],
+        );
+
+        let mut plugin = test_plugin(&[], vec![]);
+        add_top_group(
+            &mut plugin,
+            b"WRLD",
+            vec![
+                GroupChild::Record(test_record(b"WRLD", world_id, &[])),
+                GroupChild::Group(test_group(
+                    GroupType::WorldChildren,
+                    Label::ParentWorld(world_id),
+                    vec![
+                        GroupChild::Record(cell),
+                        GroupChild::Group(test_group(
+                            GroupType::CellChildren,
+                            Label::ParentCell(cell_id),
+                            vec![GroupChild::Group(temporary)],
+                        )),
+                    ],
+                )),
+            ],
+        );
+
+        let spatial = spatial::SpatialIndex::build(&plugin);
+        let heightmap = Heightmap::build(&plugin, spatial.world(world_id).unwrap())
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(heightmap.origin, GridCoord::new(3, -4));
+        assert_eq!((heightmap.width, heightmap.height), (GRID_SIZE, GRID_SIZE));
+        assert_eq!((heightmap.min_height, heightmap.max_height), (11.0 * HEIGHT_SCALE, top));
+
+        // Rows run north to south
+        let normalized = heightmap.normalized();
+        assert_eq!(normalized[0], u16::MAX);
+        assert_eq!(normalized[(GRID_SIZE - 1) * GRID_SIZE], 0);
+        assert_eq!(heightmap.to_raw16().len(), GRID_SIZE * GRID_SIZE * 2);
+        assert!(heightmap.to_png().unwrap().starts_with(b"\x89PNG\r\n\x1a\n"));
+    }
+
+    #[test]
+    fn test_navmesh() {
+        use navmesh::{validate_edge_links, EdgeLinkIssue, Navmesh, NavmeshParent};
+
+        /// NVNM data for a single triangle, its edges given as neighbouring triangles or edge link indices
+        fn nvnm(parent: [u32; 2], edges: [i16; 3], flags: u16, edge_links: &[(u32, i16)], doors: &[u32]) -> Vec<u8> {
+            let mut bytes = [12u32, 0x5A5A_5A5A, parent[0], parent[1], 3]
+                .iter()
+                .flat_map(|value| value.to_le_bytes())
+                .collect::<Vec<_>>();
+
+            for vertex in &[[0.0f32, 0.0, 0.0], [128.0, 0.0, 0.0], [0.0, 128.0, 8.0]] {
+                bytes.extend(vertex.iter().flat_map(|value| value.to_le_bytes()));
+            }
+
+            bytes.extend(1u32.to_le_bytes());
+            bytes.extend([0i16, 1, 2].iter().chain(&edges).flat_map(|value| value.to_le_bytes()));
+            bytes.extend([flags, 0].iter().flat_map(|value| value.to_le_bytes()));
+
+            bytes.extend((edge_links.len() as u32).to_le_bytes());
+            for (navmesh, triangle) in edge_links {
+                bytes.extend([&0u32.to_le_bytes()[..], &navmesh.to_le_bytes(), &triangle.to_le_bytes()].concat());
+            }
+
+            bytes.extend((doors.len() as u32).to_le_bytes());
+            for door in doors {
+                bytes.extend([&0i16.to_le_bytes()[..], &0u32.to_le_bytes(), &door.to_le_bytes()].concat());
+            }
+
+            bytes.extend(0u32.to_le_bytes());
+            bytes
+        }
+
+        // Grid Y before X
+        let outdoor_parent = [0x0000_0900, ((-3i16) as u16 as u32) | (2 << 16)];
+        let navmesh = |id, nvnm: Vec<u8>| test_record(b"NAVM", FormId::from(id), &[(b"NVNM", &nvnm)]);
+        let navmeshes = vec![
+            navmesh(
+                0x0000_0800,
+                nvnm(outdoor_parent, [0, -1, -1], 0x0001, &[(0x0000_0801, 0)], &[0x0000_0A00]),
+            ),
+            navmesh(
+                0x0000_0801,
+                nvnm([0, 0x0000_0B00], [-1, 0, -1], 0x0002, &[(0x0000_0800, 0)], &[]),
+            ),
+            navmesh(
+                0x0000_0802,
+                nvnm([0, 0x0000_0B00], [0, 5, -1], 0x0003, &[(0x0000_0803, 0)], &[]),
+            ),
+        ];
+
+        let outdoor = Navmesh::decode(&navmeshes[0]).unwrap();
+        assert_eq!(
+            outdoor.parent,
+            NavmeshParent::Worldspace {
+                world: FormId::from(0x0000_0900),
+                grid: GridCoord::new(2, -3),
+            }
+        );
+        assert_eq!(outdoor.vertices[2], [0.0, 128.0, 8.0]);
+        assert_eq!(outdoor.triangles[0].edge_link(0), Some(0));
+        assert_eq!(outdoor.triangles[0].edge_link(1), None);
+        assert_eq!(outdoor.door_links[0].door, FormId::from(0x0000_0A00));
+        assert_eq!(
+            schema::referenced_form_ids(&navmeshes[0]),
+            vec![
+                FormId::from(0x0000_0900),
+                FormId::from(0x0000_0801),
+                FormId::from(0x0000_0A00)
+            ]
+        );
+
+        let indoor = Navmesh::decode(&navmeshes[1]).unwrap();
+        assert_eq!(indoor.parent, NavmeshParent::Cell(FormId::from(0x0000_0B00)));
+        assert_eq!(
+            navmesh::to_obj(&[&outdoor, &indoor])
+                .lines()
+                .filter(|line| line.starts_with('f'))
+                .collect::<Vec<_>>(),
+            vec!["f 1 2 3", "f 4 5 6"]
+        );
+
+        let mut load_order = load_order::LoadOrder::new();
+        load_order.push("Navmeshes.esp", test_plugin(&[], navmeshes));
+
+        let broken = validate_edge_links(&load_order.plugins[0], &load_order).unwrap();
+        assert_eq!(
+            broken
+                .iter()
+                .map(|link| (link.navmesh.object_id, link.edge, link.issue.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                (0x802, 0, EdgeLinkIssue::MissingNavmesh),
+                (0x802, 1, EdgeLinkIssue::MissingEdgeLink(5))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dialogue_tree() {
+        use dialogue::{Dialogue, Info};
+        use parsers::common::LString;
+        use scripts::{FlaggedFragments, Fragment, Fragments, Vmad};
+
+        let (quest_id, branch_id, topic_id) = (
+            FormId::from(0x0000_0800),
+            FormId::from(0x0000_0801),
+            FormId::from(0x0000_0802),
+        );
+        let (first_id, second_id) = (FormId::from(0x0000_0811), FormId::from(0x0000_0810));
+
+        let vmad = Vmad {
+            version: 5,
+            object_format: 2,
+            scripts: vec![],
+            fragments: Some(Fragments::Info(FlaggedFragments {
+                unknown: 2,
+                flags: 0x01,
+                file_name: String::from("TIF__00000811"),
+                fragments: vec![Fragment {
+                    unknown: 0,
+                    script: String::from("TIF__00000811"),
+                    function: String::from("Fragment_0"),
+                }],
+            })),
+        };
+        let trdt = [&[0u8; 12][..], &[1, 0, 0, 0], &[0; 4]].concat();
+
+        // Responses are Windows-1252, not UTF-8
+        let first = test_record(
+            b"INFO",
+            first_id,
+            &[
+                (b"VMAD", &vmad.encode()),
+                (b"TRDT", &trdt),
+                (b"NAM1", b"Caf\xe9 au lait?\0"),
+                (b"NAM2", b"Na\xefve\0"),
+                (b"TCLT", &topic_id.to_le_bytes()),
+            ],
+        );
+        let second = test_record(
+            b"INFO",
+            second_id,
+            &[
+                (b"PNAM", &first_id.to_le_bytes()),
+                (b"TRDT", &trdt),
+                (b"NAM1", b"Goodbye.\0"),
+            ],
+        );
+        let topic = test_record(
+            b"DIAL",
+            topic_id,
+            &[
+                (b"EDID", &zstring_data("GemTopic")),
+                (b"FULL", &zstring_data("Gems?")),
+                (b"BNAM", &branch_id.to_le_bytes()),
+                (b"QNAM", &quest_id.to_le_bytes()),
+            ],
+        );
+
+        let mut plugin = test_plugin(
+            &[],
+            vec![
+                test_record(
+                    b"QUST",
+                    quest_id,
+                    &[(b"EDID", &zstring_data("GemQuest")), (b"FULL", &zstring_data("Gems"))],
+                ),
+                test_record(
+                    b"DLBR",
+                    branch_id,
+                    &[
+                        (b"EDID", &zstring_data("GemBranch")),
+                        (b"QNAM", &quest_id.to_le_bytes()),
+                        (b"SNAM", &topic_id.to_le_bytes()),
+                    ],
+                ),
+            ],
+        );
+        add_top_group(
+            &mut plugin,
+            b"DIAL",
+            vec![
+                GroupChild::Record(topic),
+                GroupChild::Group(test_group(
+                    GroupType::TopicChildren,
+                    Label::ParentDialog(topic_id),
+                    vec![GroupChild::Record(second), GroupChild::Record(first)],
+                )),
+            ],
+        );
+
+        let dialogue = Dialogue::build(&plugin).unwrap();
+        let quest = &dialogue.quests[0];
+        let topic = &quest.branches[0].topics[0];
+
+        assert_eq!(dialogue.quests.len(), 1);
+        assert_eq!(quest.editor_id.as_deref(), Some("GemQuest"));
+        assert_eq!(quest.name, Some(LString::Inline(String::from("Gems"))));
+        assert!(quest.topics.is_empty());
+        assert_eq!(topic.topic.editor_id.as_deref(), Some("GemTopic"));
+        assert_eq!(
+            topic.infos.iter().map(|info| info.id).collect::<Vec<_>>(),
+            vec![first_id, second_id]
+        );
+
+        let info = &topic.infos[0];
+        assert_eq!(
+            info.responses[0].text,
+            Some(LString::Inline(String::from("Café au lait?")))
+        );
+        assert_eq!(info.responses[0].notes.as_deref(), Some("Naïve"));
+        assert_eq!(info.scripts, vec!["TIF__00000811"]);
+        assert_eq!(info.linked_topics, vec![topic_id]);
+
+        let dot = dialogue.to_dot();
+        assert!(dot.contains("q00000800 -> b00000801;"));
+        assert!(dot.contains("i00000811 -> t00000802 [style=dashed];"));
+        assert!(dot.contains("Scripts: TIF__00000811\\nCafé au lait?"));
+
+        let json = dialogue.to_json();
+        assert!(json.contains("\"scripts\":[\"TIF__00000811\"]"));
+        assert!(json.contains("\"notes\":\"Naïve\""));
+
+        // Script data that can't be read doesn't hide the response
+        let unreadable = test_record(
+            b"INFO",
+            first_id,
+            &[(b"VMAD", &[5, 0]), (b"TRDT", &trdt), (b"NAM1", b"Goodbye.\0")],
+        );
+        let info = Info::decode(&unreadable, false).unwrap();
+        assert!(info.scripts.is_empty());
+        assert_eq!(info.responses.len(), 1);
+    }
+
+    #[test]
+    fn test_condition_render() {
+        let mut bytes = vec![0x01, 0, 0, 0];
+        bytes.extend_from_slice(&1.0f32.to_le_bytes());
+        bytes.extend_from_slice(&[
+            72, 0, 0, 0, 0x07, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF,
+        ]);
+        let is_player = condition::Condition::decode(&bytes).unwrap();
+
+        let mut bytes = vec![0x44, 0, 0, 0, 0x34, 0x12, 0, 0];
+        bytes.extend_from_slice(&[
+            58, 0, 0, 0, 0x78, 0x56, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF,
+        ]);
+        let stage = condition::Condition::decode(&bytes).unwrap();
+
+        assert_eq!(
+            condition::render_all(&[is_player.clone(), stage.clone()], |_| None),
+            "GetIsID(Player) == 1 OR Target.GetStage(0x00005678) > 0x00001234"
+        );
+        assert_eq!(
+            condition::render_all(&[stage.clone(), is_player.clone(), stage.clone()], |_| None),
+            "Target.GetStage(0x00005678) > 0x00001234 AND (GetIsID(Player) == 1 OR Target.GetStage(0x00005678) > \
+             0x00001234)"
+        );
+        assert_eq!(
+            condition::render_all(
+                &[is_player.clone(), stage.clone(), is_player.clone(), is_player],
+                |_| None
+            ),
+            "(GetIsID(Player) == 1 OR Target.GetStage(0x00005678) > 0x00001234) AND (GetIsID(Player) == 1 OR \
+             GetIsID(Player) == 1)"
+        );
+        assert_eq!(stage.form_ids(), vec![FormId::from(0x1234), FormId::from(0x5678)]);
+    }
+
+    #[test]
+    fn test_vmad_round_trip() {
+        use scripts::{AliasScripts, Fragments, ObjectRef, PropertyValue, Script, Vmad};
+
+        let object = |id| ObjectRef {
+            form_id: FormId::from(id),
+            alias: -1,
+            unused: 0,
+        };
+
+        let mut script = Script {
+            name: String::from("QF_Example"),
+            status: 0,
+            properties: vec![],
+        };
+        script.set_property("Target", PropertyValue::Object(object(0x0100_0D62)));
+        script.set_property("Count", PropertyValue::IntArray(vec![1, 2]));
+
+        let vmad = Vmad {
+            version: 5,
+            object_format: 2,
+            scripts: vec![script.clone()],
+            fragments: Some(Fragments::Quest {
+                unknown: 2,
+                file_name: String::from("QF_Example"),
+                fragments: vec![],
+                aliases: vec![AliasScripts {
+                    object: ObjectRef { alias: 0, ..object(0) },
+                    version: 5,
+                    object_format: 2,
+                    scripts: vec![script],
+                }],
+            }),
+        };
+
+        let bytes = vmad.encode();
+        assert_eq!(Vmad::parse(&bytes, &TypeCode::from(*b"QUST")).unwrap(), vmad);
+        assert_eq!(
+            parsers::records::vmad::form_id_offsets(&bytes, &TypeCode::from(*b"QUST")).map(|offsets| offsets.len()),
+            Some(3)
+        );
+    }
+
+    #[test]
+    fn test_pex_disassemble() {
+        fn wstring(out: &mut Vec<u8>, value: &str) {
+            out.extend_from_slice(&(value.len() as u16).to_be_bytes());
+            out.extend_from_slice(value.as_bytes());
+        }
+
+        let strings = [
+            "Foo",
+            "Quest",
+            "",
+            "Bar",
+            "Actor",
+            "::Bar_var",
+            "OnInit",
+            "None",
+            "self",
+            "::NoneVar",
+            "Start",
+        ];
+        let refs = |indices: &[u16]| indices.iter().flat_map(|index| index.to_be_bytes()).collect::<Vec<_>>();
+
+        let mut object = refs(&[1, 2]);
+        object.extend_from_slice(&0u32.to_be_bytes());
+        object.extend(refs(&[2, 1, 5, 4]));
+        object.extend_from_slice(&[0, 0, 0, 0, 0]);
+        object.extend(refs(&[1, 3, 4, 2]));
+        object.extend_from_slice(&[0, 0, 0, 0, 0x07]);
+        object.extend(refs(&[5, 1, 2, 1, 6, 7, 2]));
+        object.extend_from_slice(&[0, 0, 0, 0, 0]);
+        object.extend(refs(&[0, 0, 1]));
+        object.extend_from_slice(&[23, 1, 0, 10, 1, 0, 8, 1, 0, 9, 3, 0, 0, 0, 1, 3, 0, 0, 0, 5]);
+
+        let mut bytes = vec![0xFA, 0x57, 0xC0, 0xDE, 3, 2, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0];
+        wstring(&mut bytes, "Foo.psc");
+        wstring(&mut bytes, "user");
+        wstring(&mut bytes, "pc");
+        bytes.extend(refs(&[strings.len() as u16]));
+        strings.iter().for_each(|string| wstring(&mut bytes, string));
+        bytes.push(0);
+        bytes.extend(refs(&[0, 1, 0]));
+        bytes.extend_from_slice(&(object.len() as u32 + 4).to_be_bytes());
+        bytes.extend(object);
+
+        let pex = pex::Pex::parse(&bytes).unwrap();
+        let listing = pex.disassemble();
+
+        assert_eq!(pex.object("foo").unwrap().parent, "Quest");
+        assert!(listing.contains(".property Bar Actor auto ::Bar_var"));
+        assert!(listing.contains("callmethod Start self ::NoneVar 5"));
+    }
+
+    #[test]
+    fn test_nif_textures() {
+        let sized = |value: &str| {
+            let mut bytes = (value.len() as u32).to_le_bytes().to_vec();
+            bytes.extend_from_slice(value.as_bytes());
+            bytes
+        };
+
+        let mut block = 2u32.to_le_bytes().to_vec();
+        block.extend(sized("Textures\\Armor\\Iron.dds"));
+        block.extend(sized(""));
+
+        let mut bytes = b"Gamebryo File Format, Version 20.2.0.7\n".to_vec();
+        bytes.extend_from_slice(&0x1402_0007u32.to_le_bytes());
+        bytes.push(1);
+        bytes.extend_from_slice(&12u32.to_le_bytes());
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.extend_from_slice(&83u32.to_le_bytes());
+        bytes.extend_from_slice(&[1, 0, 1, 0, 1, 0]);
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend(sized("BSShaderTextureSet"));
+        bytes.extend_from_slice(&0u16.to_le_bytes());
+        bytes.extend_from_slice(&(block.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&[0; 12]);
+        bytes.extend(block);
+
+        let nif = nif::Nif::parse(&bytes).unwrap();
+        assert_eq!(nif.header.block_type(0), Some("BSShaderTextureSet"));
+        assert_eq!(nif.textures(), vec![String::from("textures\\armor\\iron.dds")]);
+    }
+
+    #[test]
+    fn test_asset_dependencies() {
+        use assets::{AssetKind, DataFiles};
+        use scripts::{Script, Vmad};
+
+        let vmad = Vmad {
+            version: 5,
+            object_format: 2,
+            scripts: vec![Script {
+                name: String::from("GemScript"),
+                status: 0,
+                properties: vec![],
+            }],
+            fragments: None,
+        };
+        let quest = [&seq::flags::START_GAME_ENABLED.to_le_bytes()[..], &[50, 0], &[0; 8]].concat();
+
+        let mut plugin = test_plugin(
+            &["Skyrim.esm"],
+            vec![
+                test_record(
+                    b"MISC",
+                    FormId::from(0x0100_0800),
+                    &[(b"MODL", &zstring_data("Clutter\\Gem.nif")), (b"VMAD", &vmad.encode())],
+                ),
+                test_record(
+                    b"TXST",
+                    FormId::from(0x0100_0801),
+                    &[(b"TX00", &zstring_data("Clutter\\Gem_d.dds"))],
+                ),
+                test_record(
+                    b"SOUN",
+                    FormId::from(0x0100_0802),
+                    &[(b"FNAM", &zstring_data("FX\\Gem.wav"))],
+                ),
+                test_record(b"NPC_", FormId::from(0x0100_0803), &[]),
+                test_record(b"QUST", FormId::from(0x0100_0804), &[(b"DNAM", &quest)]),
+            ],
+        );
+        plugin.tes4.header.flags.insert(PluginFlags::LOCALIZED);
+
+        let mut load_order = load_order::LoadOrder::new();
+        load_order.push("Gems.esp", plugin);
+
+        let dependencies = assets::dependencies(&load_order.plugins[0], &load_order, None, "english").unwrap();
+
+        assert_eq!(
+            dependencies
+                .iter()
+                .map(|dependency| (dependency.kind, dependency.path.as_str(), dependency.records.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    AssetKind::Mesh,
+                    "meshes\\clutter\\gem.nif",
+                    vec![FormId::from(0x0100_0800)]
+                ),
+                (
+                    AssetKind::Texture,
+                    "textures\\clutter\\gem_d.dds",
+                    vec![FormId::from(0x0100_0801)]
+                ),
+                (AssetKind::Sound, "sound\\fx\\gem.wav", vec![FormId::from(0x0100_0802)]),
+                (
+                    AssetKind::Script,
+                    "scripts\\gemscript.pex",
+                    vec![FormId::from(0x0100_0800)]
+                ),
+                (
+                    AssetKind::FaceGen,
+                    "meshes\\actors\\character\\facegendata\\facegeom\\gems.esp\\00000803.nif",
+                    vec![FormId::from(0x0100_0803)]
+                ),
+                (
+                    AssetKind::FaceGen,
+                    "textures\\actors\\character\\facegendata\\facetint\\gems.esp\\00000803.dds",
+                    vec![FormId::from(0x0100_0803)]
+                ),
+                (AssetKind::Sequence, "seq\\gems.seq", vec![FormId::from(0x0100_0804)]),
+                (AssetKind::Strings, "strings\\gems_english.dlstrings", vec![]),
+                (AssetKind::Strings, "strings\\gems_english.ilstrings", vec![]),
+                (AssetKind::Strings, "strings\\gems_english.strings", vec![]),
+            ]
+        );
+
+        let data_dir = std::env::temp_dir().join(format!("tes-parse-assets-{}", std::process::id()));
+        std::fs::create_dir_all(data_dir.join("Meshes").join("Clutter")).unwrap();
+        std::fs::write(data_dir.join("Meshes").join("Clutter").join("Gem.nif"), b"").unwrap();
+        let files = DataFiles::open(&data_dir);
+        std::fs::remove_dir_all(&data_dir).unwrap();
+
+        let files = files.unwrap();
+        assert!(dependencies[0].is_present(&files));
+        assert!(!dependencies[1].is_present(&files));
+    }
+
+    #[test]
+    fn test_asset_paths() {
+        let npc = load_order::GlobalFormId::new("MyMod.esp", 0x0200_0D62);
+        assert_eq!(
+            assets::facegen_paths(&npc)[0],
+            "meshes\\actors\\character\\facegendata\\facegeom\\mymod.esp\\00000d62.nif"
+        );
+
+        let info = load_order::GlobalFormId::new("Skyrim.esm", 0x000B_8F5D);
+        assert_eq!(
+            assets::voice_path(&info, "MaleNord", "DialogueGeneric", "DialogueGenericHello", 1),
+            "sound\\voice\\skyrim.esm\\malenord\\dialoguege_dialoguegeneric_000b8f5d_1.fuz"
+        );
+    }
+
+    #[test]
+    fn test_seq() {
+        use seq::{flags, Quest};
+
+        let quest = |id: u32, quest_flags: u16| {
+            let dnam = [&quest_flags.to_le_bytes()[..], &[60, 0], &[0; 4], &6u32.to_le_bytes()].concat();
+            test_record(b"QUST", FormId::from(id), &[(b"DNAM", &dnam)])
+        };
+
+        let decoded = Quest::decode(&quest(0x0100_0801, flags::START_GAME_ENABLED | flags::RUN_ONCE)).unwrap();
+        assert!(decoded.is_start_game_enabled());
+        assert_eq!((decoded.priority, decoded.quest_type), (60, Some(6)));
+
+        let plugin = test_plugin(
+            &["Skyrim.esm"],
+            vec![
+                quest(0x0100_0802, flags::START_GAME_ENABLED),
+                quest(0x0100_0800, flags::RUN_ONCE),
+                quest(0x0000_0D62, flags::START_GAME_ENABLED),
+            ],
+        );
+
+        let quests = vec![FormId::from(0x0000_0D62), FormId::from(0x0100_0802)];
+        assert_eq!(seq::start_game_quests(&plugin).unwrap(), quests);
+
+        let bytes = seq::generate(&plugin).unwrap().unwrap();
+        assert_eq!(bytes, [0x62, 0x0D, 0, 0, 0x02, 0x08, 0, 0x01]);
+        assert_eq!(seq::parse(&bytes).unwrap(), quests);
+        assert!(seq::parse(&bytes[..7]).is_err());
+
+        let plugin = test_plugin(&[], vec![quest(0x0000_0800, 0)]);
+        assert_eq!(seq::generate(&plugin).unwrap(), None);
+    }
+
+    #[test]
+    fn test_leveled_merge() {
+        use leveled::{merge_lists, Entry, LeveledList};
+
+        let entry = |level, reference| Entry {
+            level,
+            unknown: 0,
+            reference: FormId::from(reference),
+            count: 1,
+            unknown2: 0,
+            extra: None,
+        };
+
+        let base = LeveledList {
+            id: FormId::from(0x0001_0000),
+            code: TypeCode::from(*b"LVLI"),
+            editor_id: None,
+            chance_none: 0,
+            flags: 0,
+            global: None,
+            entries: vec![entry(1, 0x100), entry(5, 0x200)],
+        };
+
+        let adds = LeveledList {
+            entries: vec![entry(1, 0x100), entry(5, 0x200), entry(3, 0x0100_0300)],
+            ..base.clone()
+        };
+        let removes = LeveledList {
+            chance_none: 25,
+            entries: vec![entry(5, 0x200)],
+            ..base.clone()
+        };
+
+        let merged = merge_lists(&base, &[(&base, &adds), (&base, &removes)]);
+        assert_eq!(merged.chance_none, 25);
+        assert_eq!(merged.entries, vec![entry(3, 0x0100_0300), entry(5, 0x200)]);
+    }
+
+    #[test]
+    fn test_patch_build() {
+        use load_order::{GlobalFormId, LoadOrder};
+
+        let ids = |ids: &[u32]| ids.iter().map(|id| FormId::from(*id)).collect::<Vec<_>>();
+        let build = |code: &[u8; 4], id: u32, subrecords: Vec<(&[u8; 4], Vec<u8>)>| {
+            let subrecords = subrecords
+                .iter()
+                .map(|(code, data)| (*code, data.as_slice()))
+                .collect::<Vec<_>>();
+            test_record(code, FormId::from(id), &subrecords)
+        };
+        let misc = |value: u32, weight: f32, keywords: &[u32]| {
+            build(
+                b"MISC",
+                0x0000_0800,
+                vec![
+                    (b"KSIZ", (keywords.len() as u32).to_le_bytes().to_vec()),
+                    (
+                        b"KWDA",
+                        keywords.iter().flat_map(|keyword| keyword.to_le_bytes()).collect(),
+                    ),
+                    (b"DATA", [value.to_le_bytes(), weight.to_le_bytes()].concat()),
+                ],
+            )
+        };
+        let list = |entries: &[u32]| {
+            build(
+                b"FLST",
+                0x0000_0801,
+                entries
+                    .iter()
+                    .map(|entry| (b"LNAM", entry.to_le_bytes().to_vec()))
+                    .collect(),
+            )
+        };
+        let container = |items: &[(u32, i32)]| {
+            let mut subrecords = vec![(b"COCT", (items.len() as u32).to_le_bytes().to_vec())];
+            subrecords.extend(
+                items
+                    .iter()
+                    .map(|(item, count)| (b"CNTO", [item.to_le_bytes(), count.to_le_bytes()].concat())),
+            );
+            build(b"CONT", 0x0000_0802, subrecords)
+        };
+        let armor = |value: u32, weight: f32, unknown: bool| {
+            let mut subrecords = vec![(b"DATA", [value.to_le_bytes(), weight.to_le_bytes()].concat())];
+            if unknown {
+                subrecords.push((b"XQQQ", vec![0; 4]));
+            }
+            build(b"ARMO", 0x0000_0803, subrecords)
+        };
+
+        let mut load_order = LoadOrder::new();
+        load_order.push(
+            "A.esm",
+            test_plugin(
+                &[],
+                vec![
+                    misc(10, 1.0, &[0x900, 0x901]),
+                    list(&[0x910, 0x911]),
+                    container(&[(0x920, 1), (0x921, 1)]),
+                    armor(50, 1.0, false),
+                ],
+            ),
+        );
+        load_order.push(
+            "B.esp",
+            test_plugin(
+                &["A.esm"],
+                vec![
+                    misc(20, 1.0, &[0x900, 0x901, 0x902]),
+                    list(&[0x910, 0x911, 0x912]),
+                    container(&[(0x920, 5), (0x921, 1)]),
+                    armor(100, 1.0, false),
+                ],
+            ),
+        );
+        load_order.push(
+            "New.esm",
+            test_plugin(&[], vec![test_record(b"KYWD", FormId::from(0x0000_0800), &[])]),
+        );
+
+        // C.esp loads New.esm's keyword as 0x01000800, which is 0x02000800 in load order terms and 0x01000800 again in
+        // the patch, where New.esm is the second master
+        load_order.push(
+            "C.esp",
+            test_plugin(
+                &["A.esm", "New.esm"],
+                vec![
+                    misc(10, 2.0, &[0x901, 0x0100_0800]),
+                    list(&[0x911]),
+                    container(&[(0x920, 1), (0x921, 1)]),
+                    armor(50, 3.0, true),
+                ],
+            ),
+        );
+
+        let patch = patch::build(&load_order, "Patch.esp").unwrap();
+        let plugin = &patch.plugin.plugin;
+        let form = |object_id| GlobalFormId::new("A.esm", object_id);
+        let data = |id: u32, code: &[u8; 4]| {
+            plugin
+                .record(FormId::from(id))
+                .unwrap()
+                .subrecords()
+                .iter()
+                .filter(|subrecord| &*subrecord.code == code)
+                .map(|subrecord| subrecord.data.to_vec())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(patch.patched, vec![form(0x800), form(0x801), form(0x802)]);
+        assert_eq!(patch.skipped.len(), 1);
+        assert_eq!(patch.skipped[0].0, form(0x803));
+        assert!(patch.skipped[0].1.contains("XQQQ"));
+        assert_eq!(plugin.masters(), vec!["A.esm", "New.esm"]);
+        // Three records, each in its own top group
+        assert_eq!(plugin.file_header().hedr.num_records, 6);
+
+        // Fields: the value from B.esp and the weight from C.esp
+        assert_eq!(
+            data(0x800, b"DATA"),
+            vec![[20u32.to_le_bytes(), 2.0f32.to_le_bytes()].concat()]
+        );
+
+        // Arrays: B.esp's added keyword, without the one C.esp removed and with the one it added
+        assert_eq!(data(0x800, b"KSIZ"), vec![3u32.to_le_bytes().to_vec()]);
+        assert_eq!(
+            schema::referenced_form_ids(plugin.record(FormId::from(0x0000_0800)).unwrap()),
+            ids(&[0x901, 0x902, 0x0100_0800])
+        );
+
+        // Lists of subrecords
+        assert_eq!(
+            data(0x801, b"LNAM"),
+            vec![0x911u32.to_le_bytes().to_vec(), 0x912u32.to_le_bytes().to_vec()]
+        );
+        assert_eq!(data(0x802, b"COCT"), vec![2u32.to_le_bytes().to_vec()]);
+        assert_eq!(
+            data(0x802, b"CNTO"),
+            vec![
+                [0x921u32.to_le_bytes(), 1i32.to_le_bytes()].concat(),
+                [0x920u32.to_le_bytes(), 5i32.to_le_bytes()].concat(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_record_builder() {
+        use builder::RecordBuilder;
+
+        let id = FormId::from(0x0100_0800);
+        let record = RecordBuilder::new(*b"MISC")
+            .editor_id("Gem")
+            .compressed(true)
+            .keywords(&[FormId::from(0x0009_14E9)])
+            .subrecord(*b"DATA", [100u32.to_le_bytes(), 0.5f32.to_le_bytes()].concat())
+            .build(id);
+
+        assert_eq!(record.header.editor_id.as_deref(), Some("Gem"));
+        assert_eq!(
+            record
+                .subrecords()
+                .iter()
+                .map(|subrecord| subrecord.code.to_string())
+                .collect::<Vec<_>>(),
+            vec!["EDID", "KSIZ", "KWDA", "DATA"]
+        );
+        assert!(record.header.flags.contains(builder::RecordFlags::COMPRESSED));
+    }
+
+    #[test]
+    fn test_record_insert() {
+        use builder::RecordBuilder;
+
+        let taken = FormId::from(0x0100_0801);
+        let mut plugin = test_plugin(&["Skyrim.esm"], vec![RecordBuilder::new(*b"MISC").build(taken)]);
+
+        let first = RecordBuilder::new(*b"MISC")
+            .editor_id("First")
+            .insert(&mut plugin)
+            .unwrap();
+        let second = RecordBuilder::new(*b"MISC")
+            .editor_id("Second")
+            .insert(&mut plugin)
+            .unwrap();
+        let weapon = RecordBuilder::new(*b"WEAP").insert(&mut plugin).unwrap();
+
+        assert_eq!(first, FormId::from(0x0100_0800));
+        assert_eq!(second, FormId::from(0x0100_0802));
+        assert_eq!(weapon, FormId::from(0x0100_0803));
+        assert_eq!(plugin.file_header().hedr.next_id, FormId::from(0x804));
+        assert!(plugin.groups.contains_key(&TypeCode::from(*b"WEAP")));
+
+        // Two top groups, holding three records and one
+        assert_eq!(plugin.file_header().hedr.num_records, 6);
+
+        let replaced = plugin
+            .insert_record(RecordBuilder::new(*b"MISC").editor_id("Replacement").build(taken))
+            .unwrap();
+        assert!(replaced.is_some());
+        assert_eq!(plugin.file_header().hedr.num_records, 6);
+
+        assert!(plugin
+            .insert_record(RecordBuilder::new(*b"CELL").build(FormId::from(0x0100_0900)))
+            .is_err());
+
+        // Nothing is removed or allocated when the record's group can't be edited
+        let mut npcs = Group::top(TypeCode::from(*b"NPC_"));
+        npcs.data = GroupData::Unimplemented(vec![0; 4]);
+        plugin.groups.insert(TypeCode::from(*b"NPC_"), npcs);
+        plugin.update_num_records();
+
+        assert!(plugin.insert_record(RecordBuilder::new(*b"NPC_").build(taken)).is_err());
+        assert_eq!(
+            plugin.record(taken).unwrap().header.editor_id.as_deref(),
+            Some("Replacement")
+        );
+        assert!(RecordBuilder::new(*b"NPC_").insert(&mut plugin).is_err());
+        assert_eq!(plugin.file_header().hedr.next_id, FormId::from(0x804));
+        assert_eq!(plugin.file_header().hedr.num_records, 7);
+    }
+
+    #[test]
+    fn test_write_compressed() {
+        let bytes = writer::plugin_bytes(&DAWNGUARD_PLUGIN).unwrap();
+        let plugin = read_plugin(bytes.as_slice()).unwrap();
+
+        for record in DAWNGUARD_PLUGIN
+            .records()
+            .into_iter()
+            .filter(|record| record.compressed.is_some())
+        {
+            assert_eq!(plugin.record(record.header.id).unwrap().compressed, record.compressed);
+        }
+
+        let options = writer::WriteOptions {
+            compression_level: 9,
+            keep_compressed: false,
+        };
+        let bytes = writer::plugin_bytes_with(&DAWNGUARD_PLUGIN, &options).unwrap();
+        let plugin = read_plugin(bytes.as_slice()).unwrap();
+
+        assert!(diff::diff(&DAWNGUARD_PLUGIN, &plugin).modified.is_empty());
+    }
+
+    #[test]
+    fn test_write_compressed_records() {
+        use builder::RecordBuilder;
+        use writer::WriteOptions;
+
+        let id = FormId::from(0x0100_0800);
+        let plugin = test_plugin(
+            &["Skyrim.esm"],
+            vec![RecordBuilder::new(*b"MISC")
+                .editor_id("Gem")
+                .compressed(true)
+                .string(*b"FULL", "Flawless Ruby")
+                .subrecord(*b"DATA", [100u32.to_le_bytes(), 0.5f32.to_le_bytes()].concat())
+                .build(id)],
+        );
+        let bytes = writer::plugin_bytes(&plugin).unwrap();
+        let read = read_plugin(bytes.as_slice()).unwrap();
+        let subrecords = |plugin: &Plugin| {
+            plugin
+                .record(id)
+                .unwrap()
+                .subrecords()
+                .into_iter()
+                .map(|subrecord| (subrecord.code, subrecord.data.to_vec()))
+                .collect::<Vec<_>>()
+        };
+        let compressed = read.record(id).unwrap().compressed.clone().unwrap();
+
+        // Unchanged records keep their compressed bytes, whatever the level
+        let options = WriteOptions {
+            compression_level: 0,
+            ..WriteOptions::default()
+        };
+        assert_eq!(writer::plugin_bytes_with(&read, &options).unwrap(), bytes);
+
+        let recompressed = [0, 9]
+            .iter()
+            .map(|level| {
+                let options = WriteOptions {
+                    compression_level: *level,
+                    keep_compressed: false,
+                };
+                read_plugin(writer::plugin_bytes_with(&read, &options).unwrap().as_slice()).unwrap()
+            })
+            .collect::<Vec<_>>();
+        for plugin in &recompressed {
+            assert_eq!(subrecords(plugin), subrecords(&read));
+        }
+        assert!(
+            recompressed[0].record(id).unwrap().compressed.as_ref().unwrap().len()
+                > recompressed[1].record(id).unwrap().compressed.as_ref().unwrap().len()
+        );
+
+        // Edited records are compressed again
+        let mut edited = read_plugin(bytes.as_slice()).unwrap();
+        edited
+            .record_mut(id)
+            .unwrap()
+            .edit_subrecords(|subrecords| subrecords.retain(|(code, _)| &**code != b"FULL"));
+        let edited = read_plugin(writer::plugin_bytes(&edited).unwrap().as_slice()).unwrap();
+        let record = edited.record(id).unwrap();
+
+        assert!(record.header.flags.contains(RecordFlags::COMPRESSED));
+        assert!(record.compressed.is_some());
+        assert_ne!(record.compressed, Some(compressed));
+        assert_eq!(
+            subrecords(&edited)
+                .iter()
+                .map(|(code, _)| code.to_string())
+                .collect::<Vec<_>>(),
+            vec!["EDID", "DATA"]
+        );
+
+        let options = WriteOptions {
+            compression_level: 10,
+            ..WriteOptions::default()
+        };
+        assert!(writer::plugin_bytes_with(&read, &options).is_err());
+    }
 }
@@ -0,0 +1,243 @@
+use std::{
+    cmp::Ordering,
+    fmt,
+    fs::File,
+    hash::{Hash, Hasher},
+    path::Path,
+};
+
+use crate::{
+    parsers::{common::FormId, plugin::Plugin, records::Record},
+    read_plugin, schema,
+};
+
+/// A FormID resolved against its plugin's master list, identifying a form independently of load order.
+/// Plugin names compare case-insensitively, as they do in the engine
+#[derive(Debug, Clone)]
+pub struct GlobalFormId {
+    pub plugin: String,
+    pub object_id: u32,
+}
+
+impl GlobalFormId {
+    pub fn new<S>(plugin: S, object_id: u32) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            plugin: plugin.into(),
+            object_id: object_id & 0x00FF_FFFF,
+        }
+    }
+}
+
+impl PartialEq for GlobalFormId {
+    fn eq(&self, other: &Self) -> bool {
+        self.object_id == other.object_id && self.plugin.eq_ignore_ascii_case(&other.plugin)
+    }
+}
+
+impl Eq for GlobalFormId {}
+
+impl Hash for GlobalFormId {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.plugin.to_ascii_lowercase().hash(state);
+        self.object_id.hash(state);
+    }
+}
+
+impl PartialOrd for GlobalFormId {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for GlobalFormId {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.plugin
+            .to_ascii_lowercase()
+            .cmp(&other.plugin.to_ascii_lowercase())
+            .then(self.object_id.cmp(&other.object_id))
+    }
+}
+
+impl fmt::Display for GlobalFormId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{:06X}", self.plugin, self.object_id)
+    }
+}
+
+/// A plugin along with the file name its FormIDs are resolved against
+#[derive(Debug)]
+pub struct LoadedPlugin {
+    pub name: String,
+    pub plugin: Plugin,
+}
+
+impl LoadedPlugin {
+    pub fn global_form_id(&self, id: FormId) -> GlobalFormId {
+        let masters = self.plugin.masters();
+        let plugin = masters.get(id.index() as usize).copied().unwrap_or(&self.name);
+
+        GlobalFormId::new(plugin, id.object_id())
+    }
+
+    /// The FormID this plugin uses for a form, if the defining plugin is this one or one of its masters
+    pub fn local_form_id(&self, id: &GlobalFormId) -> Option<FormId> {
+        let masters = self.plugin.masters();
+
+        let index = if id.plugin.eq_ignore_ascii_case(&self.name) {
+            masters.len()
+        } else {
+            masters
+                .iter()
+                .position(|master| master.eq_ignore_ascii_case(&id.plugin))?
+        };
+
+        Some(FormId::from(id.object_id).with_index(index as u8))
+    }
+
+    pub fn record(&self, id: &GlobalFormId) -> Option<&Record> {
+        self.local_form_id(id).and_then(|id| self.plugin.record(id))
+    }
+
+    /// Whether the record is new in this plugin, rather than an override of a master's record
+    pub fn is_new_record(&self, id: FormId) -> bool {
+        id.index() as usize >= self.plugin.masters().len()
+    }
+}
+
+/// Plugins in the order the engine loads them
+#[derive(Debug, Default)]
+pub struct LoadOrder {
+    pub plugins: Vec<LoadedPlugin>,
+}
+
+impl LoadOrder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push<S>(&mut self, name: S, plugin: Plugin)
+    where
+        S: Into<String>,
+    {
+        self.plugins.push(LoadedPlugin {
+            name: name.into(),
+            plugin,
+        });
+    }
+
+    /// Read each named plugin from `data_dir`, in order
+    pub fn load<P, S>(data_dir: P, names: &[S]) -> Result<Self, crate::Error>
+    where
+        P: AsRef<Path>,
+        S: AsRef<str>,
+    {
+        let mut load_order = Self::new();
+
+        for name in names {
+            let plugin = read_plugin(File::open(data_dir.as_ref().join(name.as_ref()))?)?;
+            load_order.push(name.as_ref(), plugin);
+        }
+
+        Ok(load_order)
+    }
+
+    /// Read a plugin from `data_dir` along with its masters, recursively, with every master loaded before the
+    /// plugins that depend on it
+    pub fn load_with_masters<P>(data_dir: P, name: &str) -> Result<Self, crate::Error>
+    where
+        P: AsRef<Path>,
+    {
+        let mut load_order = Self::new();
+        load_order.push_with_masters(data_dir.as_ref(), name)?;
+
+        Ok(load_order)
+    }
+
+    /// Read a plugin from `data_dir` and add it to the end of the load order, after any of its masters, recursively,
+    /// that aren't loaded yet
+    pub fn push_with_masters(&mut self, data_dir: &Path, name: &str) -> Result<(), crate::Error> {
+        if self.get(name).is_some() {
+            return Ok(());
+        }
+
+        let plugin = read_plugin(File::open(data_dir.join(name))?)?;
+        let masters = plugin.masters().into_iter().map(String::from).collect::<Vec<_>>();
+
+        for master in masters {
+            self.push_with_masters(data_dir, &master)?;
+        }
+
+        self.push(name, plugin);
+
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&LoadedPlugin> {
+        self.plugins
+            .iter()
+            .find(|plugin| plugin.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.plugins
+            .iter()
+            .position(|plugin| plugin.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Every version of a record, in load order
+    pub fn record_versions(&self, id: &GlobalFormId) -> Vec<(&LoadedPlugin, &Record)> {
+        self.plugins
+            .iter()
+            .filter_map(|plugin| plugin.record(id).map(|record| (plugin, record)))
+            .collect()
+    }
+
+    /// A FormID as the engine sees it at runtime, with the defining plugin's position in the load order as its index
+    pub fn absolute_form_id(&self, id: &GlobalFormId) -> Option<FormId> {
+        let index = self.position(&id.plugin)?;
+
+        (index <= 0xFF).then(|| FormId::from(id.object_id).with_index(index as u8))
+    }
+
+    /// The form a FormID from `absolute_form_id` stands for
+    pub fn global_form_id(&self, id: FormId) -> Option<GlobalFormId> {
+        let plugin = self.plugins.get(id.index() as usize)?;
+
+        Some(GlobalFormId::new(plugin.name.clone(), id.object_id()))
+    }
+
+    /// A copy of a plugin's version of a record with every FormID in `absolute_form_id` terms. Fails if the
+    /// record refers to a plugin missing from the load order
+    pub fn absolute_record(&self, plugin: &LoadedPlugin, record: &Record) -> Result<Record, crate::Error> {
+        let mut unresolved = None;
+        let mut record = record.clone();
+
+        schema::remap_form_ids(&mut record, |id| {
+            let global = plugin.global_form_id(id);
+
+            match self.absolute_form_id(&global) {
+                Some(absolute) => absolute,
+                None => {
+                    unresolved = Some(global);
+                    id
+                }
+            }
+        })?;
+
+        match unresolved {
+            Some(global) => Err(crate::Error::CorruptOrInvalidRecord(format!(
+                "{} {} in {} refers to {}, which isn't in the load order",
+                record.header.code, record.header.id, plugin.name, global
+            ))),
+            None => Ok(record),
+        }
+    }
+
+    /// The version of a record the engine ends up using
+    pub fn winning_record(&self, id: &GlobalFormId) -> Option<(&LoadedPlugin, &Record)> {
+        self.record_versions(id).pop()
+    }
+}
@@ -0,0 +1,442 @@
+use std::{env, fs, fs::File, path::Path, process};
+
+use tes_parse::{
+    assets::{self, DataFiles},
+    bsa::Archive,
+    dialogue::Dialogue,
+    diff,
+    heightmap::Heightmap,
+    load_order::LoadOrder,
+    navmesh, patch,
+    pex::Pex,
+    query::Filter,
+    read_plugin, scripts, seq,
+    spatial::SpatialIndex,
+    writer, Error,
+};
+
+const USAGE: &str = "Usage: tes-parse <command> [args]
+
+Commands:
+    diff <old> <new> [--json]                      Compare two versions of a plugin
+    query <plugin> <query> [--data=<dir>] [--json]  List records matching a query, e.g.
+                                                    \"WEAP where damage > 20 and keyword WeapTypeSword\".
+                                                    Keywords are looked up in masters read from <dir>
+    heightmap <plugin> <worldspace> <output>        Export a worldspace's terrain as a 16-bit grayscale image,
+                                                    PNG unless <output> ends in .raw
+    navmesh <plugin> [--data=<dir>] [--obj=<file>]  Report broken navmesh edge links, optionally exporting
+                                                    the plugin's navmeshes to OBJ
+    dialogue <plugin> [--json]                      Print the plugin's dialogue tree as a Graphviz graph,
+                                                    or as JSON
+    scripts <plugin> [--data=<dir>]                 Report script object properties pointing at missing forms,
+                                                    and with <dir>, scripts and properties missing from the
+                                                    compiled scripts in <dir>/Scripts
+    pex <script>                                    Disassemble a compiled Papyrus script
+    bsa <archive>                                   List the files in a BSA archive
+    models <plugin> --data=<dir>                    Report models, and textures of those models, missing from
+                                                    <dir> and its archives
+    assets <plugin> [--data=<dir>] [--missing]      List the files the plugin depends on, marking those not in
+           [--language=<name>]                      <dir> or its archives, or listing only those with
+                                                    --missing. Masters read from <dir> supply the quests and
+                                                    voice types of voice files. String tables default to
+                                                    english
+    seq <plugin> <output>                           Write the plugin's SEQ file of start-game-enabled quests
+    patch <output> <plugin>... --data=<dir>         Write a patch combining the edits the plugins, loaded in the
+                                                    order given along with their masters from <dir>, make to
+                                                    the same records";
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+
+    let result = match args.first().map(String::as_str) {
+        Some("diff") => diff_command(&args[1..]),
+        Some("query") => query_command(&args[1..]),
+        Some("heightmap") => heightmap_command(&args[1..]),
+        Some("navmesh") => navmesh_command(&args[1..]),
+        Some("dialogue") => dialogue_command(&args[1..]),
+        Some("scripts") => scripts_command(&args[1..]),
+        Some("pex") => pex_command(&args[1..]),
+        Some("bsa") => bsa_command(&args[1..]),
+        Some("models") => models_command(&args[1..]),
+        Some("assets") => assets_command(&args[1..]),
+        Some("seq") => seq_command(&args[1..]),
+        Some("patch") => patch_command(&args[1..]),
+        _ => usage(),
+    };
+
+    if let Err(err) = result {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+fn diff_command(args: &[String]) -> Result<(), Error> {
+    let json = args.iter().any(|arg| arg == "--json");
+    let paths = args.iter().filter(|arg| !arg.starts_with("--")).collect::<Vec<_>>();
+
+    if paths.len() != 2 {
+        usage();
+    }
+
+    let old = read_plugin(File::open(paths[0])?)?;
+    let new = read_plugin(File::open(paths[1])?)?;
+    let diff = diff::diff(&old, &new);
+
+    if json {
+        println!("{}", diff.to_json());
+    } else {
+        print!("{}", diff);
+    }
+
+    Ok(())
+}
+
+/// The plugin at `path`, along with its masters from `data_dir` when given
+fn load_order(path: &str, data_dir: Option<&str>) -> Result<(LoadOrder, String), Error> {
+    let name = Path::new(path)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| Error::CorruptOrInvalidFile(String::from(path)))?;
+
+    let load_order = match data_dir {
+        Some(data_dir) => LoadOrder::load_with_masters(data_dir, name)?,
+        None => {
+            let mut load_order = LoadOrder::new();
+            load_order.push(name, read_plugin(File::open(path)?)?);
+            load_order
+        }
+    };
+
+    Ok((load_order, String::from(name)))
+}
+
+fn query_command(args: &[String]) -> Result<(), Error> {
+    let json = args.iter().any(|arg| arg == "--json");
+    let data_dir = args.iter().find_map(|arg| arg.strip_prefix("--data="));
+    let positional = args.iter().filter(|arg| !arg.starts_with("--")).collect::<Vec<_>>();
+
+    if positional.len() != 2 {
+        usage();
+    }
+
+    let (load_order, name) = load_order(positional[0], data_dir)?;
+    let plugin = load_order.get(&name).ok_or(Error::Unexpected)?;
+    let records = positional[1].parse::<Filter>()?.select(plugin, &load_order)?;
+
+    if json {
+        let records = records
+            .iter()
+            .map(|record| {
+                format!(
+                    "{{\"type\":\"{}\",\"form_id\":\"{}\",\"editor_id\":{}}}",
+                    record.header.code,
+                    record.header.id,
+                    record
+                        .header
+                        .editor_id
+                        .as_ref()
+                        .map_or_else(|| String::from("null"), |editor_id| format!("{:?}", editor_id))
+                )
+            })
+            .collect::<Vec<_>>();
+
+        println!("[{}]", records.join(","));
+    } else {
+        for record in records {
+            match &record.header.editor_id {
+                Some(editor_id) => println!("{} {} \"{}\"", record.header.code, record.header.id, editor_id),
+                None => println!("{} {}", record.header.code, record.header.id),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn heightmap_command(args: &[String]) -> Result<(), Error> {
+    if args.len() != 3 {
+        usage();
+    }
+
+    let plugin = read_plugin(File::open(&args[0])?)?;
+    let index = SpatialIndex::build(&plugin);
+    let world = index
+        .world_by_editor_id(&args[1])
+        .ok_or_else(|| Error::CorruptOrInvalidFile(format!("no worldspace {} in {}", args[1], args[0])))?;
+    let heightmap = Heightmap::build(&plugin, world)?
+        .ok_or_else(|| Error::CorruptOrInvalidFile(format!("{} has no landscape", args[1])))?;
+
+    let bytes = if args[2].to_ascii_lowercase().ends_with(".raw") {
+        heightmap.to_raw16()
+    } else {
+        heightmap.to_png()?
+    };
+
+    fs::write(&args[2], bytes)?;
+
+    println!(
+        "{}x{} from cell {}, heights {} to {}",
+        heightmap.width, heightmap.height, heightmap.origin, heightmap.min_height, heightmap.max_height
+    );
+
+    Ok(())
+}
+
+fn navmesh_command(args: &[String]) -> Result<(), Error> {
+    let data_dir = args.iter().find_map(|arg| arg.strip_prefix("--data="));
+    let obj = args.iter().find_map(|arg| arg.strip_prefix("--obj="));
+    let positional = args.iter().filter(|arg| !arg.starts_with("--")).collect::<Vec<_>>();
+
+    if positional.len() != 1 {
+        usage();
+    }
+
+    let (load_order, name) = load_order(positional[0], data_dir)?;
+    let plugin = load_order.get(&name).ok_or(Error::Unexpected)?;
+
+    for broken in navmesh::validate_edge_links(plugin, &load_order)? {
+        match &broken.target {
+            Some(target) => println!(
+                "{} triangle {} edge {} -> {}: {:?}",
+                broken.navmesh, broken.triangle, broken.edge, target, broken.issue
+            ),
+            None => println!(
+                "{} triangle {} edge {}: {:?}",
+                broken.navmesh, broken.triangle, broken.edge, broken.issue
+            ),
+        }
+    }
+
+    if let Some(obj) = obj {
+        let navmeshes = plugin
+            .plugin
+            .records()
+            .into_iter()
+            .filter(|record| &*record.header.code == b"NAVM")
+            .map(navmesh::Navmesh::decode)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        fs::write(obj, navmesh::to_obj(&navmeshes.iter().collect::<Vec<_>>()))?;
+    }
+
+    Ok(())
+}
+
+fn dialogue_command(args: &[String]) -> Result<(), Error> {
+    let json = args.iter().any(|arg| arg == "--json");
+    let positional = args.iter().filter(|arg| !arg.starts_with("--")).collect::<Vec<_>>();
+
+    if positional.len() != 1 {
+        usage();
+    }
+
+    let dialogue = Dialogue::build(&read_plugin(File::open(positional[0])?)?)?;
+
+    if json {
+        println!("{}", dialogue.to_json());
+    } else {
+        print!("{}", dialogue.to_dot());
+    }
+
+    Ok(())
+}
+
+fn scripts_command(args: &[String]) -> Result<(), Error> {
+    let data_dir = args.iter().find_map(|arg| arg.strip_prefix("--data="));
+    let positional = args.iter().filter(|arg| !arg.starts_with("--")).collect::<Vec<_>>();
+
+    if positional.len() != 1 {
+        usage();
+    }
+
+    let (load_order, name) = load_order(positional[0], data_dir)?;
+    let plugin = load_order.get(&name).ok_or(Error::Unexpected)?;
+
+    for unresolved in scripts::unresolved_properties(plugin, &load_order)? {
+        match unresolved.alias {
+            Some(alias) => println!(
+                "{} alias {} {}.{} -> {}",
+                unresolved.record, alias, unresolved.script, unresolved.property, unresolved.target
+            ),
+            None => println!(
+                "{} {}.{} -> {}",
+                unresolved.record, unresolved.script, unresolved.property, unresolved.target
+            ),
+        }
+    }
+
+    if let Some(data_dir) = data_dir {
+        for problem in scripts::check_compiled_scripts(plugin, &Path::new(data_dir).join("Scripts"))? {
+            let alias = problem
+                .alias
+                .map(|alias| format!(" alias {}", alias))
+                .unwrap_or_default();
+
+            match &problem.issue {
+                scripts::ScriptIssue::MissingScript => {
+                    println!("{}{} {}: script not found", problem.record, alias, problem.script)
+                }
+                scripts::ScriptIssue::MissingProperty(property) => println!(
+                    "{}{} {}.{}: property not declared",
+                    problem.record, alias, problem.script, property
+                ),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn pex_command(args: &[String]) -> Result<(), Error> {
+    if args.len() != 1 {
+        usage();
+    }
+
+    print!("{}", Pex::parse(&fs::read(&args[0])?)?.disassemble());
+
+    Ok(())
+}
+
+fn bsa_command(args: &[String]) -> Result<(), Error> {
+    if args.len() != 1 {
+        usage();
+    }
+
+    for file in Archive::open(&args[0])?.files {
+        println!("{}", file.path);
+    }
+
+    Ok(())
+}
+
+fn models_command(args: &[String]) -> Result<(), Error> {
+    let data_dir = args.iter().find_map(|arg| arg.strip_prefix("--data="));
+    let positional = args.iter().filter(|arg| !arg.starts_with("--")).collect::<Vec<_>>();
+
+    let data_dir = match (positional.len(), data_dir) {
+        (1, Some(data_dir)) => data_dir,
+        _ => usage(),
+    };
+
+    let plugin = read_plugin(File::open(positional[0])?)?;
+    let files = DataFiles::open(data_dir)?;
+
+    for check in assets::check_models(&plugin, &files) {
+        if !check.found {
+            println!("{} {}: missing {}", check.record, check.subrecord, check.path);
+        }
+
+        for texture in &check.missing_textures {
+            println!(
+                "{} {}: {} missing texture {}",
+                check.record, check.subrecord, check.path, texture
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn assets_command(args: &[String]) -> Result<(), Error> {
+    let data_dir = args.iter().find_map(|arg| arg.strip_prefix("--data="));
+    let language = args
+        .iter()
+        .find_map(|arg| arg.strip_prefix("--language="))
+        .unwrap_or("english");
+    let missing_only = args.iter().any(|arg| arg == "--missing");
+    let positional = args.iter().filter(|arg| !arg.starts_with("--")).collect::<Vec<_>>();
+
+    if positional.len() != 1 || (missing_only && data_dir.is_none()) {
+        usage();
+    }
+
+    let (load_order, name) = load_order(positional[0], data_dir)?;
+    let plugin = load_order.get(&name).ok_or(Error::Unexpected)?;
+    let files = data_dir.map(DataFiles::open).transpose()?;
+
+    for dependency in assets::dependencies(plugin, &load_order, files.as_ref(), language)? {
+        let present = files.as_ref().map(|files| dependency.is_present(files));
+
+        if missing_only && present != Some(false) {
+            continue;
+        }
+
+        let records = dependency
+            .records
+            .iter()
+            .map(|record| record.to_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let status = match present {
+            Some(false) if !missing_only => "missing ",
+            _ => "",
+        };
+
+        println!("{}{} {} {}", status, dependency.kind, dependency.path, records);
+    }
+
+    Ok(())
+}
+
+fn seq_command(args: &[String]) -> Result<(), Error> {
+    if args.len() != 2 {
+        usage();
+    }
+
+    let plugin = read_plugin(File::open(&args[0])?)?;
+
+    match seq::generate(&plugin)? {
+        Some(bytes) => {
+            for quest in seq::start_game_quests(&plugin)? {
+                println!("{}", quest);
+            }
+
+            fs::write(&args[1], bytes)?;
+        }
+        None => println!("no start-game-enabled quests, so no SEQ file is needed"),
+    }
+
+    Ok(())
+}
+
+fn patch_command(args: &[String]) -> Result<(), Error> {
+    let data_dir = args.iter().find_map(|arg| arg.strip_prefix("--data="));
+    let positional = args.iter().filter(|arg| !arg.starts_with("--")).collect::<Vec<_>>();
+
+    let data_dir = match (positional.len(), data_dir) {
+        (2.., Some(data_dir)) => Path::new(data_dir),
+        _ => usage(),
+    };
+
+    let output = Path::new(positional[0]);
+    let name = output
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| Error::CorruptOrInvalidFile(output.display().to_string()))?;
+
+    let mut load_order = LoadOrder::new();
+
+    for plugin in &positional[1..] {
+        load_order.push_with_masters(data_dir, plugin)?;
+    }
+
+    let patch = patch::build(&load_order, name)?;
+
+    for form in &patch.patched {
+        println!("patched {}", form);
+    }
+
+    for (form, reason) in &patch.skipped {
+        println!("skipped {}: {}", form, reason);
+    }
+
+    writer::write_plugin(&patch.plugin.plugin, File::create(output)?)?;
+
+    Ok(())
+}
@@ -0,0 +1,184 @@
+use crate::{
+    merge::remap_group,
+    parsers::{common::FormId, plugin::Plugin, records::file_header::MasterFile},
+    schema,
+};
+
+const MAX_MASTERS: usize = 0xFE;
+
+/// Append a master to the plugin's master list. The plugin's own records move up one load order index
+pub fn add_master(plugin: &mut Plugin, name: &str) -> Result<(), crate::Error> {
+    if position(plugin, name).is_some() {
+        return Err(crate::Error::InvalidMasters(format!("{} is already a master", name)));
+    }
+
+    let mut masters = plugin.file_header().masters.clone();
+    masters.push(MasterFile {
+        name: String::from(name),
+        tag: 0,
+    });
+
+    set_masters(plugin, masters)
+}
+
+/// Remove a master from the plugin's master list, refusing while any record still references it
+pub fn remove_master(plugin: &mut Plugin, name: &str) -> Result<(), crate::Error> {
+    let index =
+        position(plugin, name).ok_or_else(|| crate::Error::InvalidMasters(format!("{} is not a master", name)))?;
+    let references = master_references(plugin, name)?;
+
+    if !references.is_empty() {
+        return Err(crate::Error::MasterInUse(String::from(name), references.len()));
+    }
+
+    let mut masters = plugin.file_header().masters.clone();
+    masters.remove(index);
+
+    set_masters(plugin, masters)
+}
+
+/// Reorder the master list. `order` must name every master exactly once
+pub fn reorder_masters<S>(plugin: &mut Plugin, order: &[S]) -> Result<(), crate::Error>
+where
+    S: AsRef<str>,
+{
+    let current = &plugin.file_header().masters;
+
+    if order.len() != current.len() {
+        return Err(crate::Error::InvalidMasters(format!(
+            "expected {} masters, got {}",
+            current.len(),
+            order.len()
+        )));
+    }
+
+    let mut masters = vec![];
+
+    for name in order {
+        let master = current
+            .iter()
+            .find(|master| master.name.eq_ignore_ascii_case(name.as_ref()))
+            .ok_or_else(|| crate::Error::InvalidMasters(format!("{} is not a master", name.as_ref())))?;
+
+        if masters
+            .iter()
+            .any(|existing: &MasterFile| existing.name.eq_ignore_ascii_case(&master.name))
+        {
+            return Err(crate::Error::InvalidMasters(format!("{} is listed twice", master.name)));
+        }
+
+        masters.push(master.clone());
+    }
+
+    set_masters(plugin, masters)
+}
+
+/// Sort the master list to match a load order. Masters missing from the load order keep their relative order
+/// after those that are in it
+pub fn sort_masters<S>(plugin: &mut Plugin, load_order: &[S]) -> Result<(), crate::Error>
+where
+    S: AsRef<str>,
+{
+    let mut masters = plugin.file_header().masters.clone();
+    masters.sort_by_key(|master| {
+        load_order
+            .iter()
+            .position(|name| name.as_ref().eq_ignore_ascii_case(&master.name))
+            .unwrap_or(load_order.len())
+    });
+
+    set_masters(plugin, masters)
+}
+
+/// FormIDs of records that override a record from the master or reference one of its forms. Fails if any record
+/// holds subrecords whose FormIDs can't be located, as references there would go unseen
+pub fn master_references(plugin: &Plugin, name: &str) -> Result<Vec<FormId>, crate::Error> {
+    let index = match position(plugin, name) {
+        Some(index) => index as u8,
+        None => return Ok(vec![]),
+    };
+
+    schema::check_mapped(plugin.records())?;
+
+    let mut references = plugin
+        .records()
+        .into_iter()
+        .filter(|record| {
+            record.header.id.index() == index
+                || schema::referenced_form_ids(record).iter().any(|id| id.index() == index)
+        })
+        .map(|record| record.header.id)
+        .collect::<Vec<_>>();
+
+    references.sort();
+    Ok(references)
+}
+
+fn position(plugin: &Plugin, name: &str) -> Option<usize> {
+    plugin
+        .masters()
+        .iter()
+        .position(|master| master.eq_ignore_ascii_case(name))
+}
+
+/// Replace the master list, rewriting the load order index of every FormID to match it. Fails, leaving the
+/// plugin untouched, if a master being dropped is still referenced or a record holds subrecords whose FormIDs
+/// can't be located
+fn set_masters(plugin: &mut Plugin, masters: Vec<MasterFile>) -> Result<(), crate::Error> {
+    if masters.len() > MAX_MASTERS {
+        return Err(crate::Error::InvalidMasters(format!("{} masters", masters.len())));
+    }
+
+    schema::check_mapped(plugin.records())?;
+
+    for old in &plugin.file_header().masters {
+        if !masters.iter().any(|new| new.name.eq_ignore_ascii_case(&old.name)) {
+            let references = master_references(plugin, &old.name)?;
+
+            if !references.is_empty() {
+                return Err(crate::Error::MasterInUse(old.name.clone(), references.len()));
+            }
+        }
+    }
+
+    let old_masters = plugin.file_header().masters.clone();
+
+    let mapping = old_masters
+        .iter()
+        .map(|old| {
+            masters
+                .iter()
+                .position(|new| new.name.eq_ignore_ascii_case(&old.name))
+                .map(|index| index as u8)
+        })
+        .collect::<Vec<_>>();
+
+    let own_index = masters.len() as u8;
+    let mut dropped = None;
+
+    let mut remap = |id: FormId| match mapping.get(id.index() as usize) {
+        Some(Some(index)) => id.with_index(*index),
+        Some(None) => {
+            dropped = Some(id);
+            id
+        }
+        None => id.with_index(own_index),
+    };
+
+    for group in plugin.groups.values_mut() {
+        remap_group(group, &mut remap)?;
+    }
+
+    if let Some(id) = dropped {
+        return Err(crate::Error::InvalidMasters(format!(
+            "{} refers to a master being removed",
+            id
+        )));
+    }
+
+    // ONAM may list references that have since been removed, so it's rebuilt rather than remapped
+    plugin.file_header_mut().masters = masters;
+    plugin.update_overrides();
+
+    Ok(())
+}
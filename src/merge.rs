@@ -0,0 +1,262 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::{
+    load_order::{GlobalFormId, LoadedPlugin},
+    parsers::{
+        common::FormId,
+        group::{Group, GroupChild, GroupData, Label},
+        plugin::Plugin,
+        records::file_header::MasterFile,
+    },
+    schema,
+};
+
+/// Lowest object ID available to plugins, everything below is reserved by the engine
+pub const FIRST_OBJECT_ID: u32 = 0x800;
+const MAX_OBJECT_ID: u32 = 0x00FF_FFFF;
+const MAX_MASTERS: usize = 0xFE;
+
+#[derive(Debug)]
+pub struct Merge {
+    pub plugin: LoadedPlugin,
+    /// New records that collided with another merged plugin's records and were given a new FormID
+    pub renumbered: BTreeMap<GlobalFormId, GlobalFormId>,
+}
+
+/// Combine several plugins into a single plugin named `name`.
+///
+/// New records keep their object ID unless it collides with a record already taken from an earlier plugin, in
+/// which case they are renumbered from `Hedr::next_id`. References between the merged plugins are redirected
+/// to the merged plugin, and where several of them override the same record the last one wins. FormID
+/// references are only rewritten in subrecords known to `schema`.
+pub fn merge(plugins: &[&LoadedPlugin], name: &str) -> Result<Merge, crate::Error> {
+    let first = plugins
+        .first()
+        .ok_or_else(|| crate::Error::CorruptOrInvalidFile(String::from("nothing to merge")))?;
+
+    let merged_names = plugins
+        .iter()
+        .map(|plugin| plugin.name.to_ascii_lowercase())
+        .collect::<HashSet<_>>();
+    let is_merged = |plugin: &str| merged_names.contains(&plugin.to_ascii_lowercase());
+
+    let mut masters: Vec<MasterFile> = vec![];
+
+    for plugin in plugins {
+        for master in &plugin.plugin.file_header().masters {
+            let known = masters
+                .iter()
+                .any(|existing| existing.name.eq_ignore_ascii_case(&master.name));
+
+            if !known && !is_merged(&master.name) {
+                masters.push(master.clone());
+            }
+        }
+    }
+
+    if masters.len() > MAX_MASTERS {
+        return Err(crate::Error::CorruptOrInvalidFile(format!(
+            "merged plugin would have {} masters",
+            masters.len()
+        )));
+    }
+
+    for plugin in plugins {
+        schema::check_mapped(plugin.plugin.records())?;
+    }
+
+    let (object_ids, renumbered) = allocate_object_ids(plugins, name)?;
+    let next_id = object_ids.values().copied().max().map_or(FIRST_OBJECT_ID, |id| id + 1);
+
+    let mut groups: HashMap<_, Group> = HashMap::new();
+
+    for plugin in plugins {
+        let mut remap = |id: FormId| {
+            let global_id = plugin.global_form_id(id);
+
+            if is_merged(&global_id.plugin) {
+                let object_id = object_ids.get(&global_id).copied().unwrap_or(global_id.object_id);
+                FormId::from(object_id).with_index(masters.len() as u8)
+            } else {
+                let index = masters
+                    .iter()
+                    .position(|master| master.name.eq_ignore_ascii_case(&global_id.plugin))
+                    .unwrap_or(masters.len());
+                FormId::from(global_id.object_id).with_index(index as u8)
+            }
+        };
+
+        for (code, group) in &plugin.plugin.groups {
+            let mut group = group.clone();
+            remap_group(&mut group, &mut remap)?;
+
+            match groups.get_mut(code) {
+                Some(existing) => merge_group(existing, group),
+                None => {
+                    groups.insert(code.clone(), group);
+                }
+            }
+        }
+    }
+
+    let mut plugin = Plugin {
+        tes4: first.plugin.tes4.clone(),
+        groups,
+    };
+
+    {
+        let header = plugin.file_header_mut();
+        header.masters = masters;
+        header.hedr.next_id = next_id.into();
+        header.hedr.version = plugins
+            .iter()
+            .map(|plugin| plugin.plugin.file_header().hedr.version)
+            .fold(header.hedr.version, f32::max);
+    }
+
+    plugin.update_num_records();
+    plugin.update_overrides();
+
+    let renumbered = renumbered
+        .into_iter()
+        .map(|(source, object_id)| (source, GlobalFormId::new(name, object_id)))
+        .collect();
+
+    Ok(Merge {
+        plugin: LoadedPlugin {
+            name: String::from(name),
+            plugin,
+        },
+        renumbered,
+    })
+}
+
+/// Object IDs of every new record in the merged plugins, along with those that had to change
+#[allow(clippy::type_complexity)]
+fn allocate_object_ids(
+    plugins: &[&LoadedPlugin],
+    name: &str,
+) -> Result<(HashMap<GlobalFormId, u32>, BTreeMap<GlobalFormId, u32>), crate::Error> {
+    let mut object_ids = HashMap::new();
+    let mut used = HashSet::new();
+    let mut collisions = vec![];
+
+    for plugin in plugins {
+        let mut records = plugin.plugin.records();
+        records.sort_by_key(|record| record.header.id);
+
+        for record in records {
+            if !plugin.is_new_record(record.header.id) {
+                continue;
+            }
+
+            let global_id = plugin.global_form_id(record.header.id);
+            let object_id = global_id.object_id;
+
+            if used.insert(object_id) {
+                object_ids.insert(global_id, object_id);
+            } else {
+                collisions.push(global_id);
+            }
+        }
+    }
+
+    let next = used
+        .iter()
+        .copied()
+        .max()
+        .map_or(FIRST_OBJECT_ID, |id| id + 1)
+        .max(FIRST_OBJECT_ID);
+    let mut renumbered = BTreeMap::new();
+
+    for (global_id, object_id) in collisions.into_iter().zip(next..) {
+        if object_id > MAX_OBJECT_ID {
+            return Err(crate::Error::CorruptOrInvalidFile(format!(
+                "no FormIDs left in {} for {}",
+                name, global_id
+            )));
+        }
+
+        object_ids.insert(global_id.clone(), object_id);
+        renumbered.insert(global_id, object_id);
+    }
+
+    Ok((object_ids, renumbered))
+}
+
+/// Rewrite every FormID in a group, its label included. Fails part way through if a record holds subrecords of
+/// unknown layout, so check the records with `schema::check_mapped` first
+pub(crate) fn remap_group<F>(group: &mut Group, remap: &mut F) -> Result<(), crate::Error>
+where
+    F: FnMut(FormId) -> FormId,
+{
+    group.label = match &group.label {
+        Label::ParentCell(id) => Label::ParentCell(remap(*id)),
+        Label::ParentDialog(id) => Label::ParentDialog(remap(*id)),
+        Label::ParentWorld(id) => Label::ParentWorld(remap(*id)),
+        label => label.clone(),
+    };
+
+    match &mut group.data {
+        GroupData::Records(records) => {
+            *records = records
+                .drain()
+                .map(|(_, mut record)| {
+                    schema::remap_form_ids(&mut record, &mut *remap)?;
+                    Ok((record.header.id, record))
+                })
+                .collect::<Result<_, crate::Error>>()?;
+        }
+        GroupData::Children(children) => {
+            for child in children {
+                match child {
+                    GroupChild::Record(record) => schema::remap_form_ids(record, &mut *remap)?,
+                    GroupChild::Group(group) => remap_group(group, remap)?,
+                }
+            }
+        }
+        GroupData::Unimplemented(_) => (),
+    }
+
+    Ok(())
+}
+
+/// Merge `source` into `target`, with records from `source` replacing those of the same FormID
+pub(crate) fn merge_group(target: &mut Group, source: Group) {
+    match (&mut target.data, source.data) {
+        (GroupData::Records(target), GroupData::Records(source)) => target.extend(source),
+        (GroupData::Children(target), GroupData::Children(source)) => {
+            for child in source {
+                match child {
+                    GroupChild::Record(record) => {
+                        let existing = target.iter_mut().find_map(|child| match child {
+                            GroupChild::Record(existing) if existing.header.id == record.header.id => Some(existing),
+                            _ => None,
+                        });
+
+                        match existing {
+                            Some(existing) => *existing = record,
+                            None => target.push(GroupChild::Record(record)),
+                        }
+                    }
+                    GroupChild::Group(group) => {
+                        let existing = target.iter_mut().find_map(|child| match child {
+                            GroupChild::Group(existing)
+                                if existing.group_type == group.group_type && existing.label == group.label =>
+                            {
+                                Some(existing)
+                            }
+                            _ => None,
+                        });
+
+                        match existing {
+                            Some(existing) => merge_group(existing, group),
+                            None => target.push(GroupChild::Group(group)),
+                        }
+                    }
+                }
+            }
+        }
+        (target, data) => *target = data,
+    }
+}
@@ -0,0 +1,151 @@
+use std::{collections::HashMap, convert::TryFrom, fmt::Write};
+
+use crate::load_order::{GlobalFormId, LoadOrder, LoadedPlugin};
+
+pub use crate::parsers::records::navmesh::{
+    DoorLink, EdgeLink, Island, Navmesh, NavmeshInfo, NavmeshInfoMap, NavmeshParent, Triangle, EDGE_LINK_FLAGS,
+};
+
+/// Wavefront OBJ with one object per navmesh, named after its FormID
+pub fn to_obj(navmeshes: &[&Navmesh]) -> String {
+    let mut obj = String::new();
+    let mut offset = 1;
+
+    for navmesh in navmeshes {
+        writeln!(obj, "o {:08X}", *navmesh.id).unwrap();
+
+        for [x, y, z] in &navmesh.vertices {
+            writeln!(obj, "v {} {} {}", x, y, z).unwrap();
+        }
+
+        for triangle in &navmesh.triangles {
+            let [a, b, c] = triangle.vertices;
+            writeln!(
+                obj,
+                "f {} {} {}",
+                a as usize + offset,
+                b as usize + offset,
+                c as usize + offset
+            )
+            .unwrap();
+        }
+
+        offset += navmesh.vertices.len();
+    }
+
+    obj
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EdgeLinkIssue {
+    /// The triangle's edge points past the end of the navmesh's edge links
+    MissingEdgeLink(usize),
+    /// No navmesh with the linked FormID in the load order
+    MissingNavmesh,
+    /// The linked navmesh has fewer triangles than the link expects
+    TriangleOutOfRange(i16),
+    /// The linked triangle has no edge link back to this one
+    NotReciprocated,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BrokenEdgeLink {
+    pub navmesh: GlobalFormId,
+    pub triangle: usize,
+    /// 0 to 2, for edges 0-1, 1-2 and 2-0
+    pub edge: usize,
+    pub target: Option<GlobalFormId>,
+    pub issue: EdgeLinkIssue,
+}
+
+/// Check every edge link of the plugin's navmeshes against the winning version of the navmesh it leads to.
+/// Navmeshes that fail to decode are reported as errors rather than skipped
+pub fn validate_edge_links(plugin: &LoadedPlugin, load_order: &LoadOrder) -> Result<Vec<BrokenEdgeLink>, crate::Error> {
+    let mut targets: HashMap<GlobalFormId, Option<(&LoadedPlugin, Navmesh)>> = HashMap::new();
+    let mut broken = vec![];
+
+    let mut records = plugin
+        .plugin
+        .records()
+        .into_iter()
+        .filter(|record| &*record.header.code == b"NAVM")
+        .collect::<Vec<_>>();
+    records.sort_by_key(|record| record.header.id);
+
+    for record in records {
+        let navmesh = Navmesh::decode(record)?;
+        let source = plugin.global_form_id(navmesh.id);
+
+        for (index, triangle) in navmesh.triangles.iter().enumerate() {
+            for edge in 0..3 {
+                let link = match triangle.edge_link(edge) {
+                    Some(link) => link,
+                    None => continue,
+                };
+
+                let mut report = |target: Option<GlobalFormId>, issue| {
+                    broken.push(BrokenEdgeLink {
+                        navmesh: source.clone(),
+                        triangle: index,
+                        edge,
+                        target,
+                        issue,
+                    })
+                };
+
+                let link = match navmesh.edge_links.get(link) {
+                    Some(link) => link,
+                    None => {
+                        report(None, EdgeLinkIssue::MissingEdgeLink(link));
+                        continue;
+                    }
+                };
+
+                let target_id = plugin.global_form_id(link.navmesh);
+
+                if !targets.contains_key(&target_id) {
+                    let target = match load_order.winning_record(&target_id) {
+                        Some((owner, record)) => Some((owner, Navmesh::decode(record)?)),
+                        None => plugin
+                            .record(&target_id)
+                            .map(Navmesh::decode)
+                            .transpose()?
+                            .map(|navmesh| (plugin, navmesh)),
+                    };
+
+                    targets.insert(target_id.clone(), target);
+                }
+
+                let (owner, target) = match &targets[&target_id] {
+                    Some(target) => target,
+                    None => {
+                        report(Some(target_id), EdgeLinkIssue::MissingNavmesh);
+                        continue;
+                    }
+                };
+
+                let target_triangle = match usize::try_from(link.triangle)
+                    .ok()
+                    .and_then(|triangle| target.triangles.get(triangle))
+                {
+                    Some(triangle) => triangle,
+                    None => {
+                        report(Some(target_id), EdgeLinkIssue::TriangleOutOfRange(link.triangle));
+                        continue;
+                    }
+                };
+
+                let reciprocated = (0..3)
+                    .filter_map(|edge| target_triangle.edge_link(edge))
+                    .filter_map(|back| target.edge_links.get(back))
+                    .any(|back| owner.global_form_id(back.navmesh) == source && back.triangle as usize == index);
+
+                if !reciprocated {
+                    report(Some(target_id), EdgeLinkIssue::NotReciprocated);
+                }
+            }
+        }
+    }
+
+    Ok(broken)
+}
@@ -0,0 +1,149 @@
+//! Enough of the NIF format to list a mesh's blocks and the textures its shaders use. Only the 20.2.0.7 files
+//! Skyrim uses are read
+
+use crate::parsers::common::decode_string;
+
+use nom::{
+    bytes::complete::{tag, take, take_until},
+    combinator::map,
+    multi::{count, length_count, length_data},
+    number::complete::{le_u16, le_u32, le_u8},
+    sequence::{terminated, tuple},
+};
+
+pub const VERSION: u32 = 0x1402_0007;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NifHeader {
+    pub version: u32,
+    pub user_version: u32,
+    /// The Bethesda stream version: 83 for Skyrim, 100 for Special Edition
+    pub bs_version: u32,
+    pub author: String,
+    pub block_types: Vec<String>,
+    /// Index into `block_types` of each block
+    pub block_type_indices: Vec<u16>,
+    pub block_sizes: Vec<u32>,
+    pub strings: Vec<String>,
+}
+
+impl NifHeader {
+    /// The type name of the block at `index`
+    pub fn block_type(&self, index: usize) -> Option<&str> {
+        let type_index = *self.block_type_indices.get(index)? as usize;
+        self.block_types.get(type_index).map(String::as_str)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Nif {
+    pub header: NifHeader,
+    /// The textures of each BSShaderTextureSet block, in block order. Unused slots are empty strings
+    pub texture_sets: Vec<Vec<String>>,
+}
+
+impl Nif {
+    pub fn parse(bytes: &[u8]) -> Result<Self, crate::Error> {
+        let (blocks, header) = header(bytes)?;
+
+        let mut texture_sets = vec![];
+        let mut offset = 0usize;
+
+        for (index, size) in header.block_sizes.iter().enumerate() {
+            let size = *size as usize;
+            let block = blocks.get(offset..offset + size).ok_or_else(|| {
+                crate::Error::CorruptOrInvalidFile(format!("NIF block {} runs past the end of the file", index))
+            })?;
+
+            if header.block_type(index) == Some("BSShaderTextureSet") {
+                texture_sets.push(length_count(le_u32, sized_string)(block)?.1);
+            }
+
+            offset += size;
+        }
+
+        Ok(Nif { header, texture_sets })
+    }
+
+    /// Every texture the mesh's texture sets use, once each, in the lowercase backslash-separated form archives
+    /// use and relative to the data directory
+    pub fn textures(&self) -> Vec<String> {
+        let mut textures: Vec<String> = vec![];
+
+        for texture in self.texture_sets.iter().flatten() {
+            if texture.is_empty() {
+                continue;
+            }
+
+            let mut path = crate::bsa::normalize_path(texture);
+
+            if let Some(position) = path.find("textures\\") {
+                path = path[position..].to_string();
+            } else {
+                path = format!("textures\\{}", path.trim_start_matches('\\'));
+            }
+
+            if !textures.contains(&path) {
+                textures.push(path);
+            }
+        }
+
+        textures
+    }
+}
+
+/// A string preceded by a u32 length
+fn sized_string(bytes: &[u8]) -> crate::IResult<&[u8], String> {
+    map(length_data(le_u32), decode_string)(bytes)
+}
+
+/// A string preceded by a u8 length that counts its null terminator
+fn short_string(bytes: &[u8]) -> crate::IResult<&[u8], String> {
+    map(length_data(le_u8), |string: &[u8]| {
+        decode_string(string.strip_suffix(&[0]).unwrap_or(string))
+    })(bytes)
+}
+
+fn header(bytes: &[u8]) -> crate::IResult<&[u8], NifHeader> {
+    let (bytes, _) = terminated(tag("Gamebryo File Format, Version "), take_until("\n"))(bytes)?;
+    let (bytes, _) = take(1usize)(bytes)?;
+    let (bytes, (version, endian, user_version, block_count)) = tuple((le_u32, le_u8, le_u32, le_u32))(bytes)?;
+
+    if version != VERSION || endian != 1 {
+        return Err(nom::Err::Failure(crate::Error::CorruptOrInvalidFile(format!(
+            "unsupported NIF version {:#010X}",
+            version
+        ))));
+    }
+
+    let (bytes, bs_version) = le_u32(bytes)?;
+    let (bytes, (author, _process_script, _export_script)) = tuple((short_string, short_string, short_string))(bytes)?;
+
+    // Fallout 4 and later add a maximum file path
+    let (bytes, _) = if bs_version >= 130 {
+        short_string(bytes)?
+    } else {
+        (bytes, String::new())
+    };
+
+    let (bytes, block_types) = length_count(le_u16, sized_string)(bytes)?;
+    let (bytes, block_type_indices) = count(map(le_u16, |index| index & 0x7FFF), block_count as usize)(bytes)?;
+    let (bytes, block_sizes) = count(le_u32, block_count as usize)(bytes)?;
+    let (bytes, (string_count, _max_string_length)) = tuple((le_u32, le_u32))(bytes)?;
+    let (bytes, strings) = count(sized_string, string_count as usize)(bytes)?;
+    let (bytes, _groups) = length_count(le_u32, le_u32)(bytes)?;
+
+    Ok((
+        bytes,
+        NifHeader {
+            version,
+            user_version,
+            bs_version,
+            author,
+            block_types,
+            block_type_indices,
+            block_sizes,
+            strings,
+        },
+    ))
+}
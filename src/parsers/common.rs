@@ -5,7 +5,7 @@ use std::{
 
 use nom::{
     bytes::complete::{tag, take, take_while},
-    combinator::{flat_map, map},
+    combinator::map,
     multi::many0,
     number::complete::{le_u16, le_u32},
     sequence::{pair, terminated},
@@ -48,9 +48,25 @@ impl ops::Deref for TypeCode {
     }
 }
 
-#[derive(Default, Clone, Copy, Hash, PartialEq, Eq)]
+#[derive(Default, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub struct FormId(u32);
 
+impl FormId {
+    /// Index into the plugin's master list, or the plugin itself when past the end of it
+    pub fn index(&self) -> u8 {
+        (self.0 >> 24) as u8
+    }
+
+    /// The FormID with its load order index stripped
+    pub fn object_id(&self) -> u32 {
+        self.0 & 0x00FF_FFFF
+    }
+
+    pub fn with_index(&self, index: u8) -> Self {
+        Self((index as u32) << 24 | self.object_id())
+    }
+}
+
 impl From<u32> for FormId {
     fn from(id: u32) -> Self {
         Self(id)
@@ -77,25 +93,207 @@ impl fmt::Display for FormId {
     }
 }
 
-pub(super) fn form_id(bytes: &[u8]) -> crate::IResult<&[u8], FormId> {
+/// A string subrecord, which localized plugins replace with an ID into their string tables
+#[derive(Debug, Clone, PartialEq)]
+pub enum LString {
+    Inline(String),
+    Id(u32),
+}
+
+impl fmt::Display for LString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LString::Inline(value) => write!(f, "{}", value),
+            LString::Id(id) => write!(f, "string {:#010X}", id),
+        }
+    }
+}
+
+/// Exterior cells per side of a block
+pub const CELLS_PER_BLOCK: i16 = 32;
+/// Exterior cells per side of a sub-block
+pub const CELLS_PER_SUB_BLOCK: i16 = 8;
+
+/// A position on the exterior cell grid, or the coordinates of a block or sub-block of it. Group labels store
+/// these as Y then X, and they order the same way
+#[derive(Default, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct GridCoord {
+    pub x: i16,
+    pub y: i16,
+}
+
+impl GridCoord {
+    pub fn new(x: i16, y: i16) -> Self {
+        Self { x, y }
+    }
+
+    /// The block holding this cell
+    pub fn block(&self) -> Self {
+        Self::new(self.x.div_euclid(CELLS_PER_BLOCK), self.y.div_euclid(CELLS_PER_BLOCK))
+    }
+
+    /// The sub-block holding this cell
+    pub fn sub_block(&self) -> Self {
+        Self::new(
+            self.x.div_euclid(CELLS_PER_SUB_BLOCK),
+            self.y.div_euclid(CELLS_PER_SUB_BLOCK),
+        )
+    }
+
+    /// First and last cells of this block
+    pub fn block_cells(&self) -> (Self, Self) {
+        Self::span(*self, CELLS_PER_BLOCK)
+    }
+
+    /// First and last cells of this sub-block
+    pub fn sub_block_cells(&self) -> (Self, Self) {
+        Self::span(*self, CELLS_PER_SUB_BLOCK)
+    }
+
+    /// Sub-blocks making up this block
+    pub fn block_sub_blocks(&self) -> (Self, Self) {
+        Self::span(*self, CELLS_PER_BLOCK / CELLS_PER_SUB_BLOCK)
+    }
+
+    fn span(coord: Self, size: i16) -> (Self, Self) {
+        let first = Self::new(coord.x * size, coord.y * size);
+        (first, Self::new(first.x + size - 1, first.y + size - 1))
+    }
+
+    pub(crate) fn from_label(label: [u8; 4]) -> Self {
+        Self::new(
+            i16::from_le_bytes([label[2], label[3]]),
+            i16::from_le_bytes([label[0], label[1]]),
+        )
+    }
+
+    pub(crate) fn to_label(self) -> [u8; 4] {
+        let [a, b] = self.y.to_le_bytes();
+        let [c, d] = self.x.to_le_bytes();
+        [a, b, c, d]
+    }
+}
+
+impl PartialOrd for GridCoord {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for GridCoord {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.y, self.x).cmp(&(other.y, other.x))
+    }
+}
+
+impl fmt::Debug for GridCoord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "({}, {})", self.x, self.y)
+    }
+}
+
+impl fmt::Display for GridCoord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}, {}", self.x, self.y)
+    }
+}
+
+/// Interior cell block and sub-block numbers, taken from the last two decimal digits of the object ID
+pub fn interior_block(id: FormId) -> (i32, i32) {
+    let object_id = id.object_id();
+    ((object_id % 10) as i32, (object_id / 10 % 10) as i32)
+}
+
+pub(crate) fn form_id(bytes: &[u8]) -> crate::IResult<&[u8], FormId> {
     map(le_u32, |id| id.into())(bytes)
 }
 
-pub(super) fn zstring(bytes: &[u8]) -> crate::IResult<&[u8], String> {
-    map(
-        terminated(take_while(|c| c != 0), tag([0u8])),
-        |zstring_bytes: &[u8]| String::from_utf8(zstring_bytes.to_vec()).unwrap(),
-    )(bytes)
+/// What Windows-1252 puts at 0x80 to 0x9F. The five bytes it leaves undefined decode to the matching control
+/// characters, as Windows does, so every byte string round-trips
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '\u{20AC}', '\u{0081}', '\u{201A}', '\u{0192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}', '\u{02C6}',
+    '\u{2030}', '\u{0160}', '\u{2039}', '\u{0152}', '\u{008D}', '\u{017D}', '\u{008F}', '\u{0090}', '\u{2018}',
+    '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}', '\u{02DC}', '\u{2122}', '\u{0161}',
+    '\u{203A}', '\u{0153}', '\u{009D}', '\u{017E}', '\u{0178}',
+];
+
+/// Decode a string from a plugin or one of the game's other files, which use Windows-1252. Never fails, since every
+/// byte is a character
+pub(crate) fn decode_string(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&byte| match byte {
+            0x80..=0x9F => WINDOWS_1252_HIGH[(byte - 0x80) as usize],
+            _ => char::from(byte),
+        })
+        .collect()
 }
 
-pub(super) struct Subrecord<'a> {
+/// Encode a string as Windows-1252 for writing, with `?` for characters it has no byte for
+pub(crate) fn encode_string(value: &str) -> Vec<u8> {
+    value
+        .chars()
+        .map(|c| match WINDOWS_1252_HIGH.iter().position(|high| *high == c) {
+            Some(position) => 0x80 + position as u8,
+            None if (c as u32) < 0x100 => c as u8,
+            None => b'?',
+        })
+        .collect()
+}
+
+pub(crate) fn zstring(bytes: &[u8]) -> crate::IResult<&[u8], String> {
+    map(terminated(take_while(|c| c != 0), tag([0u8])), decode_string)(bytes)
+}
+
+pub(crate) fn lstring(bytes: &[u8], localized: bool) -> crate::IResult<&[u8], LString> {
+    if localized {
+        map(le_u32, LString::Id)(bytes)
+    } else {
+        map(zstring, LString::Inline)(bytes)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Subrecord<'a> {
     pub code: TypeCode,
     pub data: &'a [u8],
 }
 
-pub(super) fn subrecords(bytes: &[u8]) -> crate::IResult<&[u8], Vec<Subrecord>> {
-    many0(map(
-        pair(map(le_u32, |code| TypeCode::from(code)), flat_map(le_u16, take)),
-        |(code, data)| Subrecord { code, data },
-    ))(bytes)
+/// Subrecords larger than 65535 bytes are preceded by an `XXXX` subrecord holding the real size
+const XXXX: TypeCode = TypeCode([b'X', b'X', b'X', b'X']);
+
+fn subrecord(bytes: &[u8]) -> crate::IResult<&[u8], Subrecord<'_>> {
+    let (bytes, (code, size)) = pair(map(le_u32, TypeCode::from), le_u16)(bytes)?;
+
+    let (bytes, (code, size)) = if code == XXXX {
+        let (bytes, size) = le_u32(bytes)?;
+        let (bytes, (code, _)) = pair(map(le_u32, TypeCode::from), le_u16)(bytes)?;
+        (bytes, (code, size))
+    } else {
+        (bytes, (code, size as u32))
+    };
+
+    let (bytes, data) = take(size)(bytes)?;
+
+    Ok((bytes, Subrecord { code, data }))
+}
+
+pub(crate) fn subrecords(bytes: &[u8]) -> crate::IResult<&[u8], Vec<Subrecord<'_>>> {
+    many0(subrecord)(bytes)
+}
+
+/// Append a subrecord to `out`, splitting off an `XXXX` size subrecord when the data is too large
+pub(crate) fn write_subrecord(out: &mut Vec<u8>, code: &TypeCode, data: &[u8]) {
+    if data.len() > u16::MAX as usize {
+        out.extend_from_slice(&*XXXX);
+        out.extend_from_slice(&4u16.to_le_bytes());
+        out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        out.extend_from_slice(&**code);
+        out.extend_from_slice(&0u16.to_le_bytes());
+    } else {
+        out.extend_from_slice(&**code);
+        out.extend_from_slice(&(data.len() as u16).to_le_bytes());
+    }
+
+    out.extend_from_slice(data);
 }
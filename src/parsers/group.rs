@@ -1,7 +1,7 @@
 use std::{collections::HashMap, convert::TryInto};
 
 use crate::parsers::{
-    common::{FormId, TypeCode},
+    common::{FormId, GridCoord, TypeCode},
     records::{record, Record},
 };
 
@@ -10,32 +10,145 @@ use nom::{
     bytes::complete::take,
     combinator::map,
     number::complete::{le_u16, le_u32},
-    sequence::{delimited, tuple},
+    sequence::{preceded, tuple},
 };
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Group {
     pub size: u32,
     pub label: Label,
     pub group_type: GroupType,
     pub timestamp: u16,
     pub vc_info: u16,
+    pub unknown: u32,
     pub data: GroupData,
 }
 
 impl Group {
     pub const CODE: TypeCode = TypeCode([b'G', b'R', b'U', b'P']);
     pub const HEADER_SIZE: usize = 24;
+
+    /// An empty top group for records of type `code`
+    pub fn top(code: TypeCode) -> Self {
+        Group {
+            size: 0,
+            label: Label::RecordType(code),
+            group_type: GroupType::Top,
+            timestamp: 0,
+            vc_info: 0,
+            unknown: 0,
+            data: GroupData::Records(HashMap::new()),
+        }
+    }
+
+    /// Records held by this group, including those in nested child groups
+    pub fn records(&self) -> Vec<&Record> {
+        match &self.data {
+            GroupData::Records(records) => records.values().collect(),
+            GroupData::Children(children) => children
+                .iter()
+                .flat_map(|child| match child {
+                    GroupChild::Record(record) => vec![record],
+                    GroupChild::Group(group) => group.records(),
+                })
+                .collect(),
+            GroupData::Unimplemented(_) => vec![],
+        }
+    }
+
+    /// Records held by temporary cell children groups nested in this group
+    pub fn temporary_records(&self) -> Vec<&Record> {
+        match &self.data {
+            _ if self.group_type == GroupType::CellTemporaryChildren => self.records(),
+            GroupData::Children(children) => children
+                .iter()
+                .flat_map(|child| match child {
+                    GroupChild::Record(_) => vec![],
+                    GroupChild::Group(group) => group.temporary_records(),
+                })
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    pub fn record(&self, id: FormId) -> Option<&Record> {
+        match &self.data {
+            GroupData::Records(records) => records.get(&id),
+            GroupData::Children(children) => children.iter().find_map(|child| match child {
+                GroupChild::Record(record) if record.header.id == id => Some(record),
+                GroupChild::Record(_) => None,
+                GroupChild::Group(group) => group.record(id),
+            }),
+            GroupData::Unimplemented(_) => None,
+        }
+    }
+
+    pub fn record_mut(&mut self, id: FormId) -> Option<&mut Record> {
+        match &mut self.data {
+            GroupData::Records(records) => records.get_mut(&id),
+            GroupData::Children(children) => children.iter_mut().find_map(|child| match child {
+                GroupChild::Record(record) if record.header.id == id => Some(record),
+                GroupChild::Record(_) => None,
+                GroupChild::Group(group) => group.record_mut(id),
+            }),
+            GroupData::Unimplemented(_) => None,
+        }
+    }
+
+    /// Number of records and non-empty groups, including this one, as counted by `Hedr::num_records`
+    pub fn record_count(&self) -> i32 {
+        let count = match &self.data {
+            GroupData::Records(records) => records.len() as i32,
+            GroupData::Children(children) => children
+                .iter()
+                .map(|child| match child {
+                    GroupChild::Record(_) => 1,
+                    GroupChild::Group(group) => group.record_count(),
+                })
+                .sum(),
+            GroupData::Unimplemented(bytes) if bytes.is_empty() => 0,
+            GroupData::Unimplemented(_) => return 1,
+        };
+
+        if count == 0 {
+            0
+        } else {
+            1 + count
+        }
+    }
+
+    /// Remove a record from this group or its nested child groups
+    pub fn remove_record(&mut self, id: FormId) -> Option<Record> {
+        match &mut self.data {
+            GroupData::Records(records) => records.remove(&id),
+            GroupData::Children(children) => {
+                let position = children
+                    .iter()
+                    .position(|child| matches!(child, GroupChild::Record(record) if record.header.id == id));
+
+                match position {
+                    Some(position) => match children.remove(position) {
+                        GroupChild::Record(record) => Some(record),
+                        GroupChild::Group(_) => unreachable!(),
+                    },
+                    None => children.iter_mut().find_map(|child| match child {
+                        GroupChild::Group(group) => group.remove_record(id),
+                        GroupChild::Record(_) => None,
+                    }),
+                }
+            }
+            GroupData::Unimplemented(_) => None,
+        }
+    }
 }
 
 pub(super) fn group(bytes: &[u8]) -> crate::IResult<&[u8], Group> {
     let (bytes, mut group): (&[u8], Group) = map(
-        delimited(
-            take(4usize),
-            tuple((le_u32, take(4usize), take(4usize), le_u16, le_u16)),
+        preceded(
             take(4usize),
+            tuple((le_u32, take(4usize), take(4usize), le_u16, le_u16, le_u32)),
         ),
-        |(size, label, mut group_type, timestamp, vc_info): (u32, &[u8], &[u8], u16, u16)| {
+        |(size, label, mut group_type, timestamp, vc_info, unknown): (u32, &[u8], &[u8], u16, u16, u32)| {
             let group_type = group_type.read_i32::<LittleEndian>().unwrap().into();
             let label = label_given_type(label, &group_type);
 
@@ -45,6 +158,7 @@ pub(super) fn group(bytes: &[u8]) -> crate::IResult<&[u8], Group> {
                 group_type,
                 timestamp,
                 vc_info,
+                unknown,
                 data: GroupData::Unimplemented(Vec::<u8>::new()),
             }
         },
@@ -73,10 +187,10 @@ fn label_given_type(bytes: &[u8], group_type: &GroupType) -> Label {
             Label::RecordType(arr.into())
         }
         GroupType::WorldChildren => Label::ParentWorld(form_id_from_vec(bytes)),
-        GroupType::InteriorCellBlock => Label::BlockNumber(i32_from_vec(bytes).into()),
-        GroupType::InteriorCellSubBlock => Label::SubBlockNumber(i32_from_vec(bytes).into()),
-        GroupType::ExteriorCellBlock => Label::GridCoordinate(grid_coord_from_vec(bytes)),
-        GroupType::ExteriorCellSubBlock => Label::GridCoordinate(grid_coord_from_vec(bytes)),
+        GroupType::InteriorCellBlock => Label::BlockNumber(i32_from_vec(bytes)),
+        GroupType::InteriorCellSubBlock => Label::SubBlockNumber(i32_from_vec(bytes)),
+        GroupType::ExteriorCellBlock => Label::GridCoordinate(GridCoord::from_label(bytes.try_into().unwrap())),
+        GroupType::ExteriorCellSubBlock => Label::GridCoordinate(GridCoord::from_label(bytes.try_into().unwrap())),
         GroupType::CellChildren => Label::ParentCell(form_id_from_vec(bytes)),
         GroupType::TopicChildren => Label::ParentDialog(form_id_from_vec(bytes)),
         GroupType::CellPersistenChildren => Label::ParentCell(form_id_from_vec(bytes)),
@@ -116,10 +230,11 @@ impl From<i32> for GroupType {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Label {
     BlockNumber(i32),
-    GridCoordinate([u16; 2]),
+    /// Block coordinates for exterior cell blocks, sub-block coordinates for sub-blocks
+    GridCoordinate(GridCoord),
     ParentCell(FormId),
     ParentDialog(FormId),
     ParentWorld(FormId),
@@ -127,12 +242,20 @@ pub enum Label {
     SubBlockNumber(i32),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum GroupData {
     Records(HashMap<FormId, Record>),
+    /// Records interleaved with their child groups, in file order
+    Children(Vec<GroupChild>),
     Unimplemented(Vec<u8>),
 }
 
+#[derive(Debug, Clone)]
+pub enum GroupChild {
+    Record(Record),
+    Group(Group),
+}
+
 fn group_data<'a>(
     bytes: &'a [u8],
     group_type: GroupType,
@@ -144,11 +267,11 @@ fn group_data<'a>(
     match group_type {
         GroupType::Top => match label {
             Label::RecordType(code) => match code.to_string().as_str() {
-                "CELL" | "WRLD" | "DIAL" => Ok((remaining, GroupData::Unimplemented(group_bytes.to_vec()))),
+                "CELL" | "WRLD" | "DIAL" => Ok((remaining, GroupData::Children(group_children(group_bytes)?.1))),
                 _ => {
                     let mut records = HashMap::new();
 
-                    while group_bytes.len() > 0 {
+                    while !group_bytes.is_empty() {
                         let (group_remaining, record) = record(group_bytes)?;
                         group_bytes = group_remaining;
                         records.insert(record.header.id, record);
@@ -159,8 +282,26 @@ fn group_data<'a>(
             },
             _ => Ok((remaining, GroupData::Unimplemented(group_bytes.to_vec()))),
         },
-        _ => Ok((remaining, GroupData::Unimplemented(group_bytes.to_vec()))),
+        _ => Ok((remaining, GroupData::Children(group_children(group_bytes)?.1))),
+    }
+}
+
+fn group_children(mut bytes: &[u8]) -> crate::IResult<&[u8], Vec<GroupChild>> {
+    let mut children = vec![];
+
+    while !bytes.is_empty() {
+        if bytes.starts_with(&*Group::CODE) {
+            let (remaining, group) = group(bytes)?;
+            children.push(GroupChild::Group(group));
+            bytes = remaining;
+        } else {
+            let (remaining, record) = record(bytes)?;
+            children.push(GroupChild::Record(record));
+            bytes = remaining;
+        }
     }
+
+    Ok((bytes, children))
 }
 
 fn form_id_from_vec(mut v: &[u8]) -> FormId {
@@ -170,10 +311,3 @@ fn form_id_from_vec(mut v: &[u8]) -> FormId {
 fn i32_from_vec(mut v: &[u8]) -> i32 {
     v.read_i32::<LittleEndian>().unwrap()
 }
-
-fn grid_coord_from_vec(mut v: &[u8]) -> [u16; 2] {
-    [
-        v.read_u16::<LittleEndian>().unwrap(),
-        v.read_u16::<LittleEndian>().unwrap(),
-    ]
-}
@@ -1,6 +1,19 @@
 use std::collections::HashMap;
 
-use crate::parsers::{common::TypeCode, group, records};
+use crate::parsers::{
+    common::{FormId, TypeCode},
+    group,
+    records::{self, file_header::FileHeaderData, RecordData},
+};
+
+/// Record types found in temporary cell children, whose overrides are listed in `ONAM`
+const TEMPORARY_CODES: &[[u8; 4]] = &[
+    *b"ACHR", *b"LAND", *b"NAVM", *b"PARW", *b"PBAR", *b"PBEA", *b"PCON", *b"PFLA", *b"PGRE", *b"PHZD", *b"PMIS",
+    *b"REFR",
+];
+
+/// Record types only found in nested groups, apart from those in temporary cell children
+const NESTED_CODES: &[[u8; 4]] = &[*b"CELL", *b"INFO"];
 
 #[derive(Debug)]
 pub struct Plugin {
@@ -9,6 +22,117 @@ pub struct Plugin {
 }
 
 impl Plugin {
+    /// All parsed records in the plugin, excluding the file header
+    pub fn records(&self) -> Vec<&records::Record> {
+        self.groups.values().flat_map(|group| group.records()).collect()
+    }
+
+    pub fn record(&self, id: FormId) -> Option<&records::Record> {
+        self.groups.values().find_map(|group| group.record(id))
+    }
+
+    pub fn record_mut(&mut self, id: FormId) -> Option<&mut records::Record> {
+        self.groups.values_mut().find_map(|group| group.record_mut(id))
+    }
+
+    /// Remove a record from whichever group holds it. `Hedr::num_records` is left to the caller
+    pub fn remove_record(&mut self, id: FormId) -> Option<records::Record> {
+        self.groups.values_mut().find_map(|group| group.remove_record(id))
+    }
+
+    /// Add a record to the top group for its type, creating the group if the plugin has none, and count it in
+    /// `Hedr::num_records`. Returns the record it replaces, if one with the same FormID was already there.
+    ///
+    /// Cells, and records that live in cell or topic children groups, need a place in a nested group and can't
+    /// be added this way
+    pub fn insert_record(&mut self, record: records::Record) -> Result<Option<records::Record>, crate::Error> {
+        let code = record.header.code.clone();
+
+        if NESTED_CODES.contains(&*code) || TEMPORARY_CODES.contains(&*code) {
+            return Err(crate::Error::CorruptOrInvalidRecord(format!(
+                "{} {} belongs in a nested group",
+                code, record.header.id
+            )));
+        }
+
+        if let Some(group::GroupData::Unimplemented(_)) = self.groups.get(&code).map(|group| &group.data) {
+            return Err(crate::Error::CorruptOrInvalidRecord(format!(
+                "the {} group of this plugin can't be edited",
+                code
+            )));
+        }
+
+        let before = self.groups.get(&code).map_or(0, group::Group::record_count);
+        let replaced = self.remove_record(record.header.id);
+        let group = self
+            .groups
+            .entry(code.clone())
+            .or_insert_with(|| group::Group::top(code.clone()));
+
+        match &mut group.data {
+            group::GroupData::Records(records) => {
+                records.insert(record.header.id, record);
+            }
+            group::GroupData::Children(children) => children.push(group::GroupChild::Record(record)),
+            group::GroupData::Unimplemented(_) => unreachable!("checked before removing anything"),
+        }
+
+        let after = group.record_count();
+
+        if replaced.as_ref().is_some_and(|replaced| replaced.header.code != code) {
+            self.update_num_records();
+        } else {
+            self.file_header_mut().hedr.num_records += after - before;
+        }
+
+        Ok(replaced)
+    }
+
+    /// Recompute `Hedr::num_records` from the records and groups the plugin holds
+    pub fn update_num_records(&mut self) {
+        let count = self.groups.values().map(group::Group::record_count).sum();
+        self.file_header_mut().hedr.num_records = count;
+    }
+
+    /// Rebuild the `ONAM` list of overridden temporary references, navmeshes and landscape
+    pub fn update_overrides(&mut self) {
+        let num_masters = self.masters().len();
+
+        let mut overrides = self
+            .groups
+            .values()
+            .flat_map(|group| group.temporary_records())
+            .filter(|record| (record.header.id.index() as usize) < num_masters)
+            .map(|record| record.header.id)
+            .collect::<Vec<_>>();
+
+        overrides.sort();
+        self.file_header_mut().overrides = overrides;
+    }
+
+    pub fn file_header(&self) -> &FileHeaderData {
+        match &self.tes4.data {
+            RecordData::FileHeader(data) => data,
+            _ => unreachable!("TES4 record is validated when parsing"),
+        }
+    }
+
+    pub fn file_header_mut(&mut self) -> &mut FileHeaderData {
+        match &mut self.tes4.data {
+            RecordData::FileHeader(data) => data,
+            _ => unreachable!("TES4 record is validated when parsing"),
+        }
+    }
+
+    /// File names of the plugin's masters, in the order used by FormID load order indices
+    pub fn masters(&self) -> Vec<&str> {
+        self.file_header()
+            .masters
+            .iter()
+            .map(|master| master.name.as_str())
+            .collect()
+    }
+
     pub fn get_editor_ids_by_code(&self, code: [u8; 4]) -> Vec<String> {
         let code: TypeCode = code.into();
 
@@ -34,9 +158,17 @@ impl Plugin {
 
 pub fn plugin(bytes: &[u8]) -> crate::IResult<&[u8], Plugin> {
     let (mut bytes, tes4) = records::file_header_record(bytes)?;
+
+    if !matches!(tes4.data, RecordData::FileHeader(_)) {
+        return Err(nom::Err::Failure(crate::Error::CorruptOrInvalidFile(format!(
+            "expected TES4 header, found {}",
+            tes4.header.code
+        ))));
+    }
+
     let mut groups = HashMap::new();
 
-    while bytes.len() > 0 {
+    while !bytes.is_empty() {
         let (remaining, (code, group)) = group::top_group(bytes)?;
         groups.insert(code, group);
         bytes = remaining;
@@ -0,0 +1,836 @@
+use std::fmt;
+
+use crate::parsers::{
+    common::{form_id, zstring, FormId},
+    records::Record,
+};
+
+use nom::{
+    bytes::complete::take,
+    number::complete::{le_i32, le_u16, le_u32, le_u8},
+    sequence::{terminated, tuple},
+};
+
+/// Condition flags, the low bits of a CTDA's first byte
+pub mod flags {
+    /// Joined to the next condition with OR rather than AND
+    pub const OR: u8 = 0x01;
+    /// FormID parameters are quest alias indices
+    pub const USE_ALIASES: u8 = 0x02;
+    /// The comparison value is a GLOB FormID
+    pub const USE_GLOBAL: u8 = 0x04;
+    /// FormID parameters are package data indices
+    pub const USE_PACKAGE_DATA: u8 = 0x08;
+    pub const SWAP_SUBJECT_AND_TARGET: u8 = 0x10;
+}
+
+/// How a condition function parameter is interpreted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamType {
+    Form,
+    Integer,
+    Float,
+    /// The character code of X, Y or Z
+    Axis,
+    ActorValue,
+    Sex,
+    /// Left hand, right hand, voice or instant
+    CastingSource,
+    /// A quest alias index
+    Alias,
+    /// A package data index
+    PackageData,
+    /// A string held in the CIS1 or CIS2 following the CTDA
+    Text,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Function {
+    pub index: u16,
+    pub name: &'static str,
+    pub params: &'static [ParamType],
+}
+
+const fn function(index: u16, name: &'static str, params: &'static [ParamType]) -> Function {
+    Function { index, name, params }
+}
+
+use ParamType::*;
+
+/// Skyrim's condition functions, by index
+pub static FUNCTIONS: &[Function] = &[
+    function(0, "GetWantBlocking", &[]),
+    function(1, "GetDistance", &[Form]),
+    function(5, "GetLocked", &[]),
+    function(6, "GetPos", &[Axis]),
+    function(8, "GetAngle", &[Axis]),
+    function(10, "GetStartingPos", &[Axis]),
+    function(11, "GetStartingAngle", &[Axis]),
+    function(12, "GetSecondsPassed", &[]),
+    function(14, "GetActorValue", &[ActorValue]),
+    function(18, "GetCurrentTime", &[]),
+    function(24, "GetScale", &[]),
+    function(25, "IsMoving", &[]),
+    function(26, "IsTurning", &[]),
+    function(27, "GetLineOfSight", &[Form]),
+    function(32, "GetInSameCell", &[Form]),
+    function(35, "GetDisabled", &[]),
+    function(36, "MenuMode", &[Integer]),
+    function(39, "GetDisease", &[]),
+    function(41, "GetClothingValue", &[]),
+    function(42, "SameFaction", &[Form]),
+    function(43, "SameRace", &[Form]),
+    function(44, "SameSex", &[Form]),
+    function(45, "GetDetected", &[Form]),
+    function(46, "GetDead", &[]),
+    function(47, "GetItemCount", &[Form]),
+    function(48, "GetGold", &[]),
+    function(49, "GetSleeping", &[]),
+    function(50, "GetTalkedToPC", &[]),
+    function(53, "GetScriptVariable", &[Form, Text]),
+    function(56, "GetQuestRunning", &[Form]),
+    function(58, "GetStage", &[Form]),
+    function(59, "GetStageDone", &[Form, Integer]),
+    function(60, "GetFactionRankDifference", &[Form, Form]),
+    function(61, "GetAlarmed", &[]),
+    function(62, "IsRaining", &[]),
+    function(63, "GetAttacked", &[]),
+    function(64, "GetIsCreature", &[]),
+    function(65, "GetLockLevel", &[]),
+    function(66, "GetShouldAttack", &[Form]),
+    function(67, "GetInCell", &[Form]),
+    function(68, "GetIsClass", &[Form]),
+    function(69, "GetIsRace", &[Form]),
+    function(70, "GetIsSex", &[Sex]),
+    function(71, "GetInFaction", &[Form]),
+    function(72, "GetIsID", &[Form]),
+    function(73, "GetFactionRank", &[Form]),
+    function(74, "GetGlobalValue", &[Form]),
+    function(75, "IsSnowing", &[]),
+    function(77, "GetRandomPercent", &[]),
+    function(79, "GetQuestVariable", &[Form, Text]),
+    function(80, "GetLevel", &[]),
+    function(81, "IsRotating", &[]),
+    function(84, "GetDeadCount", &[Form]),
+    function(91, "GetIsAlerted", &[]),
+    function(98, "GetPlayerControlsDisabled", &[]),
+    function(99, "GetHeadingAngle", &[Form]),
+    function(101, "IsWeaponMagicOut", &[]),
+    function(102, "IsTorchOut", &[]),
+    function(103, "IsShieldOut", &[]),
+    function(106, "IsFacingUp", &[]),
+    function(107, "GetKnockedState", &[]),
+    function(108, "GetWeaponAnimType", &[]),
+    function(109, "IsWeaponSkillType", &[ActorValue]),
+    function(110, "GetCurrentAIPackage", &[]),
+    function(111, "IsWaiting", &[]),
+    function(112, "IsIdlePlaying", &[]),
+    function(116, "IsIntimidatedbyPlayer", &[]),
+    function(117, "IsPlayerInRegion", &[Form]),
+    function(118, "GetActorAggroRadiusViolated", &[]),
+    function(122, "GetCrime", &[Form, Integer]),
+    function(123, "IsGreetingPlayer", &[]),
+    function(125, "IsGuard", &[]),
+    function(127, "HasBeenEaten", &[]),
+    function(128, "GetStaminaPercentage", &[]),
+    function(129, "HasBeenRead", &[]),
+    function(130, "GetDying", &[]),
+    function(131, "GetSceneActionPercent", &[Form, Integer]),
+    function(132, "WouldRefuseCommand", &[Form]),
+    function(133, "SameFactionAsPC", &[]),
+    function(134, "SameRaceAsPC", &[]),
+    function(135, "SameSexAsPC", &[]),
+    function(136, "GetIsReference", &[Form]),
+    function(141, "IsTalking", &[]),
+    function(142, "GetWalkSpeed", &[]),
+    function(143, "GetCurrentAIProcedure", &[]),
+    function(144, "GetTrespassWarningLevel", &[]),
+    function(145, "IsTrespassing", &[]),
+    function(146, "IsInMyOwnedCell", &[]),
+    function(147, "GetWindSpeed", &[]),
+    function(148, "GetCurrentWeatherPercent", &[]),
+    function(149, "GetIsCurrentWeather", &[Form]),
+    function(150, "IsContinuingPackagePCNear", &[]),
+    function(152, "GetIsCrimeFaction", &[Form]),
+    function(153, "CanHaveFlames", &[]),
+    function(154, "HasFlames", &[]),
+    function(157, "GetOpenState", &[]),
+    function(159, "GetSitting", &[]),
+    function(161, "GetIsCurrentPackage", &[Form]),
+    function(162, "IsCurrentFurnitureRef", &[Form]),
+    function(163, "IsCurrentFurnitureObj", &[Form]),
+    function(170, "GetDayOfWeek", &[]),
+    function(172, "GetTalkedToPCParam", &[Form]),
+    function(175, "IsPCSleeping", &[]),
+    function(176, "IsPCAMurderer", &[]),
+    function(180, "HasSameEditorLocAsRef", &[Form, Form]),
+    function(181, "HasSameEditorLocAsRefAlias", &[Alias, Form]),
+    function(182, "GetEquipped", &[Form]),
+    function(185, "IsSwimming", &[]),
+    function(190, "GetAmountSoldStolen", &[]),
+    function(192, "GetIgnoreCrime", &[]),
+    function(193, "GetPCExpelled", &[Form]),
+    function(195, "GetPCFactionMurder", &[Form]),
+    function(197, "GetPCEnemyofFaction", &[Form]),
+    function(199, "GetPCFactionAttack", &[Form]),
+    function(203, "GetDestroyed", &[]),
+    function(214, "HasMagicEffect", &[Form]),
+    function(215, "GetDefaultOpen", &[]),
+    function(219, "GetAnimAction", &[]),
+    function(223, "IsSpellTarget", &[Form]),
+    function(224, "GetVATSMode", &[]),
+    function(225, "GetPersuasionNumber", &[]),
+    function(226, "GetVampireFeed", &[]),
+    function(227, "GetCannibal", &[]),
+    function(228, "GetIsClassDefault", &[Form]),
+    function(229, "GetClassDefaultMatch", &[]),
+    function(230, "GetInCellParam", &[Form, Form]),
+    function(231, "GetPlayerDialogueInput", &[]),
+    function(235, "GetVatsTargetHeight", &[]),
+    function(237, "GetIsGhost", &[]),
+    function(242, "GetUnconscious", &[]),
+    function(244, "GetRestrained", &[]),
+    function(246, "GetIsUsedItem", &[Form]),
+    function(247, "GetIsUsedItemType", &[Integer]),
+    function(248, "IsScenePlaying", &[Form]),
+    function(249, "IsInDialogueWithPlayer", &[]),
+    function(250, "GetLocationCleared", &[Form]),
+    function(254, "GetIsPlayableRace", &[]),
+    function(255, "GetOffersServicesNow", &[]),
+    function(258, "HasAssociationType", &[Form, Form]),
+    function(259, "HasFamilyRelationship", &[Form]),
+    function(261, "HasParentRelationship", &[Form]),
+    function(262, "IsWarningAbout", &[Form]),
+    function(263, "IsWeaponOut", &[]),
+    function(264, "HasSpell", &[Form]),
+    function(265, "IsTimePassing", &[]),
+    function(266, "IsPleasant", &[]),
+    function(267, "IsCloudy", &[]),
+    function(274, "IsSmallBump", &[]),
+    function(277, "GetBaseActorValue", &[ActorValue]),
+    function(278, "IsOwner", &[Form]),
+    function(280, "IsCellOwner", &[Form, Form]),
+    function(282, "IsHorseStolen", &[]),
+    function(285, "IsLeftUp", &[]),
+    function(286, "IsSneaking", &[]),
+    function(287, "IsRunning", &[]),
+    function(288, "GetFriendHit", &[]),
+    function(289, "IsInCombat", &[Integer]),
+    function(300, "IsInInterior", &[]),
+    function(304, "IsWaterObject", &[]),
+    function(305, "GetPlayerAction", &[]),
+    function(306, "IsActorUsingATorch", &[]),
+    function(309, "IsXBox", &[]),
+    function(310, "GetInWorldspace", &[Form]),
+    function(312, "GetPCMiscStat", &[Integer]),
+    function(313, "GetPairedAnimation", &[]),
+    function(314, "IsActorAVictim", &[]),
+    function(315, "GetTotalPersuasionNumber", &[]),
+    function(318, "GetIdleDoneOnce", &[]),
+    function(320, "GetNoRumors", &[]),
+    function(323, "GetCombatState", &[]),
+    function(325, "GetWithinPackageLocation", &[PackageData]),
+    function(327, "IsRidingMount", &[]),
+    function(329, "IsFleeing", &[]),
+    function(332, "IsInDangerousWater", &[]),
+    function(338, "GetIgnoreFriendlyHits", &[]),
+    function(339, "IsPlayersLastRiddenMount", &[]),
+    function(353, "IsActor", &[]),
+    function(354, "IsEssential", &[]),
+    function(358, "IsPlayerMovingIntoNewSpace", &[]),
+    function(359, "GetInCurrentLoc", &[Form]),
+    function(360, "GetInCurrentLocAlias", &[Alias]),
+    function(361, "GetTimeDead", &[]),
+    function(362, "HasLinkedRef", &[Form]),
+    function(365, "IsChild", &[]),
+    function(366, "GetStolenItemValueNoCrime", &[Form]),
+    function(367, "GetLastPlayerAction", &[]),
+    function(368, "IsPlayerActionActive", &[Integer]),
+    function(370, "IsTalkingActivatorActor", &[Form]),
+    function(372, "IsInList", &[Form]),
+    function(373, "GetStolenItemValue", &[Form]),
+    function(375, "GetCrimeGoldViolent", &[Form]),
+    function(376, "GetCrimeGoldNonviolent", &[Form]),
+    function(378, "HasShout", &[Form]),
+    function(381, "GetHasNote", &[Form]),
+    function(390, "GetHitLocation", &[]),
+    function(391, "IsPC1stPerson", &[]),
+    function(396, "GetCauseofDeath", &[]),
+    function(397, "IsLimbGone", &[Integer]),
+    function(398, "IsWeaponInList", &[Form]),
+    function(402, "IsBribedbyPlayer", &[]),
+    function(403, "GetRelationshipRank", &[Form]),
+    function(407, "GetVATSValue", &[Integer, Integer]),
+    function(408, "IsKiller", &[Form]),
+    function(409, "IsKillerObject", &[Form]),
+    function(410, "GetFactionCombatReaction", &[Form, Form]),
+    function(414, "Exists", &[Form]),
+    function(415, "GetGroupMemberCount", &[]),
+    function(416, "GetGroupTargetCount", &[]),
+    function(426, "GetIsVoiceType", &[Form]),
+    function(427, "GetPlantedExplosive", &[]),
+    function(429, "IsScenePackageRunning", &[]),
+    function(430, "GetHealthPercentage", &[]),
+    function(432, "GetIsObjectType", &[Integer]),
+    function(434, "GetDialogueEmotion", &[]),
+    function(435, "GetDialogueEmotionValue", &[]),
+    function(437, "GetIsCreatureType", &[Integer]),
+    function(444, "GetInCurrentLocFormList", &[Form]),
+    function(445, "GetInZone", &[Form]),
+    function(446, "GetVelocity", &[Axis]),
+    function(447, "GetGraphVariableFloat", &[Text]),
+    function(448, "HasPerk", &[Form]),
+    function(449, "GetFactionRelation", &[Form]),
+    function(450, "IsLastIdlePlayed", &[Form]),
+    function(453, "GetPlayerTeammate", &[]),
+    function(454, "GetPlayerTeammateCount", &[]),
+    function(458, "GetActorCrimePlayerEnemy", &[]),
+    function(459, "GetCrimeGold", &[Form]),
+    function(462, "GetPlayerGrabbedRef", &[Form]),
+    function(463, "IsPlayerGrabbedRef", &[Form]),
+    function(465, "GetKeywordItemCount", &[Form]),
+    function(467, "GetBroadcastState", &[]),
+    function(470, "GetDestructionStage", &[]),
+    function(473, "GetIsAlignment", &[Integer]),
+    function(476, "IsProtected", &[]),
+    function(477, "GetThreatRatio", &[Form]),
+    function(479, "GetIsUsedItemEquipType", &[Form]),
+    function(487, "IsCarryable", &[]),
+    function(488, "GetConcussed", &[]),
+    function(491, "GetMapMarkerVisible", &[]),
+    function(493, "PlayerKnows", &[Form]),
+    function(494, "GetPermanentActorValue", &[ActorValue]),
+    function(495, "GetKillingBlowLimb", &[]),
+    function(497, "CanPayCrimeGold", &[Form]),
+    function(499, "GetDaysInJail", &[]),
+    function(500, "EPAlchemyGetMakingPoison", &[]),
+    function(501, "EPAlchemyEffectHasKeyword", &[Form]),
+    function(503, "GetAllowWorldInteractions", &[]),
+    function(508, "GetLastHitCritical", &[]),
+    function(513, "IsCombatTarget", &[Form]),
+    function(515, "GetVATSRightAreaFree", &[Form]),
+    function(516, "GetVATSLeftAreaFree", &[Form]),
+    function(517, "GetVATSBackAreaFree", &[Form]),
+    function(518, "GetVATSFrontAreaFree", &[Form]),
+    function(519, "GetIsLockBroken", &[]),
+    function(520, "IsPS3", &[]),
+    function(521, "IsWin32", &[]),
+    function(522, "GetVATSRightTargetVisible", &[Form]),
+    function(523, "GetVATSLeftTargetVisible", &[Form]),
+    function(524, "GetVATSBackTargetVisible", &[Form]),
+    function(525, "GetVATSFrontTargetVisible", &[Form]),
+    function(528, "IsInCriticalStage", &[Integer]),
+    function(530, "GetXPForNextLevel", &[]),
+    function(533, "GetInfamy", &[Form]),
+    function(534, "GetInfamyViolent", &[Form]),
+    function(535, "GetInfamyNonViolent", &[Form]),
+    function(543, "GetQuestCompleted", &[Form]),
+    function(547, "IsGoreDisabled", &[]),
+    function(550, "IsSceneActionComplete", &[Form, Integer]),
+    function(552, "GetSpellUsageNum", &[Form]),
+    function(554, "GetActorsInHigh", &[]),
+    function(555, "HasLoaded3D", &[]),
+    function(560, "HasKeyword", &[Form]),
+    function(561, "HasRefType", &[Form]),
+    function(562, "LocationHasKeyword", &[Form]),
+    function(563, "LocationHasRefType", &[Form]),
+    function(565, "GetIsEditorLocation", &[Form]),
+    function(566, "GetIsAliasRef", &[Alias]),
+    function(567, "GetIsEditorLocAlias", &[Alias]),
+    function(568, "IsSprinting", &[]),
+    function(569, "IsBlocking", &[]),
+    function(570, "HasEquippedSpell", &[CastingSource]),
+    function(571, "GetCurrentCastingType", &[CastingSource]),
+    function(572, "GetCurrentDeliveryType", &[CastingSource]),
+    function(574, "GetAttackState", &[]),
+    function(576, "GetEventData", &[Integer, Form]),
+    function(577, "IsCloserToAThanB", &[Form, Form]),
+    function(579, "GetEquippedShout", &[Form]),
+    function(580, "IsBleedingOut", &[]),
+    function(584, "GetRelativeAngle", &[Form, Axis]),
+    function(589, "GetMovementDirection", &[]),
+    function(590, "IsInScene", &[]),
+    function(591, "GetRefTypeDeadCount", &[Form, Form]),
+    function(592, "GetRefTypeAliveCount", &[Form, Form]),
+    function(594, "GetIsFlying", &[]),
+    function(595, "IsCurrentSpell", &[Form, CastingSource]),
+    function(596, "SpellHasKeyword", &[CastingSource, Form]),
+    function(597, "GetEquippedItemType", &[CastingSource]),
+    function(598, "GetLocationAliasCleared", &[Alias]),
+    function(600, "GetLocAliasRefTypeDeadCount", &[Alias, Form]),
+    function(601, "GetLocAliasRefTypeAliveCount", &[Alias, Form]),
+    function(602, "IsWardState", &[Integer]),
+    function(603, "IsInSameCurrentLocAsRef", &[Form, Form]),
+    function(604, "IsInSameCurrentLocAsRefAlias", &[Alias, Form]),
+    function(605, "LocAliasIsLocation", &[Alias, Form]),
+    function(606, "GetKeywordDataForLocation", &[Form, Form]),
+    function(608, "GetKeywordDataForAlias", &[Alias, Form]),
+    function(610, "LocAliasHasKeyword", &[Alias, Form]),
+    function(611, "IsNullPackageData", &[PackageData]),
+    function(612, "GetNumericPackageData", &[PackageData]),
+    function(613, "IsFurnitureAnimType", &[Integer]),
+    function(614, "IsFurnitureEntryType", &[Integer]),
+    function(615, "GetHighestRelationshipRank", &[]),
+    function(616, "GetLowestRelationshipRank", &[]),
+    function(617, "HasAssociationTypeAny", &[Form]),
+    function(618, "HasFamilyRelationshipAny", &[]),
+    function(619, "GetPathingTargetOffset", &[Axis]),
+    function(620, "GetPathingTargetAngleOffset", &[Axis]),
+    function(621, "GetPathingTargetSpeed", &[]),
+    function(622, "GetPathingTargetSpeedAngle", &[Axis]),
+    function(623, "GetMovementSpeed", &[]),
+    function(624, "GetInContainer", &[Form]),
+    function(625, "IsLocationLoaded", &[Form]),
+    function(626, "IsLocAliasLoaded", &[Alias]),
+    function(627, "IsDualCasting", &[]),
+    function(629, "GetVMQuestVariable", &[Form, Text]),
+    function(630, "GetVMScriptVariable", &[Form, Text]),
+    function(631, "IsEnteringInteractionQuick", &[]),
+    function(632, "IsCasting", &[]),
+    function(633, "GetFlyingState", &[]),
+    function(635, "IsInFavorState", &[]),
+    function(636, "HasTwoHandedWeaponEquipped", &[]),
+    function(637, "IsExitingInstant", &[]),
+    function(638, "IsInFriendStatewithPlayer", &[]),
+    function(639, "GetWithinDistance", &[Form, Float]),
+    function(640, "GetActorValuePercent", &[ActorValue]),
+    function(641, "IsUnique", &[]),
+    function(642, "GetLastBumpDirection", &[]),
+    function(644, "IsInFurnitureState", &[Integer]),
+    function(645, "GetIsInjured", &[]),
+    function(646, "GetIsCrashLandRequest", &[]),
+    function(647, "GetIsHastyLandRequest", &[]),
+    function(650, "IsLinkedTo", &[Form, Form]),
+    function(651, "GetKeywordDataForCurrentLocation", &[Form]),
+    function(652, "GetInSharedCrimeFaction", &[Form]),
+    function(654, "GetBribeSuccess", &[]),
+    function(655, "GetIntimidateSuccess", &[]),
+    function(656, "GetArrestedState", &[]),
+    function(657, "GetArrestingActor", &[]),
+    function(659, "EPTemperingItemIsEnchanted", &[]),
+    function(660, "EPTemperingItemHasKeyword", &[Form]),
+    function(664, "GetReplacedItemType", &[CastingSource]),
+    function(672, "IsAttacking", &[]),
+    function(673, "IsPowerAttacking", &[]),
+    function(674, "IsLastHostileActor", &[]),
+    function(675, "GetGraphVariableInt", &[Text]),
+    function(676, "GetCurrentShoutVariation", &[]),
+    function(678, "ShouldAttackKill", &[Form]),
+    function(680, "GetActivatorHeight", &[]),
+    function(681, "EPMagic_IsAdvanceSkill", &[ActorValue]),
+    function(682, "WornHasKeyword", &[Form]),
+    function(683, "GetPathingCurrentSpeed", &[]),
+    function(684, "GetPathingCurrentSpeedAngle", &[Axis]),
+    function(691, "EPModSkillUsage_AdvanceObjectHasKeyword", &[Form]),
+    function(692, "EPModSkillUsage_IsAdvanceAction", &[Integer]),
+    function(693, "EPMagic_SpellHasKeyword", &[Form]),
+    function(694, "GetNoBleedoutRecovery", &[]),
+    function(696, "EPMagic_SpellHasSkill", &[ActorValue]),
+    function(697, "IsAttackType", &[Form]),
+    function(698, "IsAllowedToFly", &[]),
+    function(699, "HasMagicEffectKeyword", &[Form]),
+    function(700, "IsCommandedActor", &[]),
+    function(701, "IsStaggered", &[]),
+    function(702, "IsRecoiling", &[]),
+    function(703, "IsExitingInteractionQuick", &[]),
+    function(704, "IsPathing", &[]),
+    function(705, "GetShouldHelp", &[Form]),
+    function(706, "HasBoundWeaponEquipped", &[CastingSource]),
+    function(707, "GetCombatTargetHasKeyword", &[Form]),
+    function(709, "GetCombatGroupMemberCount", &[]),
+    function(710, "IsIgnoringCombat", &[]),
+    function(711, "GetLightLevel", &[]),
+    function(713, "SpellHasCastingPerk", &[Form]),
+    function(714, "IsBeingRidden", &[]),
+    function(715, "IsUndead", &[]),
+    function(716, "GetRealHoursPassed", &[]),
+    function(718, "IsUnlockedDoor", &[]),
+    function(719, "IsHostileToActor", &[Form]),
+    function(720, "GetTargetHeight", &[Form]),
+    function(721, "IsPoison", &[]),
+    function(722, "WornApparelHasKeywordCount", &[Form]),
+    function(723, "GetItemHealthPercent", &[]),
+    function(724, "EffectWasDualCast", &[]),
+    function(725, "GetKnockStateEnum", &[]),
+    function(726, "DoesNotExist", &[]),
+    function(730, "IsOnFlyingMount", &[]),
+    function(731, "CanFlyHere", &[]),
+    function(732, "IsFlyingMountPatrolQueud", &[]),
+    function(733, "IsFlyingMountFastTravelling", &[]),
+    function(734, "IsOverEncumbered", &[]),
+    function(735, "GetActorWarmth", &[]),
+];
+
+/// The condition function with the given index
+pub fn lookup(index: u16) -> Option<&'static Function> {
+    FUNCTIONS
+        .binary_search_by_key(&index, |function| function.index)
+        .ok()
+        .map(|position| &FUNCTIONS[position])
+}
+
+/// Names of the actor values parameters and functions index into, up to the resistances
+const ACTOR_VALUES: &[&str] = &[
+    "Aggression",
+    "Confidence",
+    "Energy",
+    "Morality",
+    "Mood",
+    "Assistance",
+    "OneHanded",
+    "TwoHanded",
+    "Marksman",
+    "Block",
+    "Smithing",
+    "HeavyArmor",
+    "LightArmor",
+    "Pickpocket",
+    "Lockpicking",
+    "Sneak",
+    "Alchemy",
+    "Speechcraft",
+    "Alteration",
+    "Conjuration",
+    "Destruction",
+    "Illusion",
+    "Restoration",
+    "Enchanting",
+    "Health",
+    "Magicka",
+    "Stamina",
+    "HealRate",
+    "MagickaRate",
+    "StaminaRate",
+    "SpeedMult",
+    "InventoryWeight",
+    "CarryWeight",
+    "CriticalChance",
+    "MeleeDamage",
+    "UnarmedDamage",
+    "Mass",
+    "VoicePoints",
+    "VoiceRate",
+    "DamageResist",
+    "PoisonResist",
+    "FireResist",
+    "ElectricResist",
+    "FrostResist",
+    "MagicResist",
+    "DiseaseResist",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+}
+
+impl Comparison {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Comparison::Equal => "==",
+            Comparison::NotEqual => "!=",
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ComparisonValue {
+    Float(f32),
+    Global(FormId),
+}
+
+/// What the condition function is called on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunOn {
+    Subject,
+    Target,
+    Reference(FormId),
+    CombatTarget,
+    LinkedReference,
+    QuestAlias(i32),
+    PackageData(i32),
+    EventData,
+    Unknown(u32),
+}
+
+/// A CTDA subrecord, along with the CIS1 and CIS2 strings following it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    pub comparison: Comparison,
+    /// See [`flags`]
+    pub flags: u8,
+    pub value: ComparisonValue,
+    pub function: u16,
+    /// Raw parameter values, to be read according to the function's parameter types
+    pub params: [u32; 2],
+    pub string_params: [Option<String>; 2],
+    pub run_on: RunOn,
+}
+
+impl Condition {
+    pub fn decode(bytes: &[u8]) -> Result<Self, crate::Error> {
+        let (_, (operator, value, function, params, run_on, reference, param3)) = tuple((
+            terminated(le_u8, take(3usize)),
+            le_u32,
+            terminated(le_u16, take(2usize)),
+            tuple((le_u32, le_u32)),
+            le_u32,
+            form_id,
+            le_i32,
+        ))(bytes)?;
+
+        let comparison = match operator >> 5 {
+            0 => Comparison::Equal,
+            1 => Comparison::NotEqual,
+            2 => Comparison::Greater,
+            3 => Comparison::GreaterOrEqual,
+            4 => Comparison::Less,
+            5 => Comparison::LessOrEqual,
+            other => {
+                return Err(crate::Error::CorruptOrInvalidRecord(format!(
+                    "Invalid condition operator {}",
+                    other
+                )))
+            }
+        };
+
+        let flags = operator & 0x1F;
+
+        let value = if flags & flags::USE_GLOBAL != 0 {
+            ComparisonValue::Global(FormId::from(value))
+        } else {
+            ComparisonValue::Float(f32::from_bits(value))
+        };
+
+        let run_on = match run_on {
+            0 => RunOn::Subject,
+            1 => RunOn::Target,
+            2 => RunOn::Reference(reference),
+            3 => RunOn::CombatTarget,
+            4 => RunOn::LinkedReference,
+            5 => RunOn::QuestAlias(param3),
+            6 => RunOn::PackageData(param3),
+            7 => RunOn::EventData,
+            other => RunOn::Unknown(other),
+        };
+
+        Ok(Condition {
+            comparison,
+            flags,
+            value,
+            function,
+            params: [params.0, params.1],
+            string_params: [None, None],
+            run_on,
+        })
+    }
+
+    /// Every condition of a record, in order. Records that keep conditions in several places, such as a perk's
+    /// entry points, have them all returned together
+    pub fn decode_all(record: &Record) -> Result<Vec<Self>, crate::Error> {
+        let mut conditions: Vec<Condition> = vec![];
+
+        for subrecord in record.subrecords() {
+            match &*subrecord.code {
+                b"CTDA" => conditions.push(Condition::decode(subrecord.data)?),
+                b"CIS1" | b"CIS2" => {
+                    if let Some(condition) = conditions.last_mut() {
+                        let index = if &*subrecord.code == b"CIS1" { 0 } else { 1 };
+                        condition.string_params[index] = Some(zstring(subrecord.data)?.1);
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        Ok(conditions)
+    }
+
+    pub fn is_or(&self) -> bool {
+        self.flags & flags::OR != 0
+    }
+
+    pub fn function(&self) -> Option<&'static Function> {
+        lookup(self.function)
+    }
+
+    /// The FormIDs among the parameters, the global compared against and the reference run on
+    pub fn form_ids(&self) -> Vec<FormId> {
+        let mut ids = vec![];
+
+        if let ComparisonValue::Global(global) = self.value {
+            ids.push(global);
+        }
+
+        for (index, param) in self.params.iter().enumerate() {
+            if self.param_type(index) == Some(Form) {
+                ids.push(FormId::from(*param));
+            }
+        }
+
+        if let RunOn::Reference(reference) = self.run_on {
+            ids.push(reference);
+        }
+
+        ids
+    }
+
+    /// How a parameter is read, after the alias and package data flags are taken into account
+    fn param_type(&self, index: usize) -> Option<ParamType> {
+        let param_type = *self.function()?.params.get(index)?;
+
+        Some(match param_type {
+            Form if self.flags & flags::USE_ALIASES != 0 => Alias,
+            Form if self.flags & flags::USE_PACKAGE_DATA != 0 => PackageData,
+            other => other,
+        })
+    }
+
+    /// A readable expression such as `GetIsID(Player) == 1`, naming FormIDs with `name` where it knows them
+    pub fn render<F>(&self, name: F) -> String
+    where
+        F: Fn(FormId) -> Option<String>,
+    {
+        let form = |id: FormId| name(id).or_else(|| default_name(id)).unwrap_or_else(|| id.to_string());
+
+        let target = match self.run_on {
+            RunOn::Subject => String::new(),
+            RunOn::Target => String::from("Target."),
+            RunOn::Reference(reference) => format!("{}.", form(reference)),
+            RunOn::CombatTarget => String::from("CombatTarget."),
+            RunOn::LinkedReference => String::from("LinkedRef."),
+            RunOn::QuestAlias(alias) => format!("Alias[{}].", alias),
+            RunOn::PackageData(index) => format!("PackageData[{}].", index),
+            RunOn::EventData => String::from("EventData."),
+            RunOn::Unknown(run_on) => format!("RunOn{}.", run_on),
+        };
+
+        let (function, params) = match self.function() {
+            Some(function) => {
+                let params = (0..function.params.len())
+                    .map(|index| {
+                        let param = self.params[index];
+
+                        match self.param_type(index).unwrap_or(Integer) {
+                            Form => form(FormId::from(param)),
+                            Integer => (param as i32).to_string(),
+                            Float => f32::from_bits(param).to_string(),
+                            Axis => char::from(param as u8).to_string(),
+                            ActorValue => ACTOR_VALUES
+                                .get(param as usize)
+                                .map_or_else(|| format!("ActorValue{}", param), |name| String::from(*name)),
+                            Sex => String::from(if param == 0 { "Male" } else { "Female" }),
+                            CastingSource => String::from(match param {
+                                0 => "Left",
+                                1 => "Right",
+                                2 => "Voice",
+                                _ => "Instant",
+                            }),
+                            Alias => format!("Alias[{}]", param as i32),
+                            PackageData => format!("PackageData[{}]", param as i32),
+                            Text => format!("{:?}", self.string_params[index].as_deref().unwrap_or_default()),
+                        }
+                    })
+                    .collect::<Vec<_>>();
+
+                (String::from(function.name), params)
+            }
+            None => (
+                format!("Function{}", self.function),
+                self.params.iter().map(|param| format!("{:#010X}", param)).collect(),
+            ),
+        };
+
+        let value = match self.value {
+            ComparisonValue::Float(value) => value.to_string(),
+            ComparisonValue::Global(global) => form(global),
+        };
+
+        format!(
+            "{}{}({}) {} {}",
+            target,
+            function,
+            params.join(", "),
+            self.comparison.as_str(),
+            value
+        )
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.render(|_| None))
+    }
+}
+
+/// Conditions joined by AND and OR, as the game evaluates them: OR binds tighter than AND, so runs of OR
+/// terms are parenthesized when ANDed with anything else
+pub fn render_all<F>(conditions: &[Condition], name: F) -> String
+where
+    F: Fn(FormId) -> Option<String>,
+{
+    let mut runs: Vec<Vec<String>> = vec![];
+
+    for (index, condition) in conditions.iter().enumerate() {
+        let rendered = condition.render(&name);
+
+        match runs.last_mut() {
+            Some(run) if conditions[index - 1].is_or() => run.push(rendered),
+            _ => runs.push(vec![rendered]),
+        }
+    }
+
+    let parenthesize = runs.len() > 1;
+
+    runs.into_iter()
+        .map(|run| match run.len() {
+            1 => run.join(""),
+            _ if parenthesize => format!("({})", run.join(" OR ")),
+            _ => run.join(" OR "),
+        })
+        .collect::<Vec<_>>()
+        .join(" AND ")
+}
+
+/// Names for the hardcoded player FormIDs
+fn default_name(id: FormId) -> Option<String> {
+    match *id {
+        0x07 => Some(String::from("Player")),
+        0x14 => Some(String::from("PlayerRef")),
+        _ => None,
+    }
+}
+
+/// Byte offsets of the FormIDs in a CTDA, which depend on its function and flags. `None` if the function isn't in
+/// the function table, so its parameters could be FormIDs or not
+pub(crate) fn form_id_offsets(bytes: &[u8]) -> Option<Vec<usize>> {
+    let condition = Condition::decode(bytes).ok()?;
+    condition.function()?;
+
+    let mut offsets = vec![];
+
+    if let ComparisonValue::Global(_) = condition.value {
+        offsets.push(4);
+    }
+
+    for index in 0..2 {
+        if condition.param_type(index) == Some(Form) {
+            offsets.push(12 + index * 4);
+        }
+    }
+
+    if let RunOn::Reference(_) = condition.run_on {
+        offsets.push(24);
+    }
+
+    Some(offsets)
+}
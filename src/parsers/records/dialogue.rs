@@ -0,0 +1,239 @@
+use crate::parsers::{
+    common::{form_id, lstring, zstring, FormId, LString, TypeCode},
+    records::{condition::Condition, vmad::Vmad, Record},
+};
+
+use nom::{
+    bytes::complete::take,
+    combinator::map,
+    number::complete::{le_f32, le_i32, le_u16, le_u32, le_u8},
+    sequence::{terminated, tuple},
+};
+
+/// A DLBR record, grouping topics under a quest
+#[derive(Debug, Clone, PartialEq)]
+pub struct Branch {
+    pub id: FormId,
+    pub editor_id: Option<String>,
+    pub quest: FormId,
+    pub category: u32,
+    pub flags: u32,
+    pub starting_topic: FormId,
+}
+
+impl Branch {
+    pub fn decode(record: &Record) -> Result<Self, crate::Error> {
+        expect_code(record, b"DLBR")?;
+
+        let mut branch = Branch {
+            id: record.header.id,
+            editor_id: record.header.editor_id.clone(),
+            quest: FormId::default(),
+            category: 0,
+            flags: 0,
+            starting_topic: FormId::default(),
+        };
+
+        for subrecord in record.subrecords() {
+            let bytes = subrecord.data;
+
+            match &*subrecord.code {
+                b"QNAM" => branch.quest = form_id(bytes)?.1,
+                b"TNAM" => branch.category = le_u32(bytes)?.1,
+                b"DNAM" => branch.flags = le_u32(bytes)?.1,
+                b"SNAM" => branch.starting_topic = form_id(bytes)?.1,
+                _ => (),
+            }
+        }
+
+        Ok(branch)
+    }
+}
+
+/// A DIAL record
+#[derive(Debug, Clone, PartialEq)]
+pub struct Topic {
+    pub id: FormId,
+    pub editor_id: Option<String>,
+    /// What the player says to start the topic
+    pub prompt: Option<LString>,
+    pub priority: f32,
+    pub branch: FormId,
+    pub quest: FormId,
+    pub flags: u8,
+    pub category: u8,
+    pub subtype: u16,
+    pub subtype_name: Option<TypeCode>,
+}
+
+impl Topic {
+    pub fn decode(record: &Record, localized: bool) -> Result<Self, crate::Error> {
+        expect_code(record, b"DIAL")?;
+
+        let mut topic = Topic {
+            id: record.header.id,
+            editor_id: record.header.editor_id.clone(),
+            prompt: None,
+            priority: 0.0,
+            branch: FormId::default(),
+            quest: FormId::default(),
+            flags: 0,
+            category: 0,
+            subtype: 0,
+            subtype_name: None,
+        };
+
+        for subrecord in record.subrecords() {
+            let bytes = subrecord.data;
+
+            match &*subrecord.code {
+                b"FULL" => topic.prompt = Some(lstring(bytes, localized)?.1),
+                b"PNAM" => topic.priority = le_f32(bytes)?.1,
+                b"BNAM" => topic.branch = form_id(bytes)?.1,
+                b"QNAM" => topic.quest = form_id(bytes)?.1,
+                b"DATA" => {
+                    let (_, (flags, category, subtype)) = tuple((le_u8, le_u8, le_u16))(bytes)?;
+                    topic.flags = flags;
+                    topic.category = category;
+                    topic.subtype = subtype;
+                }
+                b"SNAM" => topic.subtype_name = Some(map(le_u32, TypeCode::from)(bytes)?.1),
+                _ => (),
+            }
+        }
+
+        Ok(topic)
+    }
+}
+
+/// One line of an INFO, from TRDT and the subrecords following it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub number: u8,
+    pub emotion: u32,
+    pub emotion_value: u32,
+    pub sound: FormId,
+    pub text: Option<LString>,
+    pub notes: Option<String>,
+    pub edits: Option<String>,
+}
+
+/// An INFO record, one of the responses to a topic
+#[derive(Debug, Clone, PartialEq)]
+pub struct Info {
+    pub id: FormId,
+    pub editor_id: Option<String>,
+    pub flags: u16,
+    pub reset_hours: u16,
+    /// The INFO this one follows in its topic, from PNAM
+    pub previous: FormId,
+    /// An INFO whose responses this one reuses
+    pub shared: FormId,
+    /// Topics the player can choose from after this, from TCLT
+    pub linked_topics: Vec<FormId>,
+    /// Overrides the topic's prompt
+    pub prompt: Option<LString>,
+    pub speaker: FormId,
+    pub responses: Vec<Response>,
+    pub conditions: Vec<Condition>,
+    /// Scripts from the INFO's VMAD, its fragment scripts included
+    pub scripts: Vec<String>,
+}
+
+impl Info {
+    pub fn decode(record: &Record, localized: bool) -> Result<Self, crate::Error> {
+        expect_code(record, b"INFO")?;
+
+        let mut info = Info {
+            id: record.header.id,
+            editor_id: record.header.editor_id.clone(),
+            flags: 0,
+            reset_hours: 0,
+            previous: FormId::default(),
+            shared: FormId::default(),
+            linked_topics: vec![],
+            prompt: None,
+            speaker: FormId::default(),
+            responses: vec![],
+            conditions: vec![],
+            scripts: vec![],
+        };
+
+        for subrecord in record.subrecords() {
+            let bytes = subrecord.data;
+
+            match &*subrecord.code {
+                // The dialogue is still worth showing when its script data can't be read
+                b"VMAD" => match Vmad::parse(bytes, &record.header.code) {
+                    Ok(vmad) => info.scripts = vmad.script_names().into_iter().map(String::from).collect(),
+                    Err(err) => log::debug!("Skipping VMAD of INFO {}: {}", record.header.id, err),
+                },
+                b"ENAM" => {
+                    let (_, (flags, reset_hours)) = tuple((le_u16, le_u16))(bytes)?;
+                    info.flags = flags;
+                    info.reset_hours = reset_hours;
+                }
+                b"PNAM" => info.previous = form_id(bytes)?.1,
+                b"DNAM" => info.shared = form_id(bytes)?.1,
+                b"TCLT" => info.linked_topics.push(form_id(bytes)?.1),
+                b"TRDT" => info.responses.push(response_data(bytes)?.1),
+                b"NAM1" => {
+                    if let Some(response) = info.responses.last_mut() {
+                        response.text = Some(lstring(bytes, localized)?.1);
+                    }
+                }
+                b"NAM2" => {
+                    if let Some(response) = info.responses.last_mut() {
+                        response.notes = Some(zstring(bytes)?.1);
+                    }
+                }
+                b"NAM3" => {
+                    if let Some(response) = info.responses.last_mut() {
+                        response.edits = Some(zstring(bytes)?.1);
+                    }
+                }
+                b"CTDA" => info.conditions.push(Condition::decode(bytes)?),
+                b"CIS1" | b"CIS2" => {
+                    if let Some(condition) = info.conditions.last_mut() {
+                        let index = if &*subrecord.code == b"CIS1" { 0 } else { 1 };
+                        condition.string_params[index] = Some(zstring(bytes)?.1);
+                    }
+                }
+                b"RNAM" => info.prompt = Some(lstring(bytes, localized)?.1),
+                b"ANAM" => info.speaker = form_id(bytes)?.1,
+                _ => (),
+            }
+        }
+
+        Ok(info)
+    }
+}
+
+/// TRDT: emotion, emotion value, an unused value, the response number and the sound file. The text comes after
+fn response_data(bytes: &[u8]) -> crate::IResult<&[u8], Response> {
+    map(
+        tuple((le_u32, le_u32, le_i32, terminated(le_u8, take(3usize)), form_id)),
+        |(emotion, emotion_value, _, number, sound)| Response {
+            number,
+            emotion,
+            emotion_value,
+            sound,
+            text: None,
+            notes: None,
+            edits: None,
+        },
+    )(bytes)
+}
+
+fn expect_code(record: &Record, code: &[u8; 4]) -> Result<(), crate::Error> {
+    if &*record.header.code == code {
+        Ok(())
+    } else {
+        Err(crate::Error::CorruptOrInvalidRecord(format!(
+            "{} {} is not a {} record",
+            record.header.code,
+            record.header.id,
+            TypeCode::from(*code)
+        )))
+    }
+}
@@ -9,7 +9,7 @@ use nom::{
     sequence::tuple,
 };
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct FileHeaderData {
     pub hedr: Hedr,
     pub author: Option<String>,
@@ -39,7 +39,15 @@ pub(super) fn data(bytes: &[u8]) -> crate::IResult<&[u8], FileHeaderData> {
                 record_data.description = Some(zstring(bytes)?.1);
             }
             "MAST" => {
-                record_data.masters = many0(mast)(bytes)?.1;
+                record_data.masters.push(MasterFile {
+                    name: zstring(bytes)?.1,
+                    tag: 0,
+                });
+            }
+            "DATA" => {
+                if let Some(master) = record_data.masters.last_mut() {
+                    master.tag = le_u64(bytes)?.1;
+                }
             }
             "ONAM" => {
                 record_data.overrides = many0(form_id)(bytes)?.1;
@@ -57,7 +65,7 @@ pub(super) fn data(bytes: &[u8]) -> crate::IResult<&[u8], FileHeaderData> {
     Ok((bytes, record_data))
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct Hedr {
     pub version: f32,
     pub num_records: i32,
@@ -74,12 +82,8 @@ fn hedr(bytes: &[u8]) -> crate::IResult<&[u8], Hedr> {
     })(bytes)
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct MasterFile {
     pub name: String,
     pub tag: u64,
 }
-
-fn mast(bytes: &[u8]) -> crate::IResult<&[u8], MasterFile> {
-    map(tuple((zstring, le_u64)), |(name, tag)| MasterFile { name, tag })(bytes)
-}
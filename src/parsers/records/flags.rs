@@ -1,11 +1,15 @@
 use std::convert::{Infallible, TryFrom, TryInto};
 
-use bitflags::{bitflags, BitFlags};
+use bitflags::bitflags;
+
+pub trait Flags: TryFrom<u32> + TryInto<u32> + Default + Copy + std::fmt::Debug {
+    fn bits(&self) -> u32;
+
+    /// The known flags among the given bits, dropping the rest
+    fn from_bits_truncate(bits: u32) -> Self;
 
-pub trait Flags: BitFlags<u32> + TryFrom<u32> + TryInto<u32> + Default {
     fn test(&self, value: u32) -> bool {
-        let flags = value.try_into().or(Err(crate::Error::Unexpected)).unwrap();
-        self.contains(flags)
+        self.bits() & value == value
     }
 }
 
@@ -57,7 +61,15 @@ impl TryInto<u32> for RecordFlags {
     }
 }
 
-impl Flags for RecordFlags {}
+impl Flags for RecordFlags {
+    fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    fn from_bits_truncate(bits: u32) -> Self {
+        RecordFlags::from_bits_truncate(bits)
+    }
+}
 
 bitflags! {
     pub struct PluginFlags: u32 {
@@ -89,4 +101,12 @@ impl TryInto<u32> for PluginFlags {
     }
 }
 
-impl Flags for PluginFlags {}
+impl Flags for PluginFlags {
+    fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    fn from_bits_truncate(bits: u32) -> Self {
+        PluginFlags::from_bits_truncate(bits)
+    }
+}
@@ -0,0 +1,157 @@
+use crate::parsers::{
+    common::{form_id, FormId},
+    records::Record,
+};
+
+use nom::{
+    bytes::complete::take,
+    combinator::map,
+    multi::{count, many0},
+    number::complete::{le_f32, le_i8, le_u16, le_u32, le_u8},
+    sequence::{terminated, tuple},
+};
+
+/// Vertices per side of a cell's landscape grid. Neighbouring cells share their edge vertices
+pub const GRID_SIZE: usize = 33;
+/// Heights are stored in units of this many game units
+pub const HEIGHT_SCALE: f32 = 8.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BaseTexture {
+    /// LTEX, or null for the default texture
+    pub texture: FormId,
+    /// 0 to 3: bottom left, bottom right, top left, top right
+    pub quadrant: u8,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextureLayer {
+    pub texture: FormId,
+    pub quadrant: u8,
+    pub layer: u16,
+    /// Vertex position within the quadrant's 17×17 grid, with the opacity of the layer there
+    pub opacities: Vec<(u16, f32)>,
+}
+
+/// A decoded LAND record. Vertex data is row-major, starting from the south-west corner of the cell
+#[derive(Debug, Clone, PartialEq)]
+pub struct Landscape {
+    pub id: FormId,
+    pub flags: u32,
+    /// Absolute heights in game units
+    pub heights: Option<Vec<f32>>,
+    pub normals: Option<Vec<[i8; 3]>>,
+    pub colors: Option<Vec<[u8; 3]>>,
+    pub base_textures: Vec<BaseTexture>,
+    pub layers: Vec<TextureLayer>,
+}
+
+impl Landscape {
+    pub fn decode(record: &Record) -> Result<Self, crate::Error> {
+        if &*record.header.code != b"LAND" {
+            return Err(crate::Error::CorruptOrInvalidRecord(format!(
+                "{} {} is not a LAND record",
+                record.header.code, record.header.id
+            )));
+        }
+
+        let mut landscape = Landscape {
+            id: record.header.id,
+            flags: 0,
+            heights: None,
+            normals: None,
+            colors: None,
+            base_textures: vec![],
+            layers: vec![],
+        };
+
+        for subrecord in record.subrecords() {
+            let bytes = subrecord.data;
+
+            match &*subrecord.code {
+                b"DATA" => {
+                    landscape.flags = le_u32(bytes)?.1;
+                }
+                b"VHGT" => {
+                    landscape.heights = Some(heights(bytes)?.1);
+                }
+                b"VNML" => {
+                    landscape.normals = Some(
+                        count(
+                            map(tuple((le_i8, le_i8, le_i8)), |(x, y, z)| [x, y, z]),
+                            GRID_SIZE * GRID_SIZE,
+                        )(bytes)?
+                        .1,
+                    );
+                }
+                b"VCLR" => {
+                    landscape.colors = Some(
+                        count(
+                            map(tuple((le_u8, le_u8, le_u8)), |(r, g, b)| [r, g, b]),
+                            GRID_SIZE * GRID_SIZE,
+                        )(bytes)?
+                        .1,
+                    );
+                }
+                b"BTXT" => {
+                    landscape.base_textures.push(
+                        map(tuple((form_id, le_u8)), |(texture, quadrant)| BaseTexture {
+                            texture,
+                            quadrant,
+                        })(bytes)?
+                        .1,
+                    );
+                }
+                b"ATXT" => {
+                    landscape.layers.push(
+                        map(
+                            tuple((form_id, terminated(le_u8, le_u8), le_u16)),
+                            |(texture, quadrant, layer)| TextureLayer {
+                                texture,
+                                quadrant,
+                                layer,
+                                opacities: vec![],
+                            },
+                        )(bytes)?
+                        .1,
+                    );
+                }
+                b"VTXT" => {
+                    if let Some(layer) = landscape.layers.last_mut() {
+                        layer.opacities = many0(tuple((terminated(le_u16, take(2usize)), le_f32)))(bytes)?.1;
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        Ok(landscape)
+    }
+
+    /// Height of a vertex, counting from the south-west corner
+    pub fn height(&self, x: usize, y: usize) -> Option<f32> {
+        self.heights.as_ref()?.get(y * GRID_SIZE + x).copied()
+    }
+}
+
+/// VHGT holds a base height followed by the change from one vertex to the next. The first vertex of each row is
+/// relative to the first vertex of the row below, and the others to the vertex before them
+fn heights(bytes: &[u8]) -> crate::IResult<&[u8], Vec<f32>> {
+    let (bytes, (offset, gradients)) = tuple((le_f32, count(le_i8, GRID_SIZE * GRID_SIZE)))(bytes)?;
+
+    let mut heights = Vec::with_capacity(GRID_SIZE * GRID_SIZE);
+    let mut row_start = offset;
+
+    for row in gradients.chunks_exact(GRID_SIZE) {
+        row_start += row[0] as f32;
+        let mut height = row_start;
+        heights.push(height * HEIGHT_SCALE);
+
+        for gradient in &row[1..] {
+            height += *gradient as f32;
+            heights.push(height * HEIGHT_SCALE);
+        }
+    }
+
+    Ok((bytes, heights))
+}
@@ -0,0 +1,209 @@
+use crate::parsers::{
+    common::{form_id, FormId, TypeCode},
+    records::Record,
+};
+
+use nom::{
+    combinator::map,
+    number::complete::{le_f32, le_u16, le_u32, le_u8},
+    sequence::tuple,
+};
+
+/// Leveled item, NPC and spell lists
+pub const CODES: &[[u8; 4]] = &[*b"LVLI", *b"LVLN", *b"LVSP"];
+
+/// Subrecords making up the list itself, which `LeveledList::write` replaces
+const LIST_SUBRECORDS: &[[u8; 4]] = &[*b"LVLD", *b"LVLF", *b"LVLG", *b"LLCT", *b"LVLO", *b"COED"];
+
+/// LVLF flags
+pub mod flags {
+    pub const CALCULATE_FROM_ALL_LEVELS: u8 = 0x01;
+    pub const CALCULATE_FOR_EACH_ITEM: u8 = 0x02;
+    pub const USE_ALL: u8 = 0x04;
+    pub const SPECIAL_LOOT: u8 = 0x08;
+}
+
+pub fn is_leveled_list(code: &TypeCode) -> bool {
+    CODES.iter().any(|leveled| **code == *leveled)
+}
+
+/// A COED following an entry: the owner and condition of the items it gives
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExtraData {
+    /// An NPC or a faction
+    pub owner: FormId,
+    /// A global variable for NPC owners, the required rank for faction owners
+    pub global_or_rank: u32,
+    pub condition: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub level: u16,
+    pub unknown: u16,
+    pub reference: FormId,
+    pub count: u16,
+    pub unknown2: u16,
+    pub extra: Option<ExtraData>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LeveledList {
+    pub id: FormId,
+    pub code: TypeCode,
+    pub editor_id: Option<String>,
+    /// Percent chance of the list giving nothing
+    pub chance_none: u8,
+    pub flags: u8,
+    /// A global variable overriding the chance none, for leveled item lists
+    pub global: Option<FormId>,
+    /// In file order, which the Creation Kit keeps sorted by level
+    pub entries: Vec<Entry>,
+}
+
+impl LeveledList {
+    pub fn decode(record: &Record) -> Result<Self, crate::Error> {
+        if !is_leveled_list(&record.header.code) {
+            return Err(crate::Error::CorruptOrInvalidRecord(format!(
+                "{} {} is not a leveled list",
+                record.header.code, record.header.id
+            )));
+        }
+
+        let mut list = LeveledList {
+            id: record.header.id,
+            code: record.header.code.clone(),
+            editor_id: record.header.editor_id.clone(),
+            chance_none: 0,
+            flags: 0,
+            global: None,
+            entries: vec![],
+        };
+
+        for subrecord in record.subrecords() {
+            let bytes = subrecord.data;
+
+            match &*subrecord.code {
+                b"LVLD" => {
+                    list.chance_none = le_u8(bytes)?.1;
+                }
+                b"LVLF" => {
+                    list.flags = le_u8(bytes)?.1;
+                }
+                b"LVLG" => {
+                    list.global = Some(form_id(bytes)?.1);
+                }
+                b"LVLO" => {
+                    list.entries.push(entry(bytes)?.1);
+                }
+                b"COED" => {
+                    let entry = list.entries.last_mut().ok_or_else(|| {
+                        crate::Error::CorruptOrInvalidRecord(format!(
+                            "{} {} has a COED before any LVLO",
+                            record.header.code, record.header.id
+                        ))
+                    })?;
+                    entry.extra = Some(extra_data(bytes)?.1);
+                }
+                _ => (),
+            }
+        }
+
+        Ok(list)
+    }
+
+    /// Every FormID the list refers to, rewritten with `remap`
+    pub fn remap_form_ids<F>(&mut self, mut remap: F)
+    where
+        F: FnMut(FormId) -> FormId,
+    {
+        self.global = self.global.map(&mut remap);
+
+        for entry in &mut self.entries {
+            entry.reference = remap(entry.reference);
+
+            if let Some(extra) = &mut entry.extra {
+                extra.owner = remap(extra.owner);
+            }
+        }
+    }
+
+    /// Replace the list subrecords of a leveled list record with this list's, leaving the others, such as the
+    /// bounds and models, alone
+    pub fn write(&self, record: &mut Record) -> Result<(), crate::Error> {
+        if self.entries.len() > u8::MAX as usize {
+            return Err(crate::Error::CorruptOrInvalidRecord(format!(
+                "{} {} has {} entries, more than a leveled list can hold",
+                self.code,
+                self.id,
+                self.entries.len()
+            )));
+        }
+
+        let mut list = vec![
+            (TypeCode::from(*b"LVLD"), vec![self.chance_none]),
+            (TypeCode::from(*b"LVLF"), vec![self.flags]),
+        ];
+
+        if let Some(global) = self.global {
+            list.push((TypeCode::from(*b"LVLG"), global.to_le_bytes().to_vec()));
+        }
+
+        list.push((TypeCode::from(*b"LLCT"), vec![self.entries.len() as u8]));
+
+        for entry in &self.entries {
+            let mut data = vec![];
+            data.extend_from_slice(&entry.level.to_le_bytes());
+            data.extend_from_slice(&entry.unknown.to_le_bytes());
+            data.extend_from_slice(&entry.reference.to_le_bytes());
+            data.extend_from_slice(&entry.count.to_le_bytes());
+            data.extend_from_slice(&entry.unknown2.to_le_bytes());
+            list.push((TypeCode::from(*b"LVLO"), data));
+
+            if let Some(extra) = &entry.extra {
+                let mut data = vec![];
+                data.extend_from_slice(&extra.owner.to_le_bytes());
+                data.extend_from_slice(&extra.global_or_rank.to_le_bytes());
+                data.extend_from_slice(&extra.condition.to_le_bytes());
+                list.push((TypeCode::from(*b"COED"), data));
+            }
+        }
+
+        record.edit_subrecords(|subrecords| {
+            let position = subrecords
+                .iter()
+                .position(|(code, _)| LIST_SUBRECORDS.contains(code))
+                .unwrap_or(subrecords.len());
+
+            subrecords.retain(|(code, _)| !LIST_SUBRECORDS.contains(code));
+            subrecords.splice(position..position, list);
+        });
+
+        Ok(())
+    }
+}
+
+fn entry(bytes: &[u8]) -> crate::IResult<&[u8], Entry> {
+    map(
+        tuple((le_u16, le_u16, form_id, le_u16, le_u16)),
+        |(level, unknown, reference, count, unknown2)| Entry {
+            level,
+            unknown,
+            reference,
+            count,
+            unknown2,
+            extra: None,
+        },
+    )(bytes)
+}
+
+fn extra_data(bytes: &[u8]) -> crate::IResult<&[u8], ExtraData> {
+    map(
+        tuple((form_id, le_u32, le_f32)),
+        |(owner, global_or_rank, condition)| ExtraData {
+            owner,
+            global_or_rank,
+            condition,
+        },
+    )(bytes)
+}
@@ -1,12 +1,19 @@
+pub mod condition;
+pub mod dialogue;
 pub mod file_header;
 pub mod flags;
+pub mod land;
+pub mod leveled;
+pub mod navmesh;
+pub mod quest;
+pub mod reference;
+pub mod vmad;
 
 use std::{fmt::Debug, io::Read};
 
-use crate::parsers::common::{subrecords, FormId, TypeCode};
+use crate::parsers::common::{subrecords, write_subrecord, zstring, FormId, Subrecord, TypeCode};
 use flags::{Flags, RecordFlags};
 
-use byteorder::{LittleEndian, ReadBytesExt};
 use flate2::read::ZlibDecoder;
 use nom::{
     bytes::complete::take,
@@ -18,34 +25,98 @@ use nom::{
 pub type FileHeaderRecord = GenericRecord<flags::PluginFlags>;
 pub type Record = GenericRecord<flags::RecordFlags>;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct GenericRecord<Flags>
 where
     Flags: Debug,
 {
     pub header: RecordHeader<Flags>,
     pub data: RecordData,
+    /// The data as the plugin stored it, size prefix included, if the record was read compressed. The writer reuses
+    /// it for records whose data is unchanged
+    pub compressed: Option<Vec<u8>>,
+}
+
+impl Record {
+    /// Subrecords of the (decompressed) record data, in file order
+    pub fn subrecords(&self) -> Vec<Subrecord<'_>> {
+        match &self.data {
+            RecordData::Unknown(bytes) => subrecords(bytes).map(|(_, subrecords)| subrecords).unwrap_or_default(),
+            _ => vec![],
+        }
+    }
+
+    /// First subrecord with the given code
+    pub fn subrecord(&self, code: &[u8; 4]) -> Option<Subrecord<'_>> {
+        self.subrecords().into_iter().find(|subrecord| &*subrecord.code == code)
+    }
+
+    /// Rewrite the record's subrecords in place. Apart from the editor ID, the header, including its flags, is
+    /// left untouched
+    pub fn edit_subrecords<F>(&mut self, edit: F)
+    where
+        F: FnOnce(&mut Vec<(TypeCode, Vec<u8>)>),
+    {
+        let mut owned = self
+            .subrecords()
+            .into_iter()
+            .map(|subrecord| (subrecord.code, subrecord.data.to_vec()))
+            .collect::<Vec<_>>();
+
+        edit(&mut owned);
+
+        self.header.editor_id = owned
+            .first()
+            .filter(|(code, _)| &**code == b"EDID")
+            .and_then(|(_, data)| zstring(data).ok())
+            .map(|(_, editor_id)| editor_id);
+
+        let mut bytes = vec![];
+
+        for (code, data) in &owned {
+            write_subrecord(&mut bytes, code, data);
+        }
+
+        self.data = RecordData::Unknown(bytes);
+        self.compressed = None;
+    }
 }
 
 pub(crate) fn record(bytes: &[u8]) -> crate::IResult<&[u8], Record> {
     let (bytes, mut header) = header::<flags::RecordFlags>(bytes)?;
-    let (bytes, (editor_id, data)) = data::<flags::RecordFlags>(bytes, &header)?;
+    let (bytes, (editor_id, data, compressed)) = data::<flags::RecordFlags>(bytes, &header)?;
 
-    log::debug!("Loaded editor_id: {}", editor_id);
+    if let Some(editor_id) = &editor_id {
+        log::debug!("Loaded editor_id: {}", editor_id);
+    }
 
-    header.editor_id = Some(editor_id);
+    header.editor_id = editor_id;
 
-    Ok((bytes, (Record { header, data })))
+    Ok((
+        bytes,
+        (Record {
+            header,
+            data,
+            compressed,
+        }),
+    ))
 }
 
 pub(crate) fn file_header_record(bytes: &[u8]) -> crate::IResult<&[u8], FileHeaderRecord> {
     let (bytes, header) = header::<flags::PluginFlags>(bytes)?;
-    let (bytes, data) = data::<flags::PluginFlags>(bytes, &header)?;
-
-    Ok((bytes, FileHeaderRecord { header, data: data.1 }))
+    let (bytes, (_, data, _)) = data::<flags::PluginFlags>(bytes, &header)?;
+
+    Ok((
+        bytes,
+        FileHeaderRecord {
+            header,
+            data,
+            compressed: None,
+        },
+    ))
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct RecordHeader<F>
 where
     F: Debug,
@@ -53,6 +124,8 @@ where
     pub code: TypeCode,
     pub size: u32,
     pub flags: F,
+    /// Flag bits `flags` has no name for, kept so they're written back unchanged
+    pub unknown_flags: u32,
     pub id: FormId,
     pub timestamp: u16,
     pub vc_info: u16,
@@ -70,7 +143,8 @@ where
         |(code, size, flags, id, timestamp, vc_info, version, unknown)| RecordHeader::<F> {
             code: code.into(),
             size,
-            flags: F::try_from(flags).unwrap_or(F::default()),
+            flags: F::from_bits_truncate(flags),
+            unknown_flags: flags & !F::from_bits_truncate(flags).bits(),
             id: id.into(),
             timestamp,
             vc_info,
@@ -81,13 +155,16 @@ where
     )(bytes)
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum RecordData {
     FileHeader(file_header::FileHeaderData),
     Unknown(Vec<u8>),
 }
 
-fn data<'a, F>(bytes: &'a [u8], header: &RecordHeader<F>) -> crate::IResult<&'a [u8], (String, RecordData)>
+/// The editor ID, the (decompressed) data, and the data as stored if it was compressed
+type Data = (Option<String>, RecordData, Option<Vec<u8>>);
+
+fn data<'a, F>(bytes: &'a [u8], header: &RecordHeader<F>) -> crate::IResult<&'a [u8], Data>
 where
     F: Flags,
 {
@@ -96,46 +173,47 @@ where
     match header.code.to_string().as_ref() {
         "TES4" => {
             let (_, data) = file_header::data(data_bytes)?;
-            Ok((bytes, (String::new(), RecordData::FileHeader(data))))
+            Ok((bytes, (None, RecordData::FileHeader(data), None)))
+        }
+        _ if header.flags.test(RecordFlags::COMPRESSED.bits()) => {
+            let data = decompress(data_bytes).map_err(nom::Err::Failure)?;
+            let (_, editor_id) = editor_id(&data)?;
+            Ok((bytes, (editor_id, RecordData::Unknown(data), Some(data_bytes.to_vec()))))
         }
         _ => {
-            let (_, (editor_id, data)) = unknown_data(data_bytes, header)?;
-            Ok((bytes, (editor_id, RecordData::Unknown(data))))
+            let (_, editor_id) = editor_id(data_bytes)?;
+            Ok((bytes, (editor_id, RecordData::Unknown(data_bytes.to_vec()), None)))
         }
     }
 }
 
-fn unknown_data<'a, F>(bytes: &'a [u8], header: &RecordHeader<F>) -> crate::IResult<&'a [u8], (String, Vec<u8>)>
-where
-    F: Flags,
-{
-    let mut record_data = bytes.to_vec();
-
-    if header.flags.test(RecordFlags::COMPRESSED.bits()) {
-        record_data = decompress(record_data, header.size).unwrap();
-    }
+fn editor_id(bytes: &[u8]) -> crate::IResult<&[u8], Option<String>> {
+    let (_, subrecords) = subrecords(bytes)?;
 
-    let (_, subrecords) = subrecords(record_data.as_slice())?;
-
-    if let Some(first_subrecord) = subrecords.first() {
-        if first_subrecord.code.to_string().as_str() == "EDID" {
-            let editor_id = String::from_utf8(first_subrecord.data.to_vec()).unwrap();
-            Ok((&[], (editor_id, record_data)))
-        } else {
-            Ok((&[], (String::from("Missing EditorID"), record_data)))
+    match subrecords.first() {
+        Some(first_subrecord) if first_subrecord.code.to_string().as_str() == "EDID" => {
+            let (_, editor_id) = zstring(first_subrecord.data)?;
+            Ok((&[], Some(editor_id)))
         }
-    } else {
-        Ok((&[], (String::from("Compressed Record"), record_data)))
+        _ => Ok((&[], None)),
     }
 }
 
-fn decompress(mut bytes: Vec<u8>, size: u32) -> Result<Vec<u8>, crate::Error> {
-    let decompressed_size = bytes.drain(0..4).as_slice().read_u32::<LittleEndian>().unwrap();
-    let decoder = ZlibDecoder::new(bytes.as_slice());
-    let mut decompressed = vec![];
+/// Compressed record data is prefixed with its decompressed size, which the zlib stream must match
+fn decompress(bytes: &[u8]) -> Result<Vec<u8>, crate::Error> {
+    let (compressed, decompressed_size) = le_u32(bytes)?;
+    let mut decompressed = Vec::with_capacity(decompressed_size as usize);
 
     log::debug!("Decompressing record, expecting {} bytes", decompressed_size);
-    decoder.take(size as u64).read_to_end(&mut decompressed).unwrap();
+    ZlibDecoder::new(compressed).read_to_end(&mut decompressed)?;
+
+    if decompressed.len() != decompressed_size as usize {
+        return Err(crate::Error::CorruptOrInvalidRecord(format!(
+            "compressed data holds {} bytes, not the {} its size prefix gives",
+            decompressed.len(),
+            decompressed_size
+        )));
+    }
 
     Ok(decompressed)
 }
@@ -0,0 +1,296 @@
+use crate::parsers::{
+    common::{form_id, FormId, GridCoord},
+    records::Record,
+};
+
+use nom::{
+    combinator::map,
+    multi::{count, length_count},
+    number::complete::{le_f32, le_i16, le_u16, le_u32, le_u8},
+    sequence::tuple,
+};
+
+/// Triangle flags marking an edge as leading to another navmesh, in which case the edge holds an index into
+/// `Navmesh::edge_links` rather than a neighbouring triangle
+pub const EDGE_LINK_FLAGS: [u16; 3] = [0x0001, 0x0002, 0x0004];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NavmeshParent {
+    Worldspace { world: FormId, grid: GridCoord },
+    Cell(FormId),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Triangle {
+    pub vertices: [i16; 3],
+    /// Neighbouring triangle across edges 0-1, 1-2 and 2-0, or -1 for none
+    pub edges: [i16; 3],
+    pub flags: u16,
+    pub cover_flags: u16,
+}
+
+impl Triangle {
+    /// Index into `Navmesh::edge_links` for an edge leading to another navmesh
+    pub fn edge_link(&self, edge: usize) -> Option<usize> {
+        if self.flags & EDGE_LINK_FLAGS[edge] != 0 && self.edges[edge] >= 0 {
+            Some(self.edges[edge] as usize)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EdgeLink {
+    pub link_type: u32,
+    pub navmesh: FormId,
+    pub triangle: i16,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DoorLink {
+    pub triangle: i16,
+    pub unknown: u32,
+    pub door: FormId,
+}
+
+/// Geometry and links from a NAVM record's NVNM subrecord. The search grid that follows them is left undecoded
+#[derive(Debug, Clone, PartialEq)]
+pub struct Navmesh {
+    pub id: FormId,
+    pub version: u32,
+    pub parent: NavmeshParent,
+    pub vertices: Vec<[f32; 3]>,
+    pub triangles: Vec<Triangle>,
+    pub edge_links: Vec<EdgeLink>,
+    pub door_links: Vec<DoorLink>,
+    pub cover_triangles: Vec<u16>,
+}
+
+impl Navmesh {
+    pub fn decode(record: &Record) -> Result<Self, crate::Error> {
+        if &*record.header.code != b"NAVM" {
+            return Err(crate::Error::CorruptOrInvalidRecord(format!(
+                "{} {} is not a NAVM record",
+                record.header.code, record.header.id
+            )));
+        }
+
+        let nvnm = record.subrecord(b"NVNM").ok_or_else(|| {
+            crate::Error::CorruptOrInvalidRecord(format!("{} {} has no NVNM", record.header.code, record.header.id))
+        })?;
+
+        let (bytes, (version, _magic, parent)) = tuple((le_u32, le_u32, parent))(nvnm.data)?;
+        let (bytes, vertices) = length_count(le_u32, vertex)(bytes)?;
+        let (bytes, triangles) = length_count(le_u32, triangle)(bytes)?;
+        let (bytes, edge_links) = length_count(
+            le_u32,
+            map(tuple((le_u32, form_id, le_i16)), |(link_type, navmesh, triangle)| {
+                EdgeLink {
+                    link_type,
+                    navmesh,
+                    triangle,
+                }
+            }),
+        )(bytes)?;
+        let (bytes, door_links) = length_count(
+            le_u32,
+            map(tuple((le_i16, le_u32, form_id)), |(triangle, unknown, door)| DoorLink {
+                triangle,
+                unknown,
+                door,
+            }),
+        )(bytes)?;
+        let (_, cover_triangles) = length_count(le_u32, le_u16)(bytes)?;
+
+        Ok(Navmesh {
+            id: record.header.id,
+            version,
+            parent,
+            vertices,
+            triangles,
+            edge_links,
+            door_links,
+            cover_triangles,
+        })
+    }
+}
+
+/// An entry of the NAVI navmesh info map
+#[derive(Debug, Clone, PartialEq)]
+pub struct NavmeshInfo {
+    pub navmesh: FormId,
+    pub flags: u32,
+    pub center: [f32; 3],
+    pub preferred_merges_flag: u32,
+    pub merged_to: Vec<FormId>,
+    pub preferred_merges: Vec<FormId>,
+    /// Door references, with an unknown value preceding each
+    pub linked_doors: Vec<(u32, FormId)>,
+    pub island: Option<Island>,
+    pub unknown: u32,
+    pub parent: NavmeshParent,
+}
+
+/// A navmesh area unreachable from the rest of its worldspace or cell
+#[derive(Debug, Clone, PartialEq)]
+pub struct Island {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+    pub triangles: Vec<[u16; 3]>,
+    pub vertices: Vec<[f32; 3]>,
+}
+
+/// The NAVI record's NVMI entries. Precomputed pathing and the remaining subrecords are left undecoded
+#[derive(Debug, Clone, PartialEq)]
+pub struct NavmeshInfoMap {
+    pub id: FormId,
+    pub version: u32,
+    pub navmeshes: Vec<NavmeshInfo>,
+}
+
+impl NavmeshInfoMap {
+    pub fn decode(record: &Record) -> Result<Self, crate::Error> {
+        if &*record.header.code != b"NAVI" {
+            return Err(crate::Error::CorruptOrInvalidRecord(format!(
+                "{} {} is not a NAVI record",
+                record.header.code, record.header.id
+            )));
+        }
+
+        let mut map = NavmeshInfoMap {
+            id: record.header.id,
+            version: 0,
+            navmeshes: vec![],
+        };
+
+        for subrecord in record.subrecords() {
+            match &*subrecord.code {
+                b"NVER" => {
+                    map.version = le_u32(subrecord.data)?.1;
+                }
+                b"NVMI" => {
+                    map.navmeshes.push(navmesh_info(subrecord.data)?.1);
+                }
+                _ => (),
+            }
+        }
+
+        Ok(map)
+    }
+
+    pub fn get(&self, navmesh: FormId) -> Option<&NavmeshInfo> {
+        self.navmeshes.iter().find(|info| info.navmesh == navmesh)
+    }
+}
+
+/// Byte offsets of the FormIDs in NVNM data: the parent worldspace or cell, and the navmeshes and doors it links to.
+/// `None` if the data doesn't decode
+pub(crate) fn form_id_offsets(bytes: &[u8]) -> Option<Vec<usize>> {
+    let offset = |rest: &[u8]| bytes.len() - rest.len();
+
+    let (rest, (_, _, world)) = tuple((le_u32, le_u32, form_id))(bytes).ok()?;
+    let mut offsets = vec![8];
+
+    if *world == 0 {
+        offsets.push(12);
+    }
+
+    let (rest, _) = le_u32::<_, crate::Error>(rest).ok()?;
+    let (rest, _) = length_count(le_u32, vertex)(rest).ok()?;
+    let (rest, _) = length_count(le_u32, triangle)(rest).ok()?;
+    let (mut rest, edge_links) = le_u32::<_, crate::Error>(rest).ok()?;
+
+    for _ in 0..edge_links {
+        offsets.push(offset(rest) + 4);
+        rest = tuple((le_u32, form_id, le_i16))(rest).ok()?.0;
+    }
+
+    let (mut rest, door_links) = le_u32::<_, crate::Error>(rest).ok()?;
+
+    for _ in 0..door_links {
+        offsets.push(offset(rest) + 6);
+        rest = tuple((le_i16, le_u32, form_id))(rest).ok()?.0;
+    }
+
+    Some(offsets)
+}
+
+fn vertex(bytes: &[u8]) -> crate::IResult<&[u8], [f32; 3]> {
+    map(tuple((le_f32, le_f32, le_f32)), |(x, y, z)| [x, y, z])(bytes)
+}
+
+/// A parent worldspace followed by a grid Y and X, or a null worldspace followed by a parent cell
+fn parent(bytes: &[u8]) -> crate::IResult<&[u8], NavmeshParent> {
+    let (bytes, world) = form_id(bytes)?;
+
+    if *world == 0 {
+        map(form_id, NavmeshParent::Cell)(bytes)
+    } else {
+        map(tuple((le_i16, le_i16)), |(y, x)| NavmeshParent::Worldspace {
+            world,
+            grid: GridCoord::new(x, y),
+        })(bytes)
+    }
+}
+
+fn triangle(bytes: &[u8]) -> crate::IResult<&[u8], Triangle> {
+    map(
+        tuple((count(le_i16, 3), count(le_i16, 3), le_u16, le_u16)),
+        |(vertices, edges, flags, cover_flags)| Triangle {
+            vertices: [vertices[0], vertices[1], vertices[2]],
+            edges: [edges[0], edges[1], edges[2]],
+            flags,
+            cover_flags,
+        },
+    )(bytes)
+}
+
+fn island(bytes: &[u8]) -> crate::IResult<&[u8], Island> {
+    map(
+        tuple((
+            vertex,
+            vertex,
+            length_count(le_u32, map(tuple((le_u16, le_u16, le_u16)), |(a, b, c)| [a, b, c])),
+            length_count(le_u32, vertex),
+        )),
+        |(min, max, triangles, vertices)| Island {
+            min,
+            max,
+            triangles,
+            vertices,
+        },
+    )(bytes)
+}
+
+fn navmesh_info(bytes: &[u8]) -> crate::IResult<&[u8], NavmeshInfo> {
+    let (bytes, (navmesh, flags, center, preferred_merges_flag)) = tuple((form_id, le_u32, vertex, le_u32))(bytes)?;
+    let (bytes, merged_to) = length_count(le_u32, form_id)(bytes)?;
+    let (bytes, preferred_merges) = length_count(le_u32, form_id)(bytes)?;
+    let (bytes, linked_doors) = length_count(le_u32, tuple((le_u32, form_id)))(bytes)?;
+    let (bytes, is_island) = le_u8(bytes)?;
+
+    let (bytes, island) = if is_island != 0 {
+        map(island, Some)(bytes)?
+    } else {
+        (bytes, None)
+    };
+
+    let (bytes, (unknown, parent)) = tuple((le_u32, parent))(bytes)?;
+
+    Ok((
+        bytes,
+        NavmeshInfo {
+            navmesh,
+            flags,
+            center,
+            preferred_merges_flag,
+            merged_to,
+            preferred_merges,
+            linked_doors,
+            island,
+            unknown,
+            parent,
+        },
+    ))
+}
@@ -0,0 +1,61 @@
+use crate::parsers::{common::FormId, records::Record};
+
+use nom::{
+    combinator::opt,
+    number::complete::{le_u16, le_u32, le_u8},
+    sequence::tuple,
+};
+
+/// QUST DNAM flags
+pub mod flags {
+    pub const START_GAME_ENABLED: u16 = 0x0001;
+    pub const ALLOW_REPEATED_STAGES: u16 = 0x0008;
+    pub const RUN_ONCE: u16 = 0x0100;
+    pub const EXCLUDE_FROM_DIALOGUE_EXPORT: u16 = 0x0200;
+    pub const WARN_ON_ALIAS_FILL_FAILURE: u16 = 0x0400;
+}
+
+/// The general data of a QUST record, from its DNAM
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quest {
+    pub id: FormId,
+    pub editor_id: Option<String>,
+    pub flags: u16,
+    pub priority: u8,
+    pub form_version: u8,
+    pub unknown: u32,
+    /// The quest type, from 0 for none to 11 for miscellaneous. Missing from some older records
+    pub quest_type: Option<u32>,
+}
+
+impl Quest {
+    pub fn decode(record: &Record) -> Result<Self, crate::Error> {
+        if &*record.header.code != b"QUST" {
+            return Err(crate::Error::CorruptOrInvalidRecord(format!(
+                "{} {} is not a quest",
+                record.header.code, record.header.id
+            )));
+        }
+
+        let dnam = record
+            .subrecord(b"DNAM")
+            .ok_or_else(|| crate::Error::CorruptOrInvalidRecord(format!("QUST {} has no DNAM", record.header.id)))?;
+
+        let (_, (flags, priority, form_version, unknown, quest_type)) =
+            tuple((le_u16, le_u8, le_u8, le_u32, opt(le_u32)))(dnam.data)?;
+
+        Ok(Quest {
+            id: record.header.id,
+            editor_id: record.header.editor_id.clone(),
+            flags,
+            priority,
+            form_version,
+            unknown,
+            quest_type,
+        })
+    }
+
+    pub fn is_start_game_enabled(&self) -> bool {
+        self.flags & flags::START_GAME_ENABLED != 0
+    }
+}
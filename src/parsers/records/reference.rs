@@ -0,0 +1,231 @@
+use crate::parsers::{
+    common::{form_id, FormId, TypeCode},
+    records::Record,
+};
+
+use nom::{
+    bytes::complete::take,
+    combinator::{map, opt},
+    number::complete::{le_f32, le_i32, le_u32, le_u8},
+    sequence::{terminated, tuple},
+};
+
+/// Record types that place an object in a cell. They share a layout, apart from the actor and projectile
+/// specific subrecords this module ignores
+pub const CODES: &[[u8; 4]] = &[
+    *b"REFR", *b"ACHR", *b"PGRE", *b"PHZD", *b"PMIS", *b"PARW", *b"PBAR", *b"PBEA", *b"PCON", *b"PFLA",
+];
+
+pub fn is_placed_reference(code: &TypeCode) -> bool {
+    CODES.iter().any(|placed| **code == *placed)
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Placement {
+    pub position: [f32; 3],
+    /// Euler angles in radians
+    pub rotation: [f32; 3],
+}
+
+impl Placement {
+    /// DATA of a placed reference
+    pub fn decode(bytes: &[u8]) -> Result<Self, crate::Error> {
+        Ok(placement_data(bytes)?.1)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnableParent {
+    pub reference: FormId,
+    /// Enabled when the parent is disabled, and the other way round
+    pub opposite: bool,
+    pub pop_in: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkedReference {
+    /// Null for links without a keyword
+    pub keyword: FormId,
+    pub reference: FormId,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Teleport {
+    /// The door at the other end
+    pub destination: FormId,
+    pub placement: Placement,
+    pub flags: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lock {
+    pub level: u8,
+    pub key: FormId,
+    pub flags: u8,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ownership {
+    /// An NPC or a faction
+    pub owner: FormId,
+    /// Minimum faction rank, when the owner is a faction
+    pub rank: Option<i32>,
+}
+
+/// A decoded REFR, ACHR or other placed object. The record itself is left untouched, this is a read-only view
+/// of it
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlacedReference {
+    pub id: FormId,
+    pub code: TypeCode,
+    pub editor_id: Option<String>,
+    /// The placed base object, or NPC for ACHR
+    pub base: FormId,
+    pub placement: Placement,
+    pub scale: Option<f32>,
+    pub enable_parent: Option<EnableParent>,
+    pub linked_references: Vec<LinkedReference>,
+    pub teleport: Option<Teleport>,
+    pub lock: Option<Lock>,
+    pub ownership: Option<Ownership>,
+}
+
+impl PlacedReference {
+    pub fn decode(record: &Record) -> Result<Self, crate::Error> {
+        if !is_placed_reference(&record.header.code) {
+            return Err(crate::Error::CorruptOrInvalidRecord(format!(
+                "{} {} is not a placed reference",
+                record.header.code, record.header.id
+            )));
+        }
+
+        let mut base = None;
+        let mut placement = None;
+        let mut reference = PlacedReference {
+            id: record.header.id,
+            code: record.header.code.clone(),
+            editor_id: record.header.editor_id.clone(),
+            base: FormId::default(),
+            placement: Placement::default(),
+            scale: None,
+            enable_parent: None,
+            linked_references: vec![],
+            teleport: None,
+            lock: None,
+            ownership: None,
+        };
+
+        for subrecord in record.subrecords() {
+            let bytes = subrecord.data;
+
+            match &*subrecord.code {
+                b"NAME" => {
+                    base = Some(form_id(bytes)?.1);
+                }
+                b"DATA" => {
+                    placement = Some(placement_data(bytes)?.1);
+                }
+                b"XSCL" => {
+                    reference.scale = Some(le_f32(bytes)?.1);
+                }
+                b"XESP" => {
+                    reference.enable_parent = Some(enable_parent(bytes)?.1);
+                }
+                b"XLKR" => {
+                    reference.linked_references.push(linked_reference(bytes)?.1);
+                }
+                b"XTEL" => {
+                    reference.teleport = Some(teleport(bytes)?.1);
+                }
+                b"XLOC" => {
+                    reference.lock = Some(lock(bytes)?.1);
+                }
+                b"XOWN" => {
+                    reference.ownership = Some(Ownership {
+                        owner: form_id(bytes)?.1,
+                        rank: None,
+                    });
+                }
+                b"XRNK" => {
+                    if let Some(ownership) = &mut reference.ownership {
+                        ownership.rank = Some(le_i32(bytes)?.1);
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        let missing = |subrecord| {
+            crate::Error::CorruptOrInvalidRecord(format!(
+                "{} {} has no {}",
+                record.header.code, record.header.id, subrecord
+            ))
+        };
+
+        reference.base = base.ok_or_else(|| missing("NAME"))?;
+        reference.placement = placement.ok_or_else(|| missing("DATA"))?;
+
+        Ok(reference)
+    }
+
+    /// Scale, defaulting to 1 when there is no XSCL
+    pub fn scale(&self) -> f32 {
+        self.scale.unwrap_or(1.0)
+    }
+}
+
+fn vector(bytes: &[u8]) -> crate::IResult<&[u8], [f32; 3]> {
+    map(tuple((le_f32, le_f32, le_f32)), |(x, y, z)| [x, y, z])(bytes)
+}
+
+fn placement_data(bytes: &[u8]) -> crate::IResult<&[u8], Placement> {
+    map(tuple((vector, vector)), |(position, rotation)| Placement {
+        position,
+        rotation,
+    })(bytes)
+}
+
+fn enable_parent(bytes: &[u8]) -> crate::IResult<&[u8], EnableParent> {
+    map(tuple((form_id, le_u8)), |(reference, flags)| EnableParent {
+        reference,
+        opposite: flags & 0x01 != 0,
+        pop_in: flags & 0x02 != 0,
+    })(bytes)
+}
+
+/// Older plugins only hold the linked reference, without a keyword
+fn linked_reference(bytes: &[u8]) -> crate::IResult<&[u8], LinkedReference> {
+    if bytes.len() < 8 {
+        map(form_id, |reference| LinkedReference {
+            keyword: FormId::default(),
+            reference,
+        })(bytes)
+    } else {
+        map(tuple((form_id, form_id)), |(keyword, reference)| LinkedReference {
+            keyword,
+            reference,
+        })(bytes)
+    }
+}
+
+fn teleport(bytes: &[u8]) -> crate::IResult<&[u8], Teleport> {
+    map(
+        tuple((form_id, placement_data, opt(le_u32))),
+        |(destination, placement, flags)| Teleport {
+            destination,
+            placement,
+            flags: flags.unwrap_or_default(),
+        },
+    )(bytes)
+}
+
+fn lock(bytes: &[u8]) -> crate::IResult<&[u8], Lock> {
+    map(
+        tuple((terminated(le_u8, take(3usize)), form_id, opt(le_u8))),
+        |(level, key, flags)| Lock {
+            level,
+            key,
+            flags: flags.unwrap_or_default(),
+        },
+    )(bytes)
+}
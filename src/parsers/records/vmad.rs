@@ -0,0 +1,703 @@
+use crate::parsers::{
+    common::{decode_string, encode_string, form_id, FormId, TypeCode},
+    records::Record,
+};
+
+use nom::{
+    combinator::{map, rest},
+    multi::{count, length_count, length_data},
+    number::complete::{le_f32, le_i16, le_i32, le_u16, le_u32, le_u8},
+    sequence::tuple,
+};
+
+/// A reference to a form, or to a quest alias when `alias` is not -1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ObjectRef {
+    pub form_id: FormId,
+    pub alias: i16,
+    pub unused: u16,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PropertyValue {
+    Object(ObjectRef),
+    String(String),
+    Int(i32),
+    Float(f32),
+    Bool(bool),
+    ObjectArray(Vec<ObjectRef>),
+    StringArray(Vec<String>),
+    IntArray(Vec<i32>),
+    FloatArray(Vec<f32>),
+    BoolArray(Vec<bool>),
+}
+
+impl PropertyValue {
+    fn type_id(&self) -> u8 {
+        match self {
+            PropertyValue::Object(_) => 1,
+            PropertyValue::String(_) => 2,
+            PropertyValue::Int(_) => 3,
+            PropertyValue::Float(_) => 4,
+            PropertyValue::Bool(_) => 5,
+            PropertyValue::ObjectArray(_) => 11,
+            PropertyValue::StringArray(_) => 12,
+            PropertyValue::IntArray(_) => 13,
+            PropertyValue::FloatArray(_) => 14,
+            PropertyValue::BoolArray(_) => 15,
+        }
+    }
+
+    /// The objects held by an object property or array
+    pub fn objects(&self) -> &[ObjectRef] {
+        match self {
+            PropertyValue::Object(object) => std::slice::from_ref(object),
+            PropertyValue::ObjectArray(objects) => objects,
+            _ => &[],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Property {
+    pub name: String,
+    /// 1 when edited, 3 when removed
+    pub status: u8,
+    pub value: PropertyValue,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Script {
+    pub name: String,
+    /// 0 for local, 1 for inherited, 3 for inherited and removed
+    pub status: u8,
+    pub properties: Vec<Property>,
+}
+
+impl Script {
+    /// Papyrus names are case-insensitive
+    pub fn property(&self, name: &str) -> Option<&Property> {
+        self.properties
+            .iter()
+            .find(|property| property.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn property_mut(&mut self, name: &str) -> Option<&mut Property> {
+        self.properties
+            .iter_mut()
+            .find(|property| property.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Set a property's value, adding the property if the script has no value for it yet
+    pub fn set_property(&mut self, name: &str, value: PropertyValue) {
+        match self.property_mut(name) {
+            Some(property) => property.value = value,
+            None => self.properties.push(Property {
+                name: String::from(name),
+                status: 1,
+                value,
+            }),
+        }
+    }
+}
+
+/// A script fragment: a function in a fragment script
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fragment {
+    pub unknown: u8,
+    pub script: String,
+    pub function: String,
+}
+
+/// Fragments selected by flag bits, as INFO and PACK records have
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlaggedFragments {
+    pub unknown: u8,
+    /// Begin and end for INFO and SCEN, plus change for PACK. One fragment follows for each bit set
+    pub flags: u8,
+    pub file_name: String,
+    pub fragments: Vec<Fragment>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PerkFragment {
+    pub index: u16,
+    pub unknown: u16,
+    pub fragment: Fragment,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuestFragment {
+    pub stage: u16,
+    pub unknown: u16,
+    pub log_entry: i32,
+    pub fragment: Fragment,
+}
+
+/// Scripts attached to a quest alias
+#[derive(Debug, Clone, PartialEq)]
+pub struct AliasScripts {
+    pub object: ObjectRef,
+    pub version: i16,
+    pub object_format: i16,
+    pub scripts: Vec<Script>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScenePhaseFragment {
+    pub unknown: u8,
+    pub phase: u32,
+    pub fragment: Fragment,
+}
+
+/// Record-specific data following the scripts
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fragments {
+    Info(FlaggedFragments),
+    Package(FlaggedFragments),
+    Perk {
+        unknown: u8,
+        file_name: String,
+        fragments: Vec<PerkFragment>,
+    },
+    Quest {
+        unknown: u8,
+        file_name: String,
+        fragments: Vec<QuestFragment>,
+        aliases: Vec<AliasScripts>,
+    },
+    Scene {
+        fragments: FlaggedFragments,
+        phases: Vec<ScenePhaseFragment>,
+    },
+    /// Data following the scripts of a record type whose fragments aren't decoded
+    Unknown(Vec<u8>),
+}
+
+/// A VMAD subrecord: the Papyrus scripts attached to a record, with their property values
+#[derive(Debug, Clone, PartialEq)]
+pub struct Vmad {
+    pub version: i16,
+    /// 1 or 2, giving the order of an object property's FormID and alias
+    pub object_format: i16,
+    pub scripts: Vec<Script>,
+    pub fragments: Option<Fragments>,
+}
+
+impl Vmad {
+    /// The record's VMAD, if it has one
+    pub fn decode(record: &Record) -> Result<Option<Self>, crate::Error> {
+        match record.subrecord(b"VMAD") {
+            Some(vmad) => Ok(Some(Vmad::parse(vmad.data, &record.header.code)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Decode VMAD data from a record of type `code`, which decides how fragments are read
+    pub fn parse(bytes: &[u8], code: &TypeCode) -> Result<Self, crate::Error> {
+        let (bytes, (version, object_format)) = tuple((le_i16, le_i16))(bytes)?;
+        let (bytes, scripts) = scripts(bytes, version, object_format)?;
+
+        let fragments = if bytes.is_empty() {
+            None
+        } else {
+            Some(fragments(bytes, code, object_format)?.1)
+        };
+
+        Ok(Vmad {
+            version,
+            object_format,
+            scripts,
+            fragments,
+        })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        self.encode_with_offsets().0
+    }
+
+    /// The encoded VMAD, with the byte offsets of the FormIDs it holds
+    pub(crate) fn encode_with_offsets(&self) -> (Vec<u8>, Vec<usize>) {
+        let mut encoder = Encoder::default();
+
+        encoder.i16(self.version);
+        encoder.i16(self.object_format);
+        encoder.scripts(&self.scripts, self.version, self.object_format);
+
+        match &self.fragments {
+            Some(Fragments::Info(fragments)) | Some(Fragments::Package(fragments)) => {
+                encoder.flagged_fragments(fragments);
+            }
+            Some(Fragments::Perk {
+                unknown,
+                file_name,
+                fragments,
+            }) => {
+                encoder.u8(*unknown);
+                encoder.wstring(file_name);
+                encoder.u16(fragments.len() as u16);
+
+                for fragment in fragments {
+                    encoder.u16(fragment.index);
+                    encoder.u16(fragment.unknown);
+                    encoder.fragment(&fragment.fragment);
+                }
+            }
+            Some(Fragments::Quest {
+                unknown,
+                file_name,
+                fragments,
+                aliases,
+            }) => {
+                encoder.u8(*unknown);
+                encoder.u16(fragments.len() as u16);
+                encoder.wstring(file_name);
+
+                for fragment in fragments {
+                    encoder.u16(fragment.stage);
+                    encoder.u16(fragment.unknown);
+                    encoder.i32(fragment.log_entry);
+                    encoder.fragment(&fragment.fragment);
+                }
+
+                encoder.u16(aliases.len() as u16);
+
+                for alias in aliases {
+                    encoder.object(&alias.object, self.object_format);
+                    encoder.i16(alias.version);
+                    encoder.i16(alias.object_format);
+                    encoder.scripts(&alias.scripts, alias.version, alias.object_format);
+                }
+            }
+            Some(Fragments::Scene { fragments, phases }) => {
+                encoder.flagged_fragments(fragments);
+                encoder.u16(phases.len() as u16);
+
+                for phase in phases {
+                    encoder.u8(phase.unknown);
+                    encoder.u32(phase.phase);
+                    encoder.fragment(&phase.fragment);
+                }
+            }
+            Some(Fragments::Unknown(bytes)) => encoder.bytes.extend_from_slice(bytes),
+            None => (),
+        }
+
+        (encoder.bytes, encoder.form_ids)
+    }
+
+    /// Papyrus names are case-insensitive
+    pub fn script(&self, name: &str) -> Option<&Script> {
+        self.scripts
+            .iter()
+            .find(|script| script.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn script_mut(&mut self, name: &str) -> Option<&mut Script> {
+        self.scripts
+            .iter_mut()
+            .find(|script| script.name.eq_ignore_ascii_case(name))
+    }
+
+    /// The record's own scripts, then those of each quest alias along with the alias
+    pub fn all_scripts(&self) -> Vec<(Option<&AliasScripts>, &Script)> {
+        let mut scripts = self.scripts.iter().map(|script| (None, script)).collect::<Vec<_>>();
+
+        if let Some(Fragments::Quest { aliases, .. }) = &self.fragments {
+            for alias in aliases {
+                scripts.extend(alias.scripts.iter().map(|script| (Some(alias), script)));
+            }
+        }
+
+        scripts
+    }
+
+    /// The names of every script the VMAD needs compiled: attached scripts, alias scripts and fragment scripts
+    pub fn script_names(&self) -> Vec<&str> {
+        let mut names = self
+            .all_scripts()
+            .into_iter()
+            .map(|(_, script)| script.name.as_str())
+            .collect::<Vec<_>>();
+
+        match &self.fragments {
+            Some(Fragments::Info(fragments)) | Some(Fragments::Package(fragments)) => {
+                names.push(&fragments.file_name);
+                names.extend(fragments.fragments.iter().map(|fragment| fragment.script.as_str()));
+            }
+            Some(Fragments::Perk {
+                file_name, fragments, ..
+            }) => {
+                names.push(file_name);
+                names.extend(fragments.iter().map(|fragment| fragment.fragment.script.as_str()));
+            }
+            Some(Fragments::Quest {
+                file_name, fragments, ..
+            }) => {
+                names.push(file_name);
+                names.extend(fragments.iter().map(|fragment| fragment.fragment.script.as_str()));
+            }
+            Some(Fragments::Scene { fragments, phases }) => {
+                names.push(&fragments.file_name);
+                names.extend(fragments.fragments.iter().map(|fragment| fragment.script.as_str()));
+                names.extend(phases.iter().map(|phase| phase.fragment.script.as_str()));
+            }
+            Some(Fragments::Unknown(_)) | None => (),
+        }
+
+        let mut unique: Vec<&str> = vec![];
+
+        for name in names {
+            if !name.is_empty() && !unique.iter().any(|other| other.eq_ignore_ascii_case(name)) {
+                unique.push(name);
+            }
+        }
+
+        unique
+    }
+
+    /// Replace the record's VMAD with this one, adding it after the editor ID if the record has none
+    pub fn write(&self, record: &mut Record) {
+        let data = self.encode();
+
+        record.edit_subrecords(
+            |subrecords| match subrecords.iter_mut().find(|(code, _)| &**code == b"VMAD") {
+                Some((_, existing)) => *existing = data,
+                None => {
+                    let position = subrecords.iter().take_while(|(code, _)| &**code == b"EDID").count();
+                    subrecords.insert(position, (TypeCode::from(*b"VMAD"), data));
+                }
+            },
+        );
+    }
+}
+
+/// Byte offsets of the FormIDs in VMAD data from a record of type `code`, or `None` if the data doesn't decode and
+/// re-encode to the same bytes, so the offsets can't be trusted
+pub(crate) fn form_id_offsets(bytes: &[u8], code: &TypeCode) -> Option<Vec<usize>> {
+    let (encoded, offsets) = Vmad::parse(bytes, code).ok()?.encode_with_offsets();
+
+    if encoded == bytes {
+        Some(offsets)
+    } else {
+        None
+    }
+}
+
+fn wstring(bytes: &[u8]) -> crate::IResult<&[u8], String> {
+    map(length_data(le_u16), decode_string)(bytes)
+}
+
+fn object(bytes: &[u8], object_format: i16) -> crate::IResult<&[u8], ObjectRef> {
+    if object_format == 1 {
+        map(tuple((form_id, le_i16, le_u16)), |(form_id, alias, unused)| ObjectRef {
+            form_id,
+            alias,
+            unused,
+        })(bytes)
+    } else {
+        map(tuple((le_u16, le_i16, form_id)), |(unused, alias, form_id)| ObjectRef {
+            form_id,
+            alias,
+            unused,
+        })(bytes)
+    }
+}
+
+fn bool(bytes: &[u8]) -> crate::IResult<&[u8], bool> {
+    map(le_u8, |value| value != 0)(bytes)
+}
+
+fn property_value(bytes: &[u8], type_id: u8, object_format: i16) -> crate::IResult<&[u8], PropertyValue> {
+    match type_id {
+        1 => map(|bytes| object(bytes, object_format), PropertyValue::Object)(bytes),
+        2 => map(wstring, PropertyValue::String)(bytes),
+        3 => map(le_i32, PropertyValue::Int)(bytes),
+        4 => map(le_f32, PropertyValue::Float)(bytes),
+        5 => map(bool, PropertyValue::Bool)(bytes),
+        11 => map(
+            length_count(le_u32, |bytes| object(bytes, object_format)),
+            PropertyValue::ObjectArray,
+        )(bytes),
+        12 => map(length_count(le_u32, wstring), PropertyValue::StringArray)(bytes),
+        13 => map(length_count(le_u32, le_i32), PropertyValue::IntArray)(bytes),
+        14 => map(length_count(le_u32, le_f32), PropertyValue::FloatArray)(bytes),
+        15 => map(length_count(le_u32, bool), PropertyValue::BoolArray)(bytes),
+        _ => Err(nom::Err::Failure(crate::Error::CorruptOrInvalidRecord(format!(
+            "Unknown script property type {}",
+            type_id
+        )))),
+    }
+}
+
+/// Scripts and properties have a status byte from version 4
+fn status(bytes: &[u8], version: i16) -> crate::IResult<&[u8], u8> {
+    if version >= 4 {
+        le_u8(bytes)
+    } else {
+        Ok((bytes, 0))
+    }
+}
+
+fn property(bytes: &[u8], version: i16, object_format: i16) -> crate::IResult<&[u8], Property> {
+    let (bytes, (name, type_id)) = tuple((wstring, le_u8))(bytes)?;
+    let (bytes, status) = status(bytes, version)?;
+    let (bytes, value) = property_value(bytes, type_id, object_format)?;
+
+    Ok((bytes, Property { name, status, value }))
+}
+
+fn script(bytes: &[u8], version: i16, object_format: i16) -> crate::IResult<&[u8], Script> {
+    let (bytes, name) = wstring(bytes)?;
+    let (bytes, status) = status(bytes, version)?;
+    let (bytes, properties) = length_count(le_u16, |bytes| property(bytes, version, object_format))(bytes)?;
+
+    Ok((
+        bytes,
+        Script {
+            name,
+            status,
+            properties,
+        },
+    ))
+}
+
+fn scripts(bytes: &[u8], version: i16, object_format: i16) -> crate::IResult<&[u8], Vec<Script>> {
+    length_count(le_u16, |bytes| script(bytes, version, object_format))(bytes)
+}
+
+fn fragment(bytes: &[u8]) -> crate::IResult<&[u8], Fragment> {
+    map(tuple((le_u8, wstring, wstring)), |(unknown, script, function)| {
+        Fragment {
+            unknown,
+            script,
+            function,
+        }
+    })(bytes)
+}
+
+/// Fragments selected by the given number of flag bits
+fn flagged_fragments(bytes: &[u8], bits: u8) -> crate::IResult<&[u8], FlaggedFragments> {
+    let (bytes, (unknown, flags, file_name)) = tuple((le_u8, le_u8, wstring))(bytes)?;
+    let set = (0..bits).filter(|bit| flags & (1 << bit) != 0).count();
+    let (bytes, fragments) = count(fragment, set)(bytes)?;
+
+    Ok((
+        bytes,
+        FlaggedFragments {
+            unknown,
+            flags,
+            file_name,
+            fragments,
+        },
+    ))
+}
+
+fn fragments<'a>(bytes: &'a [u8], code: &TypeCode, object_format: i16) -> crate::IResult<&'a [u8], Fragments> {
+    match &**code {
+        b"INFO" => map(|bytes| flagged_fragments(bytes, 2), Fragments::Info)(bytes),
+        b"PACK" => map(|bytes| flagged_fragments(bytes, 3), Fragments::Package)(bytes),
+        b"PERK" => {
+            let (bytes, (unknown, file_name)) = tuple((le_u8, wstring))(bytes)?;
+            let (bytes, fragments) = length_count(
+                le_u16,
+                map(tuple((le_u16, le_u16, fragment)), |(index, unknown, fragment)| {
+                    PerkFragment {
+                        index,
+                        unknown,
+                        fragment,
+                    }
+                }),
+            )(bytes)?;
+
+            Ok((
+                bytes,
+                Fragments::Perk {
+                    unknown,
+                    file_name,
+                    fragments,
+                },
+            ))
+        }
+        b"QUST" => {
+            let (bytes, (unknown, fragment_count, file_name)) = tuple((le_u8, le_u16, wstring))(bytes)?;
+            let (bytes, fragments) = count(
+                map(
+                    tuple((le_u16, le_u16, le_i32, fragment)),
+                    |(stage, unknown, log_entry, fragment)| QuestFragment {
+                        stage,
+                        unknown,
+                        log_entry,
+                        fragment,
+                    },
+                ),
+                fragment_count as usize,
+            )(bytes)?;
+            let (bytes, aliases) = length_count(le_u16, |bytes| {
+                let (bytes, object) = object(bytes, object_format)?;
+                let (bytes, (version, object_format)) = tuple((le_i16, le_i16))(bytes)?;
+                let (bytes, scripts) = scripts(bytes, version, object_format)?;
+
+                Ok((
+                    bytes,
+                    AliasScripts {
+                        object,
+                        version,
+                        object_format,
+                        scripts,
+                    },
+                ))
+            })(bytes)?;
+
+            Ok((
+                bytes,
+                Fragments::Quest {
+                    unknown,
+                    file_name,
+                    fragments,
+                    aliases,
+                },
+            ))
+        }
+        b"SCEN" => {
+            let (bytes, fragments) = flagged_fragments(bytes, 2)?;
+            let (bytes, phases) = length_count(
+                le_u16,
+                map(tuple((le_u8, le_u32, fragment)), |(unknown, phase, fragment)| {
+                    ScenePhaseFragment {
+                        unknown,
+                        phase,
+                        fragment,
+                    }
+                }),
+            )(bytes)?;
+
+            Ok((bytes, Fragments::Scene { fragments, phases }))
+        }
+        _ => map(rest, |bytes: &[u8]| Fragments::Unknown(bytes.to_vec()))(bytes),
+    }
+}
+
+/// Builds VMAD bytes, noting where FormIDs are written
+#[derive(Default)]
+struct Encoder {
+    bytes: Vec<u8>,
+    form_ids: Vec<usize>,
+}
+
+impl Encoder {
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn i16(&mut self, value: i16) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn i32(&mut self, value: i32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn f32(&mut self, value: f32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn wstring(&mut self, value: &str) {
+        let bytes = encode_string(value);
+        self.u16(bytes.len() as u16);
+        self.bytes.extend_from_slice(&bytes);
+    }
+
+    fn form_id(&mut self, id: FormId) {
+        self.form_ids.push(self.bytes.len());
+        self.u32(*id);
+    }
+
+    fn object(&mut self, object: &ObjectRef, object_format: i16) {
+        if object_format == 1 {
+            self.form_id(object.form_id);
+            self.i16(object.alias);
+            self.u16(object.unused);
+        } else {
+            self.u16(object.unused);
+            self.i16(object.alias);
+            self.form_id(object.form_id);
+        }
+    }
+
+    fn scripts(&mut self, scripts: &[Script], version: i16, object_format: i16) {
+        self.u16(scripts.len() as u16);
+
+        for script in scripts {
+            self.wstring(&script.name);
+
+            if version >= 4 {
+                self.u8(script.status);
+            }
+
+            self.u16(script.properties.len() as u16);
+
+            for property in &script.properties {
+                self.wstring(&property.name);
+                self.u8(property.value.type_id());
+
+                if version >= 4 {
+                    self.u8(property.status);
+                }
+
+                self.property_value(&property.value, object_format);
+            }
+        }
+    }
+
+    fn property_value(&mut self, value: &PropertyValue, object_format: i16) {
+        match value {
+            PropertyValue::Object(object) => self.object(object, object_format),
+            PropertyValue::String(value) => self.wstring(value),
+            PropertyValue::Int(value) => self.i32(*value),
+            PropertyValue::Float(value) => self.f32(*value),
+            PropertyValue::Bool(value) => self.u8(*value as u8),
+            PropertyValue::ObjectArray(objects) => {
+                self.u32(objects.len() as u32);
+                objects.iter().for_each(|object| self.object(object, object_format));
+            }
+            PropertyValue::StringArray(values) => {
+                self.u32(values.len() as u32);
+                values.iter().for_each(|value| self.wstring(value));
+            }
+            PropertyValue::IntArray(values) => {
+                self.u32(values.len() as u32);
+                values.iter().for_each(|value| self.i32(*value));
+            }
+            PropertyValue::FloatArray(values) => {
+                self.u32(values.len() as u32);
+                values.iter().for_each(|value| self.f32(*value));
+            }
+            PropertyValue::BoolArray(values) => {
+                self.u32(values.len() as u32);
+                values.iter().for_each(|value| self.u8(*value as u8));
+            }
+        }
+    }
+
+    fn fragment(&mut self, fragment: &Fragment) {
+        self.u8(fragment.unknown);
+        self.wstring(&fragment.script);
+        self.wstring(&fragment.function);
+    }
+
+    fn flagged_fragments(&mut self, fragments: &FlaggedFragments) {
+        self.u8(fragments.unknown);
+        self.u8(fragments.flags);
+        self.wstring(&fragments.file_name);
+        fragments.fragments.iter().for_each(|fragment| self.fragment(fragment));
+    }
+}
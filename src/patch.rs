@@ -0,0 +1,516 @@
+//! Conflict-resolution patches: a plugin forwarding the edits several plugins make to the same records, combined
+//! field by field, so that a plugin loading later doesn't throw away the earlier ones' changes
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::{
+    leveled::{self, apply_list_edits},
+    load_order::{GlobalFormId, LoadOrder, LoadedPlugin},
+    merge::FIRST_OBJECT_ID,
+    parsers::{
+        common::TypeCode,
+        plugin::Plugin,
+        records::{file_header::MasterFile, flags::PluginFlags, Record},
+    },
+    schema,
+};
+
+const MAX_MASTERS: usize = 0xFE;
+
+/// How the edits plugins make to a subrecord are combined
+#[derive(Debug, Clone, Copy)]
+pub enum MergeRule {
+    /// Taken whole from the last plugin to change it. The rule for any subrecord not listed
+    Replace,
+    /// A fixed-size structure with fields starting at these offsets, each taken from the last plugin to change it
+    Fields(&'static [usize]),
+    /// A packed array of entries of this size. Entries any plugin adds are added and entries any plugin removes are
+    /// removed
+    Array(usize),
+    /// Repeated subrecords, each with the subrecords attached to it, forming a list. Entries any plugin adds are
+    /// added and entries any plugin removes are removed
+    List,
+}
+
+use MergeRule::*;
+
+/// Record types whose overrides are combined. Leveled lists are combined by `leveled::merge`
+pub const MERGED_RECORDS: &[[u8; 4]] = &[
+    *b"ALCH", *b"AMMO", *b"ARMO", *b"BOOK", *b"CONT", *b"FLST", *b"INGR", *b"KEYM", *b"MISC", *b"NPC_", *b"OTFT",
+    *b"SLGM", *b"WEAP",
+];
+
+/// Rules for subrecords whatever record they appear in
+const COMMON_RULES: &[([u8; 4], MergeRule)] = &[(*b"KWDA", Array(4)), (*b"CNTO", List)];
+
+/// Every byte of the skills and skill offsets, then the stat offsets, far away model distance and geared up weapons
+const NPC_DNAM_FIELDS: &[usize] = &[
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30,
+    31, 32, 33, 34, 35, 36, 38, 40, 42, 44, 48,
+];
+
+/// Rules for subrecords of particular record types, which take precedence over the common ones
+const RECORD_RULES: &[([u8; 4], [u8; 4], MergeRule)] = &[
+    (*b"ALCH", *b"ENIT", Fields(&[0, 4, 8, 12, 16])),
+    (*b"AMMO", *b"DATA", Fields(&[0, 4, 8, 12, 16])),
+    (*b"ARMO", *b"DATA", Fields(&[0, 4])),
+    (*b"BOOK", *b"DATA", Fields(&[0, 1, 2, 4, 8, 12])),
+    (*b"FLST", *b"LNAM", List),
+    (*b"INGR", *b"DATA", Fields(&[0, 4])),
+    (*b"INGR", *b"ENIT", Fields(&[0, 4])),
+    (*b"KEYM", *b"DATA", Fields(&[0, 4])),
+    (*b"MISC", *b"DATA", Fields(&[0, 4])),
+    (*b"NPC_", *b"ACBS", Fields(&[0, 4, 6, 8, 10, 12, 14, 16, 18, 20, 22])),
+    (*b"NPC_", *b"AIDT", Fields(&[0, 1, 2, 3, 4, 5, 6, 7, 8, 12, 16])),
+    (*b"NPC_", *b"DNAM", Fields(NPC_DNAM_FIELDS)),
+    (*b"NPC_", *b"PKID", List),
+    (*b"NPC_", *b"PNAM", List),
+    (*b"NPC_", *b"PRKR", List),
+    (*b"NPC_", *b"SNAM", List),
+    (*b"NPC_", *b"SPLO", List),
+    (*b"OTFT", *b"INAM", Array(4)),
+    (*b"SLGM", *b"DATA", Fields(&[0, 4])),
+    (*b"WEAP", *b"DATA", Fields(&[0, 4, 8])),
+];
+
+/// Subrecords that belong to the one before them, and travel with it
+const ATTACHED: &[([u8; 4], &[[u8; 4]])] = &[
+    (*b"CNTO", &[*b"COED"]),
+    (*b"CTDA", &[*b"CIS1", *b"CIS2"]),
+    (*b"EFID", &[*b"EFIT", *b"CTDA", *b"CIS1", *b"CIS2"]),
+    (*b"MODL", &[*b"MODT", *b"MODS"]),
+    (*b"MOD2", &[*b"MO2T", *b"MO2S"]),
+    (*b"MOD3", &[*b"MO3T", *b"MO3S"]),
+    (*b"MOD4", &[*b"MO4T", *b"MO4S"]),
+    (*b"MOD5", &[*b"MO5T", *b"MO5S"]),
+    (*b"TINI", &[*b"TINC", *b"TINV", *b"TIAS"]),
+];
+
+/// Subrecords holding the number of entries in a list, which are rewritten to match the merged list
+const COUNTS: &[([u8; 4], [u8; 4])] = &[
+    (*b"KWDA", *b"KSIZ"),
+    (*b"CNTO", *b"COCT"),
+    (*b"SPLO", *b"SPCT"),
+    (*b"PRKR", *b"PRKZ"),
+];
+
+/// Subrecords holding a string, or with a localized plugin an ID into its string tables
+const LOCALIZED_STRINGS: &[([u8; 4], [u8; 4])] = &[
+    (*b"****", *b"FULL"),
+    (*b"****", *b"DESC"),
+    (*b"BOOK", *b"CNAM"),
+    (*b"NPC_", *b"SHRT"),
+];
+
+pub fn rule(record: &TypeCode, subrecord: &TypeCode) -> MergeRule {
+    RECORD_RULES
+        .iter()
+        .find(|(code, field, _)| **record == *code && **subrecord == *field)
+        .map(|(_, _, rule)| *rule)
+        .or_else(|| {
+            COMMON_RULES
+                .iter()
+                .find(|(field, _)| **subrecord == *field)
+                .map(|(_, rule)| *rule)
+        })
+        .unwrap_or(Replace)
+}
+
+fn is_localized_string(record: &TypeCode, subrecord: &TypeCode) -> bool {
+    LOCALIZED_STRINGS
+        .iter()
+        .any(|(code, field)| (*code == *b"****" || **record == *code) && **subrecord == *field)
+}
+
+/// A subrecord along with those attached to it
+type Entry = Vec<(TypeCode, Vec<u8>)>;
+
+/// A record's subrecords gathered by code, leaving out entry counts
+struct Fields {
+    order: Vec<TypeCode>,
+    values: HashMap<TypeCode, Vec<Entry>>,
+}
+
+impl Fields {
+    fn new(record: &Record) -> Self {
+        let mut order = vec![];
+        let mut values: HashMap<TypeCode, Vec<Entry>> = HashMap::new();
+        let mut lead: Option<TypeCode> = None;
+
+        for subrecord in record.subrecords() {
+            let code = subrecord.code;
+
+            if COUNTS.iter().any(|(_, count)| *code == *count) {
+                continue;
+            }
+
+            let is_attached = lead.as_ref().is_some_and(|lead| {
+                ATTACHED
+                    .iter()
+                    .any(|(owner, attached)| **lead == *owner && attached.contains(&*code))
+            });
+
+            if is_attached {
+                if let Some(entry) = lead
+                    .as_ref()
+                    .and_then(|lead| values.get_mut(lead))
+                    .and_then(|v| v.last_mut())
+                {
+                    entry.push((code, subrecord.data.to_vec()));
+                    continue;
+                }
+            }
+
+            if !order.contains(&code) {
+                order.push(code.clone());
+            }
+
+            values
+                .entry(code.clone())
+                .or_default()
+                .push(vec![(code.clone(), subrecord.data.to_vec())]);
+            lead = Some(code);
+        }
+
+        Fields { order, values }
+    }
+
+    fn get(&self, code: &TypeCode) -> &[Entry] {
+        self.values.get(code).map_or(&[], Vec::as_slice)
+    }
+}
+
+/// The data of a field made of a single subrecord with nothing attached
+fn single(entries: &[Entry]) -> Option<&[u8]> {
+    match entries {
+        [entry] if entry.len() == 1 => Some(&entry[0].1),
+        _ => None,
+    }
+}
+
+/// Combine the edits `version` makes to `parent` into `merged`, following the field's rule
+fn merge_field(rule: MergeRule, merged: &mut Vec<Entry>, parent: &[Entry], version: &[Entry]) {
+    let code = version
+        .first()
+        .or_else(|| parent.first())
+        .map(|entry| entry[0].0.clone());
+
+    match rule {
+        Fields(offsets) => {
+            if let (Some(parent), Some(version)) = (single(parent), single(version)) {
+                let length = single(merged).map(<[u8]>::len);
+
+                if parent.len() == version.len() && length == Some(version.len()) {
+                    let data = &mut merged[0][0].1;
+
+                    for (position, start) in offsets.iter().enumerate() {
+                        let end = offsets.get(position + 1).copied().unwrap_or(data.len()).min(data.len());
+
+                        if *start < end && parent[*start..end] != version[*start..end] {
+                            data[*start..end].copy_from_slice(&version[*start..end]);
+                        }
+                    }
+
+                    return;
+                }
+            }
+
+            *merged = version.to_vec();
+        }
+        Array(size) => {
+            let chunks = |entries: &[Entry]| {
+                entries
+                    .iter()
+                    .flat_map(|entry| entry[0].1.chunks(size).map(<[u8]>::to_vec))
+                    .collect::<Vec<_>>()
+            };
+
+            let mut items = chunks(merged);
+            apply_list_edits(&mut items, &chunks(parent), &chunks(version));
+
+            *merged = match code {
+                Some(code) if !items.is_empty() => vec![vec![(code, items.concat())]],
+                _ => vec![],
+            };
+        }
+        List => apply_list_edits(merged, parent, version),
+        Replace => *merged = version.to_vec(),
+    }
+}
+
+/// The outcome of combining a record's versions
+enum Combined {
+    /// The winning version already holds every edit
+    Unchanged,
+    Merged(Record),
+    Skipped(String),
+}
+
+/// Combine the versions of a form that at least two plugins edit, leveled lists by merging their entries
+fn combine_form(
+    load_order: &LoadOrder,
+    form: &GlobalFormId,
+    versions: Vec<(&LoadedPlugin, &Record)>,
+) -> Result<Combined, crate::Error> {
+    if leveled::is_leveled_list(&versions[0].1.header.code) {
+        return Ok(match leveled::merge(load_order, form)? {
+            Some(record) => Combined::Merged(record),
+            None => Combined::Unchanged,
+        });
+    }
+
+    let versions = versions
+        .into_iter()
+        .map(|(plugin, record)| Ok((plugin, load_order.absolute_record(plugin, record)?)))
+        .collect::<Result<Vec<_>, crate::Error>>()?;
+
+    Ok(combine(&versions))
+}
+
+/// Combine the versions of a record, each given in load order with FormIDs in `LoadOrder::absolute_form_id` terms
+/// and the plugin it comes from
+fn combine(versions: &[(&LoadedPlugin, Record)]) -> Combined {
+    let fields = versions
+        .iter()
+        .map(|(_, record)| Fields::new(record))
+        .collect::<Vec<_>>();
+    let (_, winning) = &versions[versions.len() - 1];
+    let code = &winning.header.code;
+
+    // Each field's value, along with the version it was last taken from
+    let mut merged: HashMap<TypeCode, (Vec<Entry>, usize)> = fields[0]
+        .values
+        .iter()
+        .map(|(code, entries)| (code.clone(), (entries.clone(), 0)))
+        .collect();
+
+    for (position, (plugin, _)) in versions.iter().enumerate().skip(1) {
+        let masters = plugin.plugin.masters();
+        let parent = (0..position)
+            .rev()
+            .find(|earlier| {
+                masters
+                    .iter()
+                    .any(|master| master.eq_ignore_ascii_case(&versions[*earlier].0.name))
+            })
+            .unwrap_or(0);
+
+        let mut codes = fields[parent].order.clone();
+        codes.extend(
+            fields[position]
+                .order
+                .iter()
+                .filter(|code| !fields[parent].order.contains(code))
+                .cloned(),
+        );
+
+        for field in &codes {
+            let (before, after) = (fields[parent].get(field), fields[position].get(field));
+
+            if before != after {
+                let (value, source) = merged.entry(field.clone()).or_default();
+                merge_field(rule(code, field), value, before, after);
+                *source = position;
+            }
+        }
+    }
+
+    for (field, (entries, source)) in &merged {
+        let plugin = versions[*source].0;
+
+        if !entries.is_empty()
+            && is_localized_string(code, field)
+            && plugin.plugin.tes4.header.flags.contains(PluginFlags::LOCALIZED)
+        {
+            return Combined::Skipped(format!("its {} would come from {}'s string tables", field, plugin.name));
+        }
+    }
+
+    // Fields keep the winning version's order. Others go after the field they follow in the version they came from
+    let mut order = fields[fields.len() - 1].order.clone();
+
+    for (field, (_, source)) in &merged {
+        if order.contains(field) {
+            continue;
+        }
+
+        let source_order = &fields[*source].order;
+        let position = source_order
+            .iter()
+            .position(|code| code == field)
+            .and_then(|index| {
+                source_order[..index]
+                    .iter()
+                    .rev()
+                    .find_map(|code| order.iter().position(|o| o == code))
+            })
+            .map_or(0, |position| position + 1);
+
+        order.insert(position, field.clone());
+    }
+
+    let mut subrecords = vec![];
+
+    for field in &order {
+        let entries = match merged.get(field) {
+            Some((entries, _)) if !entries.is_empty() => entries,
+            _ => continue,
+        };
+
+        if let Some((_, count)) = COUNTS.iter().find(|(list, _)| **field == *list) {
+            let length = match rule(code, field) {
+                Array(size) => entries.iter().map(|entry| entry[0].1.len() / size).sum::<usize>(),
+                _ => entries.len(),
+            };
+            subrecords.push((TypeCode::from(*count), (length as u32).to_le_bytes().to_vec()));
+        }
+
+        subrecords.extend(entries.iter().flatten().cloned());
+    }
+
+    let unchanged = winning
+        .subrecords()
+        .iter()
+        .map(|subrecord| (subrecord.code.clone(), subrecord.data.to_vec()))
+        .eq(subrecords.iter().cloned());
+
+    if unchanged {
+        return Combined::Unchanged;
+    }
+
+    let mut record = winning.clone();
+    record.edit_subrecords(|existing| *existing = subrecords);
+
+    Combined::Merged(record)
+}
+
+#[derive(Debug)]
+pub struct Patch {
+    pub plugin: LoadedPlugin,
+    /// Records the patch forwards, by the form they override
+    pub patched: Vec<GlobalFormId>,
+    /// Records with conflicting edits that couldn't be combined, and why
+    pub skipped: Vec<(GlobalFormId, String)>,
+}
+
+/// Build a patch named `name` to load after everything in `load_order`. Every record overridden by more than one
+/// plugin is combined: leveled lists by `leveled::merge`, and the record types in `MERGED_RECORDS` subrecord by
+/// subrecord following `rule`, each plugin's edits taken relative to the version in the last of its masters to have
+/// one. Records whose winning version already holds every edit are left out.
+///
+/// The patch isn't localized, so records whose strings would have to come from a localized plugin's string tables
+/// are skipped. Its masters are the plugins its records refer to
+pub fn build(load_order: &LoadOrder, name: &str) -> Result<Patch, crate::Error> {
+    let first = load_order
+        .plugins
+        .first()
+        .ok_or_else(|| crate::Error::CorruptOrInvalidFile(String::from("nothing to patch")))?;
+
+    let mut forms = BTreeSet::new();
+
+    for plugin in &load_order.plugins {
+        for record in plugin.plugin.records() {
+            let code = &record.header.code;
+
+            if !plugin.is_new_record(record.header.id)
+                && (leveled::is_leveled_list(code) || MERGED_RECORDS.contains(&**code))
+            {
+                forms.insert(plugin.global_form_id(record.header.id));
+            }
+        }
+    }
+
+    let mut records = vec![];
+    let mut patched = vec![];
+    let mut skipped = vec![];
+
+    for form in forms {
+        let versions = load_order.record_versions(&form);
+
+        if versions.len() < 3 {
+            continue;
+        }
+
+        match combine_form(load_order, &form, versions) {
+            Err(crate::Error::UnknownFormIdLayout(unmapped)) => {
+                skipped.push((form, format!("FormID layout unknown for {}", unmapped)))
+            }
+            Err(error) => return Err(error),
+            Ok(combined) => match combined {
+                Combined::Merged(record) => {
+                    records.push(record);
+                    patched.push(form);
+                }
+                Combined::Skipped(reason) => skipped.push((form, reason)),
+                Combined::Unchanged => (),
+            },
+        }
+    }
+
+    // The patch's masters are the plugins its records refer to, in load order
+    let mut used = BTreeSet::new();
+
+    for record in &mut records {
+        schema::remap_form_ids(record, |id| {
+            used.insert(id.index());
+            id
+        })?;
+    }
+
+    if used.len() > MAX_MASTERS {
+        return Err(crate::Error::CorruptOrInvalidFile(format!(
+            "patch would have {} masters",
+            used.len()
+        )));
+    }
+
+    let indices = used
+        .iter()
+        .enumerate()
+        .map(|(master, index)| (*index, master as u8))
+        .collect::<HashMap<_, _>>();
+
+    let mut tes4 = first.plugin.tes4.clone();
+    tes4.header.flags = PluginFlags::empty();
+    tes4.header.unknown_flags = 0;
+
+    let mut plugin = Plugin {
+        tes4,
+        groups: HashMap::new(),
+    };
+
+    {
+        let header = plugin.file_header_mut();
+        header.masters = used
+            .iter()
+            .map(|index| MasterFile {
+                name: load_order.plugins[*index as usize].name.clone(),
+                tag: 0,
+            })
+            .collect();
+        header.hedr.num_records = 0;
+        header.hedr.next_id = FIRST_OBJECT_ID.into();
+        header.hedr.version = load_order
+            .plugins
+            .iter()
+            .map(|plugin| plugin.plugin.file_header().hedr.version)
+            .fold(header.hedr.version, f32::max);
+        header.author = None;
+        header.description = None;
+        header.overrides = vec![];
+    }
+
+    for mut record in records {
+        schema::remap_form_ids(&mut record, |id| id.with_index(indices[&id.index()]))?;
+        plugin.insert_record(record)?;
+    }
+
+    Ok(Patch {
+        plugin: LoadedPlugin {
+            name: String::from(name),
+            plugin,
+        },
+        patched,
+        skipped,
+    })
+}
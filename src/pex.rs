@@ -0,0 +1,559 @@
+//! Compiled Papyrus scripts, as found in Scripts/*.pex. Skyrim writes them big-endian
+
+use std::fmt::{self, Write};
+
+use crate::parsers::common::decode_string;
+
+use nom::{
+    bytes::complete::{tag, take},
+    combinator::{all_consuming, map},
+    multi::{count, length_count, length_data},
+    number::complete::{be_f32, be_i32, be_u16, be_u32, be_u64, be_u8},
+    sequence::tuple,
+};
+
+const MAGIC: [u8; 4] = [0xFA, 0x57, 0xC0, 0xDE];
+
+/// Mnemonic and fixed argument count of each opcode, and whether a counted list of further arguments follows
+const OPCODES: &[(&str, usize, bool)] = &[
+    ("nop", 0, false),
+    ("iadd", 3, false),
+    ("fadd", 3, false),
+    ("isub", 3, false),
+    ("fsub", 3, false),
+    ("imul", 3, false),
+    ("fmul", 3, false),
+    ("idiv", 3, false),
+    ("fdiv", 3, false),
+    ("imod", 3, false),
+    ("not", 2, false),
+    ("ineg", 2, false),
+    ("fneg", 2, false),
+    ("assign", 2, false),
+    ("cast", 2, false),
+    ("cmp_eq", 3, false),
+    ("cmp_lt", 3, false),
+    ("cmp_le", 3, false),
+    ("cmp_gt", 3, false),
+    ("cmp_ge", 3, false),
+    ("jmp", 1, false),
+    ("jmpt", 2, false),
+    ("jmpf", 2, false),
+    ("callmethod", 3, true),
+    ("callparent", 2, true),
+    ("callstatic", 3, true),
+    ("return", 1, false),
+    ("strcat", 3, false),
+    ("propget", 3, false),
+    ("propset", 3, false),
+    ("array_create", 2, false),
+    ("array_length", 2, false),
+    ("array_getelement", 3, false),
+    ("array_setelement", 3, false),
+    ("array_findelement", 4, false),
+    ("array_rfindelement", 4, false),
+];
+
+/// An instruction argument or a variable's initial value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    None,
+    Identifier(String),
+    String(String),
+    Integer(i32),
+    Float(f32),
+    Bool(bool),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::None => write!(f, "None"),
+            Value::Identifier(name) => write!(f, "{}", name),
+            Value::String(value) => write!(f, "{:?}", value),
+            Value::Integer(value) => write!(f, "{}", value),
+            Value::Float(value) => write!(f, "{:?}", value),
+            Value::Bool(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+    pub opcode: u8,
+    pub arguments: Vec<Value>,
+}
+
+impl Instruction {
+    pub fn mnemonic(&self) -> &'static str {
+        OPCODES[self.opcode as usize].0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub return_type: String,
+    pub doc: String,
+    pub user_flags: u32,
+    /// 0x01 for global, 0x02 for native
+    pub flags: u8,
+    /// Names and types
+    pub params: Vec<(String, String)>,
+    pub locals: Vec<(String, String)>,
+    pub instructions: Vec<Instruction>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variable {
+    pub name: String,
+    pub type_name: String,
+    pub user_flags: u32,
+    pub initial_value: Value,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Property {
+    pub name: String,
+    pub type_name: String,
+    pub doc: String,
+    pub user_flags: u32,
+    /// 0x01 readable, 0x02 writable, 0x04 auto
+    pub flags: u8,
+    /// The variable backing an auto property
+    pub auto_variable: Option<String>,
+    pub getter: Option<Function>,
+    pub setter: Option<Function>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct State {
+    /// Empty for the default state
+    pub name: String,
+    pub functions: Vec<(String, Function)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Object {
+    pub name: String,
+    /// Empty when the script extends nothing
+    pub parent: String,
+    pub doc: String,
+    pub user_flags: u32,
+    pub auto_state: String,
+    pub variables: Vec<Variable>,
+    pub properties: Vec<Property>,
+    pub states: Vec<State>,
+}
+
+impl Object {
+    /// Papyrus names are case-insensitive
+    pub fn property(&self, name: &str) -> Option<&Property> {
+        self.properties
+            .iter()
+            .find(|property| property.name.eq_ignore_ascii_case(name))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DebugFunction {
+    pub object: String,
+    pub state: String,
+    pub function: String,
+    /// 0 for a normal function, 1 for a property getter, 2 for a setter
+    pub function_type: u8,
+    /// Source line of each instruction
+    pub line_numbers: Vec<u16>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DebugInfo {
+    pub modification_time: u64,
+    pub functions: Vec<DebugFunction>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pex {
+    pub major_version: u8,
+    pub minor_version: u8,
+    pub game_id: u16,
+    pub compilation_time: u64,
+    pub source_file: String,
+    pub user: String,
+    pub machine: String,
+    pub strings: Vec<String>,
+    pub debug_info: Option<DebugInfo>,
+    /// User flag names and their bit index
+    pub user_flags: Vec<(String, u8)>,
+    pub objects: Vec<Object>,
+}
+
+impl Pex {
+    pub fn parse(bytes: &[u8]) -> Result<Self, crate::Error> {
+        if !bytes.starts_with(&MAGIC) {
+            return Err(crate::Error::CorruptOrInvalidFile(String::from(
+                "not a big-endian Papyrus script",
+            )));
+        }
+
+        Ok(all_consuming(pex)(bytes)?.1)
+    }
+
+    pub fn object(&self, name: &str) -> Option<&Object> {
+        self.objects
+            .iter()
+            .find(|object| object.name.eq_ignore_ascii_case(name))
+    }
+
+    /// A Papyrus assembly listing of the script
+    pub fn disassemble(&self) -> String {
+        let mut out = String::new();
+
+        writeln!(out, ".info").unwrap();
+        writeln!(out, "  .source {:?}", self.source_file).unwrap();
+        writeln!(out, "  .compileTime {}", self.compilation_time).unwrap();
+        if let Some(debug_info) = &self.debug_info {
+            writeln!(out, "  .modifyTime {}", debug_info.modification_time).unwrap();
+        }
+        writeln!(out, "  .user {:?}", self.user).unwrap();
+        writeln!(out, "  .computer {:?}", self.machine).unwrap();
+        writeln!(out, ".endInfo").unwrap();
+
+        if !self.user_flags.is_empty() {
+            writeln!(out, ".userFlagsRef").unwrap();
+            for (name, index) in &self.user_flags {
+                writeln!(out, "  .flag {} {}", name, index).unwrap();
+            }
+            writeln!(out, ".endUserFlagsRef").unwrap();
+        }
+
+        for object in &self.objects {
+            writeln!(out, ".object {} {}", object.name, object.parent).unwrap();
+            writeln!(out, "  .userFlags {}", object.user_flags).unwrap();
+            writeln!(out, "  .docString {:?}", object.doc).unwrap();
+            writeln!(out, "  .autoState {}", object.auto_state).unwrap();
+
+            for variable in &object.variables {
+                writeln!(
+                    out,
+                    "  .variable {} {} = {}",
+                    variable.name, variable.type_name, variable.initial_value
+                )
+                .unwrap();
+            }
+
+            for property in &object.properties {
+                match &property.auto_variable {
+                    Some(variable) => writeln!(
+                        out,
+                        "  .property {} {} auto {}",
+                        property.name, property.type_name, variable
+                    )
+                    .unwrap(),
+                    None => writeln!(out, "  .property {} {}", property.name, property.type_name).unwrap(),
+                }
+
+                if let Some(getter) = &property.getter {
+                    write_function(&mut out, "get", getter, 4);
+                }
+                if let Some(setter) = &property.setter {
+                    write_function(&mut out, "set", setter, 4);
+                }
+            }
+
+            for state in &object.states {
+                writeln!(out, "  .state {}", state.name).unwrap();
+                for (name, function) in &state.functions {
+                    write_function(&mut out, name, function, 4);
+                }
+                writeln!(out, "  .endState").unwrap();
+            }
+
+            writeln!(out, ".endObject").unwrap();
+        }
+
+        out
+    }
+}
+
+fn write_function(out: &mut String, name: &str, function: &Function, indent: usize) {
+    let pad = " ".repeat(indent);
+    let native = if function.flags & 0x02 != 0 { " native" } else { "" };
+    let global = if function.flags & 0x01 != 0 { " static" } else { "" };
+
+    writeln!(out, "{}.function {}{}{}", pad, name, global, native).unwrap();
+    writeln!(out, "{}  .return {}", pad, function.return_type).unwrap();
+
+    for (name, type_name) in &function.params {
+        writeln!(out, "{}  .param {} {}", pad, name, type_name).unwrap();
+    }
+    for (name, type_name) in &function.locals {
+        writeln!(out, "{}  .local {} {}", pad, name, type_name).unwrap();
+    }
+
+    for (index, instruction) in function.instructions.iter().enumerate() {
+        let arguments = instruction
+            .arguments
+            .iter()
+            .map(Value::to_string)
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        writeln!(out, "{}  {:>4}: {} {}", pad, index, instruction.mnemonic(), arguments).unwrap();
+    }
+
+    writeln!(out, "{}.endFunction", pad).unwrap();
+}
+
+fn wstring(bytes: &[u8]) -> crate::IResult<&[u8], String> {
+    map(length_data(be_u16), decode_string)(bytes)
+}
+
+/// An index into the string table
+fn string_ref<'a>(bytes: &'a [u8], strings: &[String]) -> crate::IResult<&'a [u8], String> {
+    let (bytes, index) = be_u16(bytes)?;
+
+    match strings.get(index as usize) {
+        Some(string) => Ok((bytes, string.clone())),
+        None => Err(nom::Err::Failure(crate::Error::CorruptOrInvalidFile(format!(
+            "string index {} out of range",
+            index
+        )))),
+    }
+}
+
+fn value<'a>(bytes: &'a [u8], strings: &[String]) -> crate::IResult<&'a [u8], Value> {
+    let (bytes, value_type) = be_u8(bytes)?;
+
+    match value_type {
+        0 => Ok((bytes, Value::None)),
+        1 => map(|bytes| string_ref(bytes, strings), Value::Identifier)(bytes),
+        2 => map(|bytes| string_ref(bytes, strings), Value::String)(bytes),
+        3 => map(be_i32, Value::Integer)(bytes),
+        4 => map(be_f32, Value::Float)(bytes),
+        5 => map(be_u8, |value| Value::Bool(value != 0))(bytes),
+        _ => Err(nom::Err::Failure(crate::Error::CorruptOrInvalidFile(format!(
+            "unknown value type {}",
+            value_type
+        )))),
+    }
+}
+
+fn instruction<'a>(bytes: &'a [u8], strings: &[String]) -> crate::IResult<&'a [u8], Instruction> {
+    let (bytes, opcode) = be_u8(bytes)?;
+
+    let (_, fixed, variadic) = OPCODES
+        .get(opcode as usize)
+        .ok_or_else(|| nom::Err::Failure(crate::Error::CorruptOrInvalidFile(format!("unknown opcode {}", opcode))))?;
+
+    let (mut bytes, mut arguments) = count(|bytes| value(bytes, strings), *fixed)(bytes)?;
+
+    if *variadic {
+        let (rest, extra) = value(bytes, strings)?;
+        let extra = match extra {
+            Value::Integer(extra) if extra >= 0 => extra as usize,
+            _ => {
+                return Err(nom::Err::Failure(crate::Error::CorruptOrInvalidFile(String::from(
+                    "bad argument count",
+                ))))
+            }
+        };
+
+        let (rest, more) = count(|bytes| value(bytes, strings), extra)(rest)?;
+        arguments.extend(more);
+        bytes = rest;
+    }
+
+    Ok((bytes, Instruction { opcode, arguments }))
+}
+
+fn name_and_type<'a>(bytes: &'a [u8], strings: &[String]) -> crate::IResult<&'a [u8], (String, String)> {
+    tuple((|bytes| string_ref(bytes, strings), |bytes| string_ref(bytes, strings)))(bytes)
+}
+
+fn function<'a>(bytes: &'a [u8], strings: &[String]) -> crate::IResult<&'a [u8], Function> {
+    let (bytes, (return_type, doc, user_flags, flags)) = tuple((
+        |bytes| string_ref(bytes, strings),
+        |bytes| string_ref(bytes, strings),
+        be_u32,
+        be_u8,
+    ))(bytes)?;
+    let (bytes, params) = length_count(be_u16, |bytes| name_and_type(bytes, strings))(bytes)?;
+    let (bytes, locals) = length_count(be_u16, |bytes| name_and_type(bytes, strings))(bytes)?;
+    let (bytes, instructions) = length_count(be_u16, |bytes| instruction(bytes, strings))(bytes)?;
+
+    Ok((
+        bytes,
+        Function {
+            return_type,
+            doc,
+            user_flags,
+            flags,
+            params,
+            locals,
+            instructions,
+        },
+    ))
+}
+
+fn variable<'a>(bytes: &'a [u8], strings: &[String]) -> crate::IResult<&'a [u8], Variable> {
+    map(
+        tuple((
+            |bytes| name_and_type(bytes, strings),
+            be_u32,
+            |bytes| value(bytes, strings),
+        )),
+        |((name, type_name), user_flags, initial_value)| Variable {
+            name,
+            type_name,
+            user_flags,
+            initial_value,
+        },
+    )(bytes)
+}
+
+fn property<'a>(bytes: &'a [u8], strings: &[String]) -> crate::IResult<&'a [u8], Property> {
+    let (bytes, ((name, type_name), doc, user_flags, flags)) = tuple((
+        |bytes| name_and_type(bytes, strings),
+        |bytes| string_ref(bytes, strings),
+        be_u32,
+        be_u8,
+    ))(bytes)?;
+
+    let mut property = Property {
+        name,
+        type_name,
+        doc,
+        user_flags,
+        flags,
+        auto_variable: None,
+        getter: None,
+        setter: None,
+    };
+
+    let mut bytes = bytes;
+
+    if flags & 0x04 != 0 {
+        let (rest, variable) = string_ref(bytes, strings)?;
+        property.auto_variable = Some(variable);
+        bytes = rest;
+    } else {
+        if flags & 0x01 != 0 {
+            let (rest, getter) = function(bytes, strings)?;
+            property.getter = Some(getter);
+            bytes = rest;
+        }
+        if flags & 0x02 != 0 {
+            let (rest, setter) = function(bytes, strings)?;
+            property.setter = Some(setter);
+            bytes = rest;
+        }
+    }
+
+    Ok((bytes, property))
+}
+
+fn state<'a>(bytes: &'a [u8], strings: &[String]) -> crate::IResult<&'a [u8], State> {
+    let (bytes, name) = string_ref(bytes, strings)?;
+    let (bytes, functions) = length_count(
+        be_u16,
+        tuple((|bytes| string_ref(bytes, strings), |bytes| function(bytes, strings))),
+    )(bytes)?;
+
+    Ok((bytes, State { name, functions }))
+}
+
+fn object<'a>(bytes: &'a [u8], strings: &[String]) -> crate::IResult<&'a [u8], Object> {
+    let (bytes, (name, size)) = tuple((|bytes| string_ref(bytes, strings), be_u32))(bytes)?;
+    // The size counts itself
+    let (bytes, data) = take(size.saturating_sub(4))(bytes)?;
+
+    let (data, (parent, doc, user_flags, auto_state)) = tuple((
+        |bytes| string_ref(bytes, strings),
+        |bytes| string_ref(bytes, strings),
+        be_u32,
+        |bytes| string_ref(bytes, strings),
+    ))(data)?;
+    let (data, variables) = length_count(be_u16, |bytes| variable(bytes, strings))(data)?;
+    let (data, properties) = length_count(be_u16, |bytes| property(bytes, strings))(data)?;
+    all_consuming(length_count(be_u16, |bytes| state(bytes, strings)))(data).map(|(_, states)| {
+        (
+            bytes,
+            Object {
+                name,
+                parent,
+                doc,
+                user_flags,
+                auto_state,
+                variables,
+                properties,
+                states,
+            },
+        )
+    })
+}
+
+fn debug_info<'a>(bytes: &'a [u8], strings: &[String]) -> crate::IResult<&'a [u8], Option<DebugInfo>> {
+    let (bytes, has_debug_info) = be_u8(bytes)?;
+
+    if has_debug_info == 0 {
+        return Ok((bytes, None));
+    }
+
+    let (bytes, modification_time) = be_u64(bytes)?;
+    let (bytes, functions) = length_count(be_u16, |bytes| {
+        let (bytes, (object, state, function, function_type)) = tuple((
+            |bytes| string_ref(bytes, strings),
+            |bytes| string_ref(bytes, strings),
+            |bytes| string_ref(bytes, strings),
+            be_u8,
+        ))(bytes)?;
+        let (bytes, line_numbers) = length_count(be_u16, be_u16)(bytes)?;
+
+        Ok((
+            bytes,
+            DebugFunction {
+                object,
+                state,
+                function,
+                function_type,
+                line_numbers,
+            },
+        ))
+    })(bytes)?;
+
+    Ok((
+        bytes,
+        Some(DebugInfo {
+            modification_time,
+            functions,
+        }),
+    ))
+}
+
+fn pex(bytes: &[u8]) -> crate::IResult<&[u8], Pex> {
+    let (bytes, _) = tag(MAGIC)(bytes)?;
+    let (bytes, (major_version, minor_version, game_id, compilation_time)) =
+        tuple((be_u8, be_u8, be_u16, be_u64))(bytes)?;
+    let (bytes, (source_file, user, machine)) = tuple((wstring, wstring, wstring))(bytes)?;
+    let (bytes, strings) = length_count(be_u16, wstring)(bytes)?;
+    let (bytes, debug_info) = debug_info(bytes, &strings)?;
+    let (bytes, user_flags) = length_count(be_u16, tuple((|bytes| string_ref(bytes, &strings), be_u8)))(bytes)?;
+    let (bytes, objects) = length_count(be_u16, |bytes| object(bytes, &strings))(bytes)?;
+
+    Ok((
+        bytes,
+        Pex {
+            major_version,
+            minor_version,
+            game_id,
+            compilation_time,
+            source_file,
+            user,
+            machine,
+            strings,
+            debug_info,
+            user_flags,
+            objects,
+        },
+    ))
+}
@@ -0,0 +1,663 @@
+use std::{convert::TryInto, iter, ops, str::FromStr};
+
+use crate::{
+    load_order::{LoadOrder, LoadedPlugin},
+    parsers::{common::FormId, common::TypeCode, records::flags::RecordFlags, records::Record},
+};
+
+/// Fixed-size value types a field can be read as
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FieldKind {
+    U8,
+    U16,
+    U32,
+    I8,
+    I16,
+    I32,
+    F32,
+}
+
+impl FieldKind {
+    fn size(self) -> usize {
+        match self {
+            FieldKind::U8 | FieldKind::I8 => 1,
+            FieldKind::U16 | FieldKind::I16 => 2,
+            FieldKind::U32 | FieldKind::I32 | FieldKind::F32 => 4,
+        }
+    }
+
+    fn read(self, bytes: &[u8]) -> f64 {
+        match self {
+            FieldKind::U8 => bytes[0] as f64,
+            FieldKind::I8 => bytes[0] as i8 as f64,
+            FieldKind::U16 => u16::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            FieldKind::I16 => i16::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            FieldKind::U32 => u32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            FieldKind::I32 => i32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            FieldKind::F32 => f32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+        }
+    }
+}
+
+impl FromStr for FieldKind {
+    type Err = crate::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "u8" => Ok(FieldKind::U8),
+            "u16" => Ok(FieldKind::U16),
+            "u32" => Ok(FieldKind::U32),
+            "i8" => Ok(FieldKind::I8),
+            "i16" => Ok(FieldKind::I16),
+            "i32" => Ok(FieldKind::I32),
+            "f32" => Ok(FieldKind::F32),
+            _ => Err(crate::Error::InvalidQuery(format!("unknown field type {}", value))),
+        }
+    }
+}
+
+/// A value at a fixed offset into the first subrecord with the given code
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub subrecord: TypeCode,
+    pub offset: usize,
+    pub kind: FieldKind,
+}
+
+impl Field {
+    pub fn read(&self, record: &Record) -> Option<f64> {
+        let subrecord = record.subrecord(&self.subrecord)?;
+        let bytes = subrecord.data.get(self.offset..self.offset + self.kind.size())?;
+
+        Some(self.kind.read(bytes))
+    }
+}
+
+/// Record type, field name, subrecord, offset and kind
+type NamedField = (&'static [u8; 4], &'static str, &'static [u8; 4], usize, FieldKind);
+
+/// Fields known by name, per record type
+const NAMED_FIELDS: &[NamedField] = &[
+    (b"ALCH", "weight", b"DATA", 0, FieldKind::F32),
+    (b"ALCH", "value", b"ENIT", 0, FieldKind::I32),
+    (b"AMMO", "damage", b"DATA", 8, FieldKind::F32),
+    (b"AMMO", "value", b"DATA", 12, FieldKind::U32),
+    (b"ARMO", "value", b"DATA", 0, FieldKind::I32),
+    (b"ARMO", "weight", b"DATA", 4, FieldKind::F32),
+    // Stored multiplied by 100
+    (b"ARMO", "rating", b"DNAM", 0, FieldKind::I32),
+    (b"BOOK", "value", b"DATA", 8, FieldKind::U32),
+    (b"BOOK", "weight", b"DATA", 12, FieldKind::F32),
+    (b"INGR", "value", b"DATA", 0, FieldKind::U32),
+    (b"INGR", "weight", b"DATA", 4, FieldKind::F32),
+    (b"KEYM", "value", b"DATA", 0, FieldKind::U32),
+    (b"KEYM", "weight", b"DATA", 4, FieldKind::F32),
+    (b"MISC", "value", b"DATA", 0, FieldKind::U32),
+    (b"MISC", "weight", b"DATA", 4, FieldKind::F32),
+    (b"NPC_", "level", b"ACBS", 8, FieldKind::I16),
+    (b"SLGM", "value", b"DATA", 0, FieldKind::U32),
+    (b"SLGM", "weight", b"DATA", 4, FieldKind::F32),
+    (b"WEAP", "value", b"DATA", 0, FieldKind::U32),
+    (b"WEAP", "weight", b"DATA", 4, FieldKind::F32),
+    (b"WEAP", "damage", b"DATA", 8, FieldKind::U16),
+    (b"WEAP", "speed", b"DNAM", 4, FieldKind::F32),
+    (b"WEAP", "reach", b"DNAM", 8, FieldKind::F32),
+];
+
+/// The field a record type calls `name`, if there is one
+pub fn named_field(code: &TypeCode, name: &str) -> Option<Field> {
+    NAMED_FIELDS
+        .iter()
+        .find(|(record, field, ..)| &**code == *record && field.eq_ignore_ascii_case(name))
+        .map(|(_, _, subrecord, offset, kind)| Field {
+            subrecord: TypeCode::from(**subrecord),
+            offset: *offset,
+            kind: *kind,
+        })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldRef {
+    /// Resolved against each record's type with `named_field`, records without the field never match
+    Named(String),
+    Raw(Field),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    fn test(self, left: f64, right: f64) -> bool {
+        match self {
+            Comparison::Equal => left == right,
+            Comparison::NotEqual => left != right,
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::Greater => left > right,
+            Comparison::GreaterOrEqual => left >= right,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Keyword {
+    /// A FormID local to the plugin being queried
+    Id(FormId),
+    /// Needs `Filter::resolve` before it can match anything
+    EditorId(String),
+}
+
+const FLAG_NAMES: &[(&str, RecordFlags)] = &[
+    ("deleted", RecordFlags::DELETED),
+    ("constant", RecordFlags::CONSTANT),
+    ("must_update_anims", RecordFlags::MUST_UPDATE_ANIMS),
+    ("hidden_from_local_map", RecordFlags::HIDDEN_FROM_LOCAL_MAP),
+    ("quest_item", RecordFlags::QUEST_ITEM),
+    ("initially_disabled", RecordFlags::INITIALLY_DISABLED),
+    ("ignored", RecordFlags::IGNORED),
+    ("visible_when_distant", RecordFlags::VISIBLE_WHEN_DISTANT),
+    ("random_animation_start", RecordFlags::RANDOM_ANIMATION_START),
+    ("dangerous", RecordFlags::DANGEROUS),
+    ("compressed", RecordFlags::COMPRESSED),
+    ("cannot_wait", RecordFlags::CANNOT_WAIT),
+    ("ignore_object_interaction", RecordFlags::IGNORE_OBJECT_INTERACTION),
+    ("marker", RecordFlags::MARKER),
+    ("obstacle", RecordFlags::OBSTACLE),
+    ("navmesh_gen_filter", RecordFlags::NAVMESH_GEN_FILTER),
+    ("navmesh_gen_bbox", RecordFlags::NAVMESH_GEN_BBOX),
+    ("reflected_by_water", RecordFlags::REFLECTED_BY_WATER),
+    ("no_havok_settle", RecordFlags::NO_HAVOK_SETTLE),
+    ("no_respawn", RecordFlags::NO_RESPAWN),
+    ("multi_bound", RecordFlags::MULTI_BOUND),
+];
+
+/// A composable predicate over records.
+///
+/// Filters can be built directly, combined with `and`, `or` and `!`, or parsed from a query string:
+///
+/// ```text
+/// WEAP where damage > 20 and keyword WeapTypeSword
+/// ARMO,WEAP where not flag deleted and (edid = "Dwarven*" or value >= 500)
+/// NPC_ where ACBS[8]:i16 >= 30 and has SPLO
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    Any,
+    Code(TypeCode),
+    /// Case-insensitive, with `*` matching any run of characters
+    EditorId(String),
+    Flag(RecordFlags),
+    HasSubrecord(TypeCode),
+    Keyword(Keyword),
+    Compare(FieldRef, Comparison, f64),
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+    Not(Box<Filter>),
+}
+
+impl Filter {
+    pub fn and(self, other: Filter) -> Filter {
+        Filter::And(Box::new(self), Box::new(other))
+    }
+
+    pub fn or(self, other: Filter) -> Filter {
+        Filter::Or(Box::new(self), Box::new(other))
+    }
+
+    /// Whether a single record matches. Keywords given by editor ID can't be looked up from a record alone, so
+    /// they match nothing until the filter is resolved; `select` takes care of that
+    pub fn matches(&self, record: &Record) -> bool {
+        match self {
+            Filter::Any => true,
+            Filter::Code(code) => record.header.code == *code,
+            Filter::EditorId(pattern) => record
+                .header
+                .editor_id
+                .as_deref()
+                .is_some_and(|editor_id| glob_match(pattern, editor_id)),
+            Filter::Flag(flag) => record.header.flags.contains(*flag),
+            Filter::HasSubrecord(code) => record.subrecord(code).is_some(),
+            Filter::Keyword(Keyword::Id(id)) => keywords(record).contains(id),
+            Filter::Keyword(Keyword::EditorId(_)) => false,
+            Filter::Compare(field, comparison, value) => {
+                let field = match field {
+                    FieldRef::Named(name) => named_field(&record.header.code, name),
+                    FieldRef::Raw(field) => Some(field.clone()),
+                };
+
+                field
+                    .and_then(|field| field.read(record))
+                    .is_some_and(|field| comparison.test(field, *value))
+            }
+            Filter::And(left, right) => left.matches(record) && right.matches(record),
+            Filter::Or(left, right) => left.matches(record) || right.matches(record),
+            Filter::Not(filter) => !filter.matches(record),
+        }
+    }
+
+    /// The plugin's matching records, in FormID order. Keywords given by editor ID are resolved first, failing as
+    /// `resolve` does for any that can't be found
+    pub fn select<'a>(
+        &self,
+        plugin: &'a LoadedPlugin,
+        load_order: &LoadOrder,
+    ) -> Result<Vec<&'a Record>, crate::Error> {
+        let filter = self.clone().resolve(plugin, load_order)?;
+
+        let mut records = plugin
+            .plugin
+            .records()
+            .into_iter()
+            .filter(|record| filter.matches(record))
+            .collect::<Vec<_>>();
+
+        records.sort_by_key(|record| record.header.id);
+        Ok(records)
+    }
+
+    /// Replace keywords given by editor ID with the FormID `plugin` knows them by, looking for the KYWD record
+    /// in `load_order` and then `plugin` itself
+    pub fn resolve(self, plugin: &LoadedPlugin, load_order: &LoadOrder) -> Result<Filter, crate::Error> {
+        Ok(match self {
+            Filter::Keyword(Keyword::EditorId(editor_id)) => {
+                let global_id = load_order
+                    .plugins
+                    .iter()
+                    .chain(iter::once(plugin))
+                    .find_map(|source| {
+                        source
+                            .plugin
+                            .records()
+                            .into_iter()
+                            .find(|record| {
+                                &*record.header.code == b"KYWD"
+                                    && record
+                                        .header
+                                        .editor_id
+                                        .as_deref()
+                                        .is_some_and(|id| id.eq_ignore_ascii_case(&editor_id))
+                            })
+                            .map(|record| source.global_form_id(record.header.id))
+                    })
+                    .ok_or_else(|| crate::Error::InvalidQuery(format!("unknown keyword {}", editor_id)))?;
+
+                let id = plugin.local_form_id(&global_id).ok_or_else(|| {
+                    crate::Error::InvalidQuery(format!("keyword {} is not available to {}", editor_id, plugin.name))
+                })?;
+
+                Filter::Keyword(Keyword::Id(id))
+            }
+            Filter::And(left, right) => left
+                .resolve(plugin, load_order)?
+                .and(right.resolve(plugin, load_order)?),
+            Filter::Or(left, right) => left.resolve(plugin, load_order)?.or(right.resolve(plugin, load_order)?),
+            Filter::Not(filter) => !filter.resolve(plugin, load_order)?,
+            filter => filter,
+        })
+    }
+}
+
+impl ops::Not for Filter {
+    type Output = Filter;
+
+    fn not(self) -> Filter {
+        Filter::Not(Box::new(self))
+    }
+}
+
+impl FromStr for Filter {
+    type Err = crate::Error;
+
+    fn from_str(query: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(query)?;
+
+        let (codes, expression) = match tokens.iter().position(|token| token.is_word("where")) {
+            Some(position) => (&tokens[..position], &tokens[position + 1..]),
+            None if tokens.iter().all(|token| token.is_code() || *token == Token::Comma) => (&tokens[..], &[][..]),
+            None => (&[][..], &tokens[..]),
+        };
+
+        let mut filter = None;
+
+        for token in codes.iter().filter(|token| **token != Token::Comma) {
+            let code = match token {
+                Token::Word(word) if token.is_code() => Filter::Code(type_code(word)),
+                token => {
+                    return Err(crate::Error::InvalidQuery(format!(
+                        "expected a record type, got {}",
+                        token
+                    )))
+                }
+            };
+
+            filter = Some(match filter {
+                Some(filter) => Filter::or(filter, code),
+                None => code,
+            });
+        }
+
+        let filter = filter.unwrap_or(Filter::Any);
+
+        if expression.is_empty() {
+            return Ok(filter);
+        }
+
+        let mut parser = Parser {
+            tokens: expression,
+            position: 0,
+        };
+        let expression = parser.expression()?;
+
+        if let Some(token) = parser.peek() {
+            return Err(crate::Error::InvalidQuery(format!("unexpected {}", token)));
+        }
+
+        Ok(match filter {
+            Filter::Any => expression,
+            filter => filter.and(expression),
+        })
+    }
+}
+
+fn keywords(record: &Record) -> Vec<FormId> {
+    record
+        .subrecord(b"KWDA")
+        .map(|subrecord| {
+            subrecord
+                .data
+                .chunks_exact(4)
+                .map(|id| FormId::from(u32::from_le_bytes(id.try_into().unwrap())))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn glob_match(pattern: &str, value: &str) -> bool {
+    let pattern = pattern.to_lowercase();
+    let value = value.to_lowercase();
+    let mut parts = pattern.split('*');
+
+    let first = parts.next().unwrap_or_default();
+    let mut rest = match value.strip_prefix(first) {
+        Some(rest) => rest,
+        None => return false,
+    };
+
+    let parts = parts.collect::<Vec<_>>();
+
+    match parts.split_last() {
+        None => rest.is_empty(),
+        Some((last, middle)) => {
+            for part in middle {
+                match rest.find(part) {
+                    Some(position) => rest = &rest[position + part.len()..],
+                    None => return false,
+                }
+            }
+
+            rest.len() >= last.len() && rest.ends_with(last)
+        }
+    }
+}
+
+fn type_code(word: &str) -> TypeCode {
+    let mut code = [0; 4];
+    code.copy_from_slice(word.as_bytes());
+    TypeCode::from(code)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    String(String),
+    Operator(Comparison),
+    Open,
+    Close,
+    Comma,
+}
+
+impl Token {
+    fn is_word(&self, keyword: &str) -> bool {
+        matches!(self, Token::Word(word) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    /// Record and subrecord codes are four uppercase letters, digits or underscores
+    fn is_code(&self) -> bool {
+        matches!(self, Token::Word(word) if word.len() == 4
+            && word.bytes().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == b'_'))
+    }
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "{}", word),
+            Token::String(string) => write!(f, "\"{}\"", string),
+            Token::Operator(comparison) => write!(f, "{:?}", comparison),
+            Token::Open => write!(f, "("),
+            Token::Close => write!(f, ")"),
+            Token::Comma => write!(f, ","),
+        }
+    }
+}
+
+fn tokenize(query: &str) -> Result<Vec<Token>, crate::Error> {
+    let mut tokens = vec![];
+    let mut chars = query.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => (),
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            ',' => tokens.push(Token::Comma),
+            '"' => {
+                let mut string = String::new();
+
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => string.push(c),
+                        None => return Err(crate::Error::InvalidQuery(String::from("unterminated string"))),
+                    }
+                }
+
+                tokens.push(Token::String(string));
+            }
+            '<' | '>' | '=' | '!' => {
+                let equals = chars.peek() == Some(&'=');
+
+                if equals {
+                    chars.next();
+                }
+
+                let comparison = match (c, equals) {
+                    ('<', false) => Comparison::Less,
+                    ('<', true) => Comparison::LessOrEqual,
+                    ('>', false) => Comparison::Greater,
+                    ('>', true) => Comparison::GreaterOrEqual,
+                    ('=', _) => Comparison::Equal,
+                    ('!', true) => Comparison::NotEqual,
+                    _ => return Err(crate::Error::InvalidQuery(String::from("expected != "))),
+                };
+
+                tokens.push(Token::Operator(comparison));
+            }
+            c => {
+                let mut word = String::from(c);
+
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "()<>=!,\"".contains(c) {
+                        break;
+                    }
+
+                    word.push(c);
+                    chars.next();
+                }
+
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive descent over `or`, `and`, `not` and parentheses, in increasing order of precedence
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Result<&'a Token, crate::Error> {
+        let token = self
+            .peek()
+            .ok_or_else(|| crate::Error::InvalidQuery(String::from("unexpected end of query")))?;
+        self.position += 1;
+
+        Ok(token)
+    }
+
+    fn word(&mut self) -> Result<&'a str, crate::Error> {
+        match self.next()? {
+            Token::Word(word) | Token::String(word) => Ok(word),
+            token => Err(crate::Error::InvalidQuery(format!("unexpected {}", token))),
+        }
+    }
+
+    fn expression(&mut self) -> Result<Filter, crate::Error> {
+        let mut filter = self.term()?;
+
+        while self.peek().is_some_and(|token| token.is_word("or")) {
+            self.position += 1;
+            filter = filter.or(self.term()?);
+        }
+
+        Ok(filter)
+    }
+
+    fn term(&mut self) -> Result<Filter, crate::Error> {
+        let mut filter = self.factor()?;
+
+        while self.peek().is_some_and(|token| token.is_word("and")) {
+            self.position += 1;
+            filter = filter.and(self.factor()?);
+        }
+
+        Ok(filter)
+    }
+
+    fn factor(&mut self) -> Result<Filter, crate::Error> {
+        match self.next()? {
+            Token::Open => {
+                let filter = self.expression()?;
+
+                match self.next()? {
+                    Token::Close => Ok(filter),
+                    token => Err(crate::Error::InvalidQuery(format!("expected ), got {}", token))),
+                }
+            }
+            token if token.is_word("not") => Ok(!self.factor()?),
+            token if token.is_word("has") => match self.next()? {
+                code @ Token::Word(word) if code.is_code() => Ok(Filter::HasSubrecord(type_code(word))),
+                token => Err(crate::Error::InvalidQuery(format!(
+                    "expected a subrecord type, got {}",
+                    token
+                ))),
+            },
+            token if token.is_word("flag") => {
+                let name = self.word()?;
+
+                FLAG_NAMES
+                    .iter()
+                    .find(|(flag, _)| flag.eq_ignore_ascii_case(name))
+                    .map(|(_, flag)| Filter::Flag(*flag))
+                    .ok_or_else(|| crate::Error::InvalidQuery(format!("unknown flag {}", name)))
+            }
+            token if token.is_word("keyword") => {
+                let keyword = self.word()?;
+
+                Ok(Filter::Keyword(match parse_form_id(keyword) {
+                    Some(id) => Keyword::Id(id),
+                    None => Keyword::EditorId(String::from(keyword)),
+                }))
+            }
+            token if token.is_word("edid") => {
+                let comparison = self.comparison()?;
+                let filter = Filter::EditorId(String::from(self.word()?));
+
+                match comparison {
+                    Comparison::Equal => Ok(filter),
+                    Comparison::NotEqual => Ok(!filter),
+                    comparison => Err(crate::Error::InvalidQuery(format!(
+                        "editor IDs can't be compared with {:?}",
+                        comparison
+                    ))),
+                }
+            }
+            Token::Word(field) => {
+                let field = parse_field(field)?;
+                let comparison = self.comparison()?;
+                let value = self.word()?;
+                let value = value
+                    .parse::<f64>()
+                    .ok()
+                    .or_else(|| parse_form_id(value).map(|id| *id as f64))
+                    .ok_or_else(|| crate::Error::InvalidQuery(format!("expected a number, got {}", value)))?;
+
+                Ok(Filter::Compare(field, comparison, value))
+            }
+            token => Err(crate::Error::InvalidQuery(format!("unexpected {}", token))),
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Comparison, crate::Error> {
+        match self.next()? {
+            Token::Operator(comparison) => Ok(*comparison),
+            token => Err(crate::Error::InvalidQuery(format!(
+                "expected a comparison, got {}",
+                token
+            ))),
+        }
+    }
+}
+
+fn parse_form_id(value: &str) -> Option<FormId> {
+    let hex = value.strip_prefix("0x").or_else(|| value.strip_prefix("0X"))?;
+    u32::from_str_radix(hex, 16).ok().map(FormId::from)
+}
+
+/// Either a named field or `CODE[offset]:type`, e.g. `DATA[8]:u16`
+fn parse_field(field: &str) -> Result<FieldRef, crate::Error> {
+    let open = match field.find('[') {
+        Some(open) => open,
+        None => return Ok(FieldRef::Named(String::from(field))),
+    };
+
+    let invalid = || crate::Error::InvalidQuery(format!("invalid field {}", field));
+
+    let code = &field[..open];
+    let (offset, kind) = field[open + 1..].split_once("]:").ok_or_else(invalid)?;
+
+    if !Token::Word(String::from(code)).is_code() {
+        return Err(invalid());
+    }
+
+    Ok(FieldRef::Raw(Field {
+        subrecord: type_code(code),
+        offset: offset.parse().map_err(|_| invalid())?,
+        kind: kind.parse()?,
+    }))
+}
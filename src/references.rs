@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+
+use crate::{
+    load_order::{GlobalFormId, LoadOrder},
+    schema,
+};
+
+/// A FormID reference held by one plugin's version of a record
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Reference {
+    pub from: GlobalFormId,
+    pub to: GlobalFormId,
+    /// The plugin whose version of `from` holds the reference
+    pub plugin: String,
+}
+
+/// Forward and backward FormID references between records across a load order. Every version of a record is
+/// indexed, not only the winning one, so a form is only safe to delete when nothing in any plugin points at it.
+/// References are only found in subrecords known to `schema`.
+#[derive(Debug, Default)]
+pub struct ReferenceIndex {
+    references: Vec<Reference>,
+    by_source: HashMap<GlobalFormId, Vec<usize>>,
+    by_target: HashMap<GlobalFormId, Vec<usize>>,
+}
+
+impl ReferenceIndex {
+    pub fn build(load_order: &LoadOrder) -> Self {
+        let mut index = Self::default();
+
+        for plugin in &load_order.plugins {
+            for record in plugin.plugin.records() {
+                let from = plugin.global_form_id(record.header.id);
+
+                let mut targets = schema::referenced_form_ids(record)
+                    .into_iter()
+                    .map(|id| plugin.global_form_id(id))
+                    .collect::<Vec<_>>();
+                targets.sort();
+                targets.dedup();
+
+                for to in targets {
+                    index.insert(Reference {
+                        from: from.clone(),
+                        to,
+                        plugin: plugin.name.clone(),
+                    });
+                }
+            }
+        }
+
+        index
+    }
+
+    fn insert(&mut self, reference: Reference) {
+        let position = self.references.len();
+
+        self.by_source.entry(reference.from.clone()).or_default().push(position);
+        self.by_target.entry(reference.to.clone()).or_default().push(position);
+        self.references.push(reference);
+    }
+
+    /// Records pointing at the given form
+    pub fn references_to(&self, id: &GlobalFormId) -> Vec<&Reference> {
+        self.lookup(&self.by_target, id)
+    }
+
+    /// Forms the given record points at
+    pub fn references_from(&self, id: &GlobalFormId) -> Vec<&Reference> {
+        self.lookup(&self.by_source, id)
+    }
+
+    /// Whether anything other than the form's own overrides refers to it
+    pub fn is_referenced(&self, id: &GlobalFormId) -> bool {
+        self.references_to(id).iter().any(|reference| &reference.from != id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.references.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.references.is_empty()
+    }
+
+    fn lookup(&self, map: &HashMap<GlobalFormId, Vec<usize>>, id: &GlobalFormId) -> Vec<&Reference> {
+        let mut references: Vec<&Reference> = map
+            .get(id)
+            .map(|positions| positions.iter().map(|position| &self.references[*position]).collect())
+            .unwrap_or_default();
+
+        references.sort();
+        references
+    }
+}
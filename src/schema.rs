@@ -0,0 +1,864 @@
+//! Knowledge of where FormIDs live inside subrecord data. Subrecords are either known to hold FormIDs at known
+//! places, known to hold none, or unknown, in which case FormIDs can't be rewritten safely.
+
+use crate::parsers::{
+    common::{FormId, Subrecord, TypeCode},
+    records::{condition, navmesh, vmad, Record},
+};
+
+/// Where FormIDs sit inside a subrecord's data
+#[derive(Debug, Clone, Copy)]
+pub enum FormIdLayout {
+    /// No FormIDs at all
+    NoFormIds,
+    /// FormIDs at fixed byte offsets
+    Fields(&'static [usize]),
+    /// A packed array of FormIDs
+    Array,
+    /// Fixed-size entries from byte `start` on, each with FormIDs at the given offsets within the entry
+    Entries {
+        start: usize,
+        size: usize,
+        offsets: &'static [usize],
+    },
+    /// A FormID at the offset only when the u32 type at the start of the data is one of the given types
+    Typed(&'static [u32], usize),
+    /// A FormID at the offset only when the first byte has the given flag set
+    Flagged(u8, usize),
+    /// FormID offsets depending on the size of the data, which differs between game versions
+    BySize(&'static [(usize, &'static [usize])]),
+    /// MODS and the like: a count of alternate textures, each a sized 3D name, a TXST and an index
+    AlternateTextures,
+    /// A CTDA, whose FormIDs depend on its function and flags
+    Condition,
+    /// A VMAD, with FormIDs in its object properties and quest aliases
+    Scripts,
+    /// A NAVM's NVNM geometry, with its parent and the navmeshes and doors it links to
+    Navmesh,
+}
+
+use FormIdLayout::*;
+
+/// PLDT and PLVD location types holding a FormID: near reference, in cell, object ID and linked reference keyword
+const LOCATION_FORM_TYPES: &[u32] = &[0, 1, 4, 6];
+/// PTDA target types holding a FormID: specific reference, object ID and linked reference keyword
+const TARGET_FORM_TYPES: &[u32] = &[0, 1, 3];
+
+/// Subrecords meaning the same thing whatever record they appear in
+const COMMON_FIELDS: &[([u8; 4], FormIdLayout)] = &[
+    (*b"KWDA", Array),
+    (*b"CNTO", Fields(&[0])),
+    (*b"COED", Fields(&[0])),
+    (*b"SPLO", Fields(&[0])),
+    (*b"EITM", Fields(&[0])),
+    (*b"EFID", Fields(&[0])),
+    (*b"ETYP", Fields(&[0])),
+    (*b"BIDS", Fields(&[0])),
+    (*b"CTDA", Condition),
+    (*b"VMAD", Scripts),
+    (*b"BAMT", Fields(&[0])),
+    (*b"YNAM", Fields(&[0])),
+    (*b"ZNAM", Fields(&[0])),
+    (*b"PFIG", Fields(&[0])),
+    (*b"MDOB", Fields(&[0])),
+    (*b"DSTD", Fields(&[8, 12])),
+    (*b"MODS", AlternateTextures),
+    (*b"MO2S", AlternateTextures),
+    (*b"MO3S", AlternateTextures),
+    (*b"MO4S", AlternateTextures),
+    (*b"MO5S", AlternateTextures),
+    (*b"DMDS", AlternateTextures),
+    (*b"XOWN", Fields(&[0])),
+    (*b"XEZN", Fields(&[0])),
+    (*b"XLCN", Fields(&[0])),
+    (*b"XLRL", Fields(&[0])),
+    (*b"XEMI", Fields(&[0])),
+    (*b"XESP", Fields(&[0])),
+    (*b"XTEL", Fields(&[0])),
+    (*b"XLOC", Fields(&[4])),
+    (*b"XLKR", Fields(&[0, 4])),
+    (*b"XLRT", Array),
+    (*b"XAPR", Fields(&[0])),
+    (*b"XPWR", Fields(&[0])),
+    (*b"XNDP", Fields(&[0])),
+    (*b"XLTW", Fields(&[0])),
+    (*b"XPOD", Array),
+    (*b"XMRC", Fields(&[0])),
+    (*b"XMBR", Fields(&[0])),
+    (*b"XCZR", Fields(&[0])),
+    (*b"XCZC", Fields(&[0])),
+    (*b"XSPC", Fields(&[0])),
+    (*b"XLIB", Fields(&[0])),
+    (*b"XATR", Fields(&[0])),
+    (*b"XLRM", Fields(&[0])),
+    (*b"XHOR", Fields(&[0])),
+    (*b"XRFG", Fields(&[0])),
+    (*b"XCAS", Fields(&[0])),
+    (*b"XCIM", Fields(&[0])),
+    (*b"XCMO", Fields(&[0])),
+    (*b"XCWT", Fields(&[0])),
+    (*b"XCCM", Fields(&[0])),
+    (*b"XCLR", Array),
+    (*b"XILL", Fields(&[0])),
+    (*b"LTMP", Fields(&[0])),
+    (*b"LVLG", Fields(&[0])),
+    (*b"LVLO", Fields(&[4])),
+    (*b"QTGL", Array),
+    (*b"EDID", NoFormIds),
+    (*b"FULL", NoFormIds),
+    (*b"DESC", NoFormIds),
+    (*b"OBND", NoFormIds),
+    (*b"MODL", NoFormIds),
+    (*b"MODT", NoFormIds),
+    (*b"MOD2", NoFormIds),
+    (*b"MO2T", NoFormIds),
+    (*b"MOD3", NoFormIds),
+    (*b"MO3T", NoFormIds),
+    (*b"MOD4", NoFormIds),
+    (*b"MO4T", NoFormIds),
+    (*b"MOD5", NoFormIds),
+    (*b"MO5T", NoFormIds),
+    (*b"ICON", NoFormIds),
+    (*b"MICO", NoFormIds),
+    (*b"ICO2", NoFormIds),
+    (*b"MIC2", NoFormIds),
+    (*b"DEST", NoFormIds),
+    (*b"DSTF", NoFormIds),
+    (*b"DMDL", NoFormIds),
+    (*b"DMDT", NoFormIds),
+    (*b"KSIZ", NoFormIds),
+    (*b"COCT", NoFormIds),
+    (*b"SPCT", NoFormIds),
+    (*b"PRKZ", NoFormIds),
+    (*b"CITC", NoFormIds),
+    (*b"CIS1", NoFormIds),
+    (*b"CIS2", NoFormIds),
+    (*b"LLCT", NoFormIds),
+    (*b"LVLD", NoFormIds),
+    (*b"LVLF", NoFormIds),
+    (*b"EAMT", NoFormIds),
+    (*b"EFIT", NoFormIds),
+    (*b"BODT", NoFormIds),
+    (*b"BOD2", NoFormIds),
+    (*b"ACBS", NoFormIds),
+    (*b"AIDT", NoFormIds),
+    (*b"ATKD", NoFormIds),
+    (*b"ATKE", NoFormIds),
+    (*b"CSDT", NoFormIds),
+    (*b"SHRT", NoFormIds),
+    (*b"DODT", NoFormIds),
+    (*b"TX00", NoFormIds),
+    (*b"TX01", NoFormIds),
+    (*b"TX02", NoFormIds),
+    (*b"TX03", NoFormIds),
+    (*b"TX04", NoFormIds),
+    (*b"TX05", NoFormIds),
+    (*b"TX06", NoFormIds),
+    (*b"TX07", NoFormIds),
+    (*b"XSCL", NoFormIds),
+    (*b"XRGD", NoFormIds),
+    (*b"XRGB", NoFormIds),
+    (*b"XLCM", NoFormIds),
+    (*b"XPRD", NoFormIds),
+    (*b"XPPA", NoFormIds),
+    (*b"XIS2", NoFormIds),
+    (*b"XRNK", NoFormIds),
+    (*b"XHTW", NoFormIds),
+    (*b"XFVC", NoFormIds),
+    (*b"XCNT", NoFormIds),
+    (*b"XCHG", NoFormIds),
+    (*b"XHLP", NoFormIds),
+    (*b"XALP", NoFormIds),
+    (*b"XLIG", NoFormIds),
+    (*b"XRDS", NoFormIds),
+    (*b"XPRM", NoFormIds),
+    (*b"XMBO", NoFormIds),
+    (*b"XMBP", NoFormIds),
+    (*b"XPTL", NoFormIds),
+    (*b"XRMR", NoFormIds),
+    (*b"XOCP", NoFormIds),
+    (*b"XORD", NoFormIds),
+    (*b"XLOD", NoFormIds),
+    (*b"XMRK", NoFormIds),
+    (*b"XACT", NoFormIds),
+    (*b"XAPD", NoFormIds),
+    (*b"XTRI", NoFormIds),
+    (*b"XCZA", NoFormIds),
+    (*b"XCVL", NoFormIds),
+    (*b"XWCN", NoFormIds),
+    (*b"XWCS", NoFormIds),
+    (*b"XWCU", NoFormIds),
+    (*b"XCLC", NoFormIds),
+    (*b"XCLL", NoFormIds),
+    (*b"XCLW", NoFormIds),
+    (*b"XWEM", NoFormIds),
+    (*b"TVDT", NoFormIds),
+    (*b"MHDT", NoFormIds),
+    (*b"VNML", NoFormIds),
+    (*b"VHGT", NoFormIds),
+    (*b"VCLR", NoFormIds),
+    (*b"VTXT", NoFormIds),
+];
+
+/// Subrecords whose meaning depends on the record they appear in
+const RECORD_FIELDS: &[([u8; 4], [u8; 4], FormIdLayout)] = &[
+    (*b"ACHR", *b"NAME", Fields(&[0])),
+    (*b"REFR", *b"NAME", Fields(&[0])),
+    (*b"PGRE", *b"NAME", Fields(&[0])),
+    (*b"PHZD", *b"NAME", Fields(&[0])),
+    (*b"PMIS", *b"NAME", Fields(&[0])),
+    (*b"PARW", *b"NAME", Fields(&[0])),
+    (*b"PBAR", *b"NAME", Fields(&[0])),
+    (*b"PBEA", *b"NAME", Fields(&[0])),
+    (*b"PCON", *b"NAME", Fields(&[0])),
+    (*b"PFLA", *b"NAME", Fields(&[0])),
+    (*b"ACTI", *b"SNAM", Fields(&[0])),
+    (*b"ACTI", *b"VNAM", Fields(&[0])),
+    (*b"ACTI", *b"WNAM", Fields(&[0])),
+    (*b"ACTI", *b"KNAM", Fields(&[0])),
+    (*b"ALCH", *b"ENIT", Fields(&[8, 16])),
+    (*b"AMMO", *b"DATA", Fields(&[0])),
+    (*b"ARMA", *b"RNAM", Fields(&[0])),
+    (*b"ARMA", *b"MODL", Fields(&[0])),
+    (*b"ARMA", *b"SNDD", Fields(&[0])),
+    (*b"ARMA", *b"NAM0", Fields(&[0])),
+    (*b"ARMA", *b"NAM1", Fields(&[0])),
+    (*b"ARMA", *b"NAM2", Fields(&[0])),
+    (*b"ARMA", *b"NAM3", Fields(&[0])),
+    (*b"ARMO", *b"MODL", Fields(&[0])),
+    (*b"ARMO", *b"RNAM", Fields(&[0])),
+    (*b"ARMO", *b"TNAM", Fields(&[0])),
+    (*b"BOOK", *b"INAM", Fields(&[0])),
+    (*b"CELL", *b"XCLR", Array),
+    (*b"COBJ", *b"CNAM", Fields(&[0])),
+    (*b"COBJ", *b"BNAM", Fields(&[0])),
+    (*b"CONT", *b"SNAM", Fields(&[0])),
+    (*b"CONT", *b"QNAM", Fields(&[0])),
+    (*b"DIAL", *b"QNAM", Fields(&[0])),
+    (*b"DIAL", *b"BNAM", Fields(&[0])),
+    (*b"DLBR", *b"QNAM", Fields(&[0])),
+    (*b"DLBR", *b"SNAM", Fields(&[0])),
+    (*b"DOOR", *b"SNAM", Fields(&[0])),
+    (*b"DOOR", *b"ANAM", Fields(&[0])),
+    (*b"DOOR", *b"BNAM", Fields(&[0])),
+    (*b"EQUP", *b"PNAM", Array),
+    (*b"FACT", *b"XNAM", Fields(&[0])),
+    (*b"FACT", *b"JAIL", Fields(&[0])),
+    (*b"FACT", *b"WAIT", Fields(&[0])),
+    (*b"FACT", *b"STOL", Fields(&[0])),
+    (*b"FACT", *b"PLCN", Fields(&[0])),
+    (*b"FACT", *b"CRGR", Fields(&[0])),
+    (*b"FACT", *b"JOUT", Fields(&[0])),
+    (*b"FACT", *b"VEND", Fields(&[0])),
+    (*b"FACT", *b"VENC", Fields(&[0])),
+    (*b"FLOR", *b"SNAM", Fields(&[0])),
+    (*b"FLST", *b"LNAM", Fields(&[0])),
+    (*b"HDPT", *b"HNAM", Fields(&[0])),
+    (*b"HDPT", *b"TNAM", Fields(&[0])),
+    (*b"HDPT", *b"CNAM", Fields(&[0])),
+    (*b"HDPT", *b"RNAM", Fields(&[0])),
+    (*b"IDLE", *b"ANAM", Fields(&[0, 4])),
+    (*b"INFO", *b"PNAM", Fields(&[0])),
+    (*b"INFO", *b"TCLT", Fields(&[0])),
+    (*b"INFO", *b"DNAM", Fields(&[0])),
+    (*b"INFO", *b"ANAM", Fields(&[0])),
+    (*b"LCTN", *b"PNAM", Fields(&[0])),
+    (*b"LCTN", *b"MNAM", Fields(&[0])),
+    (*b"LIGH", *b"SNAM", Fields(&[0])),
+    (*b"MUSC", *b"TNAM", Array),
+    (*b"NPC_", *b"RNAM", Fields(&[0])),
+    (*b"NPC_", *b"CNAM", Fields(&[0])),
+    (
+        *b"NPC_",
+        *b"SNAM",
+        Entries {
+            start: 0,
+            size: 8,
+            offsets: &[0],
+        },
+    ),
+    (*b"NPC_", *b"INAM", Fields(&[0])),
+    (*b"NPC_", *b"VTCK", Fields(&[0])),
+    (*b"NPC_", *b"TPLT", Fields(&[0])),
+    (*b"NPC_", *b"WNAM", Fields(&[0])),
+    (*b"NPC_", *b"ANAM", Fields(&[0])),
+    (*b"NPC_", *b"ATKR", Fields(&[0])),
+    (
+        *b"NPC_",
+        *b"PRKR",
+        Entries {
+            start: 0,
+            size: 8,
+            offsets: &[0],
+        },
+    ),
+    (*b"NPC_", *b"DOFT", Fields(&[0])),
+    (*b"NPC_", *b"SOFT", Fields(&[0])),
+    (*b"NPC_", *b"DPLT", Fields(&[0])),
+    (*b"NPC_", *b"CRIF", Fields(&[0])),
+    (*b"NPC_", *b"FTST", Fields(&[0])),
+    (*b"NPC_", *b"PNAM", Fields(&[0])),
+    (*b"NPC_", *b"HCLF", Fields(&[0])),
+    (*b"NPC_", *b"GNAM", Fields(&[0])),
+    (*b"NPC_", *b"PKID", Fields(&[0])),
+    (*b"OTFT", *b"INAM", Array),
+    (*b"QUST", *b"ALFR", Fields(&[0])),
+    (*b"QUST", *b"ALUA", Fields(&[0])),
+    (*b"QUST", *b"ALCO", Fields(&[0])),
+    (*b"RELA", *b"DATA", Fields(&[0, 4, 12])),
+    (
+        *b"SHOU",
+        *b"SNAM",
+        Entries {
+            start: 0,
+            size: 12,
+            offsets: &[0, 4],
+        },
+    ),
+    (*b"SNDR", *b"GNAM", Fields(&[0])),
+    (*b"SNDR", *b"SNAM", Fields(&[0])),
+    (*b"SNDR", *b"ONAM", Fields(&[0])),
+    (*b"SOUN", *b"SDSC", Fields(&[0])),
+    (*b"TREE", *b"SNAM", Fields(&[0])),
+    (*b"WEAP", *b"INAM", Fields(&[0])),
+    (*b"WEAP", *b"WNAM", Fields(&[0])),
+    (*b"WEAP", *b"SNAM", Fields(&[0])),
+    (*b"WEAP", *b"XNAM", Fields(&[0])),
+    (*b"WEAP", *b"NAM7", Fields(&[0])),
+    (*b"WEAP", *b"NAM8", Fields(&[0])),
+    (*b"WEAP", *b"NAM9", Fields(&[0])),
+    (*b"WEAP", *b"TNAM", Fields(&[0])),
+    (*b"WEAP", *b"UNAM", Fields(&[0])),
+    (*b"WEAP", *b"CNAM", Fields(&[0])),
+    (*b"WRLD", *b"WNAM", Fields(&[0])),
+    (*b"WRLD", *b"CNAM", Fields(&[0])),
+    (*b"WRLD", *b"NAM2", Fields(&[0])),
+    (*b"WRLD", *b"NAM3", Fields(&[0])),
+    (*b"WRLD", *b"INAM", Fields(&[0])),
+    (*b"WRLD", *b"ZNAM", Fields(&[0])),
+    (*b"AACT", *b"CNAM", NoFormIds),
+    (*b"ACHR", *b"DATA", NoFormIds),
+    (*b"ACHR", *b"INAM", Fields(&[0])),
+    (*b"ACHR", *b"PDTO", Typed(&[0], 4)),
+    (*b"ACTI", *b"PNAM", NoFormIds),
+    (*b"ACTI", *b"RNAM", NoFormIds),
+    (*b"ACTI", *b"FNAM", NoFormIds),
+    (*b"ALCH", *b"DATA", NoFormIds),
+    (*b"ARMA", *b"DNAM", NoFormIds),
+    (*b"ARMO", *b"DATA", NoFormIds),
+    (*b"ARMO", *b"DNAM", NoFormIds),
+    (*b"ARMO", *b"BMCT", NoFormIds),
+    (*b"ARTO", *b"DNAM", NoFormIds),
+    (*b"BOOK", *b"DATA", Flagged(0x04, 4)),
+    (*b"BOOK", *b"CNAM", NoFormIds),
+    (*b"CELL", *b"DATA", NoFormIds),
+    (*b"CELL", *b"LNAM", NoFormIds),
+    (*b"CELL", *b"XNAM", NoFormIds),
+    (*b"CLAS", *b"DATA", NoFormIds),
+    (*b"COBJ", *b"NAM1", NoFormIds),
+    (*b"CONT", *b"DATA", NoFormIds),
+    (*b"DIAL", *b"PNAM", NoFormIds),
+    (*b"DIAL", *b"DATA", NoFormIds),
+    (*b"DIAL", *b"SNAM", NoFormIds),
+    (*b"DIAL", *b"TIFC", NoFormIds),
+    (*b"DLBR", *b"TNAM", NoFormIds),
+    (*b"DLBR", *b"DNAM", NoFormIds),
+    (*b"DLVW", *b"QNAM", Fields(&[0])),
+    (*b"DLVW", *b"BNAM", Fields(&[0])),
+    (*b"DLVW", *b"TNAM", NoFormIds),
+    (*b"DLVW", *b"ENAM", NoFormIds),
+    (*b"DLVW", *b"DNAM", NoFormIds),
+    (*b"DOOR", *b"FNAM", NoFormIds),
+    (*b"ECZN", *b"DATA", Fields(&[0, 4])),
+    (*b"ENCH", *b"ENIT", Fields(&[28, 32])),
+    (*b"EQUP", *b"DATA", NoFormIds),
+    (*b"EYES", *b"DATA", NoFormIds),
+    (*b"FACT", *b"DATA", NoFormIds),
+    (*b"FACT", *b"CRVA", NoFormIds),
+    (*b"FACT", *b"RNAM", NoFormIds),
+    (*b"FACT", *b"MNAM", NoFormIds),
+    (*b"FACT", *b"FNAM", NoFormIds),
+    (*b"FACT", *b"VENV", NoFormIds),
+    (*b"FACT", *b"PLVD", Typed(LOCATION_FORM_TYPES, 4)),
+    (*b"FLOR", *b"PNAM", NoFormIds),
+    (*b"FLOR", *b"RNAM", NoFormIds),
+    (*b"FLOR", *b"FNAM", NoFormIds),
+    (*b"FLOR", *b"PFPC", NoFormIds),
+    (*b"FURN", *b"PNAM", NoFormIds),
+    (*b"FURN", *b"FNAM", NoFormIds),
+    (*b"FURN", *b"MNAM", NoFormIds),
+    (*b"FURN", *b"WBDT", NoFormIds),
+    (*b"FURN", *b"ENAM", NoFormIds),
+    (*b"FURN", *b"NAM0", NoFormIds),
+    (*b"FURN", *b"FNPR", NoFormIds),
+    (*b"FURN", *b"XMRK", NoFormIds),
+    (*b"FURN", *b"KNAM", Fields(&[0])),
+    (*b"FURN", *b"NAM1", Fields(&[0])),
+    (*b"FURN", *b"FNMK", Fields(&[0])),
+    (*b"GLOB", *b"FNAM", NoFormIds),
+    (*b"GLOB", *b"FLTV", NoFormIds),
+    (*b"GMST", *b"DATA", NoFormIds),
+    (*b"HDPT", *b"DATA", NoFormIds),
+    (*b"HDPT", *b"PNAM", NoFormIds),
+    (*b"HDPT", *b"NAM0", NoFormIds),
+    (*b"HDPT", *b"NAM1", NoFormIds),
+    (*b"IDLE", *b"DNAM", NoFormIds),
+    (*b"IDLE", *b"ENAM", NoFormIds),
+    (*b"IDLE", *b"DATA", NoFormIds),
+    (*b"INFO", *b"DATA", NoFormIds),
+    (*b"INFO", *b"ENAM", NoFormIds),
+    (*b"INFO", *b"CNAM", NoFormIds),
+    (*b"INFO", *b"TRDT", Fields(&[16])),
+    (*b"INFO", *b"NAM1", NoFormIds),
+    (*b"INFO", *b"NAM2", NoFormIds),
+    (*b"INFO", *b"NAM3", NoFormIds),
+    (*b"INFO", *b"RNAM", NoFormIds),
+    (*b"INFO", *b"SNAM", Fields(&[0])),
+    (*b"INFO", *b"LNAM", Fields(&[0])),
+    (*b"INFO", *b"TWAT", Fields(&[0])),
+    (*b"INFO", *b"ONAM", Fields(&[0])),
+    (*b"INFO", *b"TPIC", Fields(&[0])),
+    (*b"INGR", *b"DATA", NoFormIds),
+    (*b"INGR", *b"ENIT", NoFormIds),
+    (*b"KEYM", *b"DATA", NoFormIds),
+    (*b"KYWD", *b"CNAM", NoFormIds),
+    (*b"LAND", *b"DATA", NoFormIds),
+    (*b"LAND", *b"BTXT", Fields(&[0])),
+    (*b"LAND", *b"ATXT", Fields(&[0])),
+    (*b"LAND", *b"VTEX", Array),
+    (*b"LCRT", *b"CNAM", NoFormIds),
+    (
+        *b"LCTN",
+        *b"ACPR",
+        Entries {
+            start: 0,
+            size: 12,
+            offsets: &[0, 4],
+        },
+    ),
+    (
+        *b"LCTN",
+        *b"LCPR",
+        Entries {
+            start: 0,
+            size: 12,
+            offsets: &[0, 4],
+        },
+    ),
+    (*b"LCTN", *b"RCPR", Array),
+    (
+        *b"LCTN",
+        *b"ACUN",
+        Entries {
+            start: 0,
+            size: 12,
+            offsets: &[0, 4, 8],
+        },
+    ),
+    (
+        *b"LCTN",
+        *b"LCUN",
+        Entries {
+            start: 0,
+            size: 12,
+            offsets: &[0, 4, 8],
+        },
+    ),
+    (*b"LCTN", *b"RCUN", Array),
+    (
+        *b"LCTN",
+        *b"ACSR",
+        Entries {
+            start: 0,
+            size: 16,
+            offsets: &[0, 4, 8],
+        },
+    ),
+    (
+        *b"LCTN",
+        *b"LCSR",
+        Entries {
+            start: 0,
+            size: 16,
+            offsets: &[0, 4, 8],
+        },
+    ),
+    (*b"LCTN", *b"RCSR", Array),
+    (*b"LCTN", *b"ACEC", Fields(&[0])),
+    (*b"LCTN", *b"LCEC", Fields(&[0])),
+    (*b"LCTN", *b"RCEC", Fields(&[0])),
+    (
+        *b"LCTN",
+        *b"ACID",
+        Entries {
+            start: 0,
+            size: 8,
+            offsets: &[0, 4],
+        },
+    ),
+    (
+        *b"LCTN",
+        *b"LCID",
+        Entries {
+            start: 0,
+            size: 8,
+            offsets: &[0, 4],
+        },
+    ),
+    (
+        *b"LCTN",
+        *b"ACEP",
+        Entries {
+            start: 0,
+            size: 12,
+            offsets: &[0, 4],
+        },
+    ),
+    (
+        *b"LCTN",
+        *b"LCEP",
+        Entries {
+            start: 0,
+            size: 12,
+            offsets: &[0, 4],
+        },
+    ),
+    (*b"LCTN", *b"NAM0", Fields(&[0])),
+    (*b"LCTN", *b"NAM1", Fields(&[0])),
+    (*b"LCTN", *b"FNAM", Fields(&[0])),
+    (*b"LCTN", *b"RNAM", NoFormIds),
+    (*b"LCTN", *b"CNAM", NoFormIds),
+    (*b"LIGH", *b"DATA", NoFormIds),
+    (*b"LIGH", *b"FNAM", NoFormIds),
+    (*b"MESG", *b"DNAM", NoFormIds),
+    (*b"MESG", *b"TNAM", NoFormIds),
+    (*b"MESG", *b"ITXT", NoFormIds),
+    (*b"MESG", *b"INAM", Fields(&[0])),
+    (*b"MESG", *b"QNAM", Fields(&[0])),
+    (
+        *b"MGEF",
+        *b"DATA",
+        Fields(&[8, 24, 32, 36, 72, 76, 92, 96, 100, 108, 116, 120, 124, 128, 132, 136]),
+    ),
+    (
+        *b"MGEF",
+        *b"SNDD",
+        Entries {
+            start: 0,
+            size: 8,
+            offsets: &[4],
+        },
+    ),
+    (*b"MGEF", *b"ESCE", Array),
+    (*b"MGEF", *b"DNAM", NoFormIds),
+    (*b"MISC", *b"DATA", NoFormIds),
+    (*b"MSTT", *b"DATA", NoFormIds),
+    (*b"MSTT", *b"SNAM", Fields(&[0])),
+    (*b"NAVM", *b"NVNM", Navmesh),
+    (*b"NPC_", *b"DATA", NoFormIds),
+    (*b"NPC_", *b"DNAM", NoFormIds),
+    (*b"NPC_", *b"NAM5", NoFormIds),
+    (*b"NPC_", *b"NAM6", NoFormIds),
+    (*b"NPC_", *b"NAM7", NoFormIds),
+    (*b"NPC_", *b"NAM8", NoFormIds),
+    (*b"NPC_", *b"NAM9", NoFormIds),
+    (*b"NPC_", *b"NAMA", NoFormIds),
+    (*b"NPC_", *b"QNAM", NoFormIds),
+    (*b"NPC_", *b"TINI", NoFormIds),
+    (*b"NPC_", *b"TINC", NoFormIds),
+    (*b"NPC_", *b"TINV", NoFormIds),
+    (*b"NPC_", *b"TIAS", NoFormIds),
+    (*b"NPC_", *b"CSDI", Fields(&[0])),
+    (*b"NPC_", *b"CSCR", Fields(&[0])),
+    (*b"NPC_", *b"SPOR", Fields(&[0])),
+    (*b"NPC_", *b"OCOR", Fields(&[0])),
+    (*b"NPC_", *b"GWOR", Fields(&[0])),
+    (*b"NPC_", *b"ECOR", Fields(&[0])),
+    (*b"PACK", *b"PKDT", NoFormIds),
+    (*b"PACK", *b"PSDT", NoFormIds),
+    (*b"PACK", *b"PKCU", Fields(&[4])),
+    (*b"PACK", *b"PLDT", Typed(LOCATION_FORM_TYPES, 4)),
+    (*b"PACK", *b"PTDA", Typed(TARGET_FORM_TYPES, 4)),
+    (*b"PACK", *b"PDTO", Typed(&[0], 4)),
+    (*b"QUST", *b"DNAM", NoFormIds),
+    (*b"QUST", *b"ENAM", NoFormIds),
+    (*b"QUST", *b"FLTR", NoFormIds),
+    (*b"QUST", *b"NEXT", NoFormIds),
+    (*b"QUST", *b"INDX", NoFormIds),
+    (*b"QUST", *b"QSDT", NoFormIds),
+    (*b"QUST", *b"CNAM", NoFormIds),
+    (*b"QUST", *b"QOBJ", NoFormIds),
+    (*b"QUST", *b"FNAM", NoFormIds),
+    (*b"QUST", *b"NNAM", NoFormIds),
+    (*b"QUST", *b"QSTA", NoFormIds),
+    (*b"QUST", *b"ANAM", NoFormIds),
+    (*b"QUST", *b"ALST", NoFormIds),
+    (*b"QUST", *b"ALLS", NoFormIds),
+    (*b"QUST", *b"ALID", NoFormIds),
+    (*b"QUST", *b"ALFI", NoFormIds),
+    (*b"QUST", *b"ALFA", NoFormIds),
+    (*b"QUST", *b"ALEA", NoFormIds),
+    (*b"QUST", *b"ALCA", NoFormIds),
+    (*b"QUST", *b"ALCL", NoFormIds),
+    (*b"QUST", *b"ALNA", NoFormIds),
+    (*b"QUST", *b"ALNT", NoFormIds),
+    (*b"QUST", *b"ALFE", NoFormIds),
+    (*b"QUST", *b"ALFD", NoFormIds),
+    (*b"QUST", *b"ALED", NoFormIds),
+    (*b"QUST", *b"NAM0", Fields(&[0])),
+    (*b"QUST", *b"ALFL", Fields(&[0])),
+    (*b"QUST", *b"ALRT", Fields(&[0])),
+    (*b"QUST", *b"ALEQ", Fields(&[0])),
+    (*b"QUST", *b"ALDN", Fields(&[0])),
+    (*b"QUST", *b"ALSP", Fields(&[0])),
+    (*b"QUST", *b"ALFC", Fields(&[0])),
+    (*b"QUST", *b"ALPC", Fields(&[0])),
+    (*b"QUST", *b"KNAM", Fields(&[0])),
+    (*b"QUST", *b"VTCK", Fields(&[0])),
+    (*b"QUST", *b"SPOR", Fields(&[0])),
+    (*b"QUST", *b"OCOR", Fields(&[0])),
+    (*b"QUST", *b"GWOR", Fields(&[0])),
+    (*b"QUST", *b"ECOR", Fields(&[0])),
+    (*b"REFR", *b"DATA", NoFormIds),
+    (*b"REFR", *b"FNAM", NoFormIds),
+    (*b"REFR", *b"TNAM", NoFormIds),
+    (*b"REFR", *b"XTNM", Fields(&[0])),
+    (*b"REFR", *b"LNAM", Fields(&[0])),
+    (*b"REFR", *b"INAM", Fields(&[0])),
+    (*b"REFR", *b"PDTO", Typed(&[0], 4)),
+    (*b"SCRL", *b"DATA", NoFormIds),
+    (*b"SCRL", *b"SPIT", Fields(&[32])),
+    (*b"SLGM", *b"DATA", NoFormIds),
+    (*b"SLGM", *b"SOUL", NoFormIds),
+    (*b"SLGM", *b"SLCP", NoFormIds),
+    (*b"SLGM", *b"NAM0", Fields(&[0])),
+    (*b"SNDR", *b"CNAM", NoFormIds),
+    (*b"SNDR", *b"ANAM", NoFormIds),
+    (*b"SNDR", *b"LNAM", NoFormIds),
+    (*b"SNDR", *b"BNAM", NoFormIds),
+    (*b"SNDR", *b"FNAM", NoFormIds),
+    (*b"SOUN", *b"FNAM", NoFormIds),
+    (*b"SOUN", *b"SNDD", NoFormIds),
+    (*b"SPEL", *b"SPIT", Fields(&[32])),
+    (*b"STAT", *b"DNAM", Fields(&[4])),
+    (*b"STAT", *b"MNAM", NoFormIds),
+    (*b"TREE", *b"PFPC", NoFormIds),
+    (*b"TREE", *b"CNAM", NoFormIds),
+    (*b"TXST", *b"DNAM", NoFormIds),
+    (*b"WEAP", *b"NNAM", NoFormIds),
+    (*b"WEAP", *b"DATA", NoFormIds),
+    (*b"WEAP", *b"DNAM", NoFormIds),
+    (*b"WEAP", *b"VNAM", NoFormIds),
+    (*b"WEAP", *b"CRDT", BySize(&[(16, &[12]), (24, &[16])])),
+    (
+        *b"WRLD",
+        *b"RNAM",
+        Entries {
+            start: 8,
+            size: 8,
+            offsets: &[0],
+        },
+    ),
+    (*b"WRLD", *b"NAM4", Fields(&[0])),
+    (*b"WRLD", *b"DATA", NoFormIds),
+    (*b"WRLD", *b"NAM0", NoFormIds),
+    (*b"WRLD", *b"NAM9", NoFormIds),
+    (*b"WRLD", *b"DNAM", NoFormIds),
+    (*b"WRLD", *b"MNAM", NoFormIds),
+    (*b"WRLD", *b"PNAM", NoFormIds),
+    (*b"WRLD", *b"ONAM", NoFormIds),
+    (*b"WRLD", *b"NAMA", NoFormIds),
+    (*b"WRLD", *b"TNAM", NoFormIds),
+    (*b"WRLD", *b"UNAM", NoFormIds),
+    (*b"WRLD", *b"OFST", NoFormIds),
+    (*b"WRLD", *b"CLSZ", NoFormIds),
+    (*b"WRLD", *b"WCTR", NoFormIds),
+    (*b"PARW", *b"DATA", NoFormIds),
+    (*b"PBAR", *b"DATA", NoFormIds),
+    (*b"PBEA", *b"DATA", NoFormIds),
+    (*b"PCON", *b"DATA", NoFormIds),
+    (*b"PFLA", *b"DATA", NoFormIds),
+    (*b"PGRE", *b"DATA", NoFormIds),
+    (*b"PHZD", *b"DATA", NoFormIds),
+    (*b"PMIS", *b"DATA", NoFormIds),
+];
+
+pub fn form_id_layout(record: &TypeCode, subrecord: &TypeCode) -> Option<FormIdLayout> {
+    RECORD_FIELDS
+        .iter()
+        .find(|(record_code, subrecord_code, _)| record_code == &**record && subrecord_code == &**subrecord)
+        .map(|(_, _, layout)| *layout)
+        .or_else(|| {
+            COMMON_FIELDS
+                .iter()
+                .find(|(subrecord_code, _)| subrecord_code == &**subrecord)
+                .map(|(_, layout)| *layout)
+        })
+}
+
+/// Byte offsets of the FormIDs held in a subrecord of the given record type, or `None` if where they are isn't known
+pub fn known_form_id_offsets(record: &TypeCode, subrecord: &Subrecord) -> Option<Vec<usize>> {
+    let data = subrecord.data;
+    let len = data.len();
+
+    let offsets = match form_id_layout(record, &subrecord.code)? {
+        NoFormIds => vec![],
+        Fields(offsets) => offsets.to_vec(),
+        Array => (0..len / 4).map(|i| i * 4).collect(),
+        Entries { start, size, offsets } => (0..len.saturating_sub(start) / size)
+            .flat_map(|entry| offsets.iter().map(move |offset| start + entry * size + offset))
+            .collect(),
+        Typed(types, offset) => match data.get(..4) {
+            Some(bytes) if types.contains(&read_u32(bytes, 0)) => vec![offset],
+            _ => vec![],
+        },
+        Flagged(flag, offset) => match data.first() {
+            Some(flags) if flags & flag != 0 => vec![offset],
+            _ => vec![],
+        },
+        BySize(sizes) => sizes.iter().find(|(size, _)| *size == len)?.1.to_vec(),
+        AlternateTextures => alternate_texture_offsets(data)?,
+        Condition => condition::form_id_offsets(data)?,
+        Scripts => vmad::form_id_offsets(data, record)?,
+        Navmesh => navmesh::form_id_offsets(data)?,
+    };
+
+    Some(offsets.into_iter().filter(|offset| offset + 4 <= len).collect())
+}
+
+/// Byte offsets of the FormIDs held in a subrecord of the given record type, treating subrecords of unknown layout
+/// as holding none
+pub fn form_id_offsets(record: &TypeCode, subrecord: &Subrecord) -> Vec<usize> {
+    known_form_id_offsets(record, subrecord).unwrap_or_default()
+}
+
+/// Types of the record's subrecords whose FormIDs can't be located, each listed once
+pub fn unmapped_subrecords(record: &Record) -> Vec<TypeCode> {
+    let mut unmapped: Vec<TypeCode> = vec![];
+
+    for subrecord in record.subrecords() {
+        if known_form_id_offsets(&record.header.code, &subrecord).is_none() && !unmapped.contains(&subrecord.code) {
+            unmapped.push(subrecord.code);
+        }
+    }
+
+    unmapped
+}
+
+/// Fail with `Error::UnknownFormIdLayout`, listing the first few offenders, if any of the records holds subrecords
+/// whose FormIDs can't be located. Run before rewriting FormIDs, so nothing is left half-rewritten
+pub fn check_mapped<'a, I>(records: I) -> Result<(), crate::Error>
+where
+    I: IntoIterator<Item = &'a Record>,
+{
+    const LISTED: usize = 10;
+
+    let mut unmapped = vec![];
+    let mut count = 0;
+
+    for record in records {
+        let codes = unmapped_subrecords(record);
+
+        if codes.is_empty() {
+            continue;
+        }
+
+        count += 1;
+
+        if unmapped.len() < LISTED {
+            let codes = codes.iter().map(ToString::to_string).collect::<Vec<_>>();
+            unmapped.push(format!(
+                "{} {} ({})",
+                record.header.code,
+                record.header.id,
+                codes.join(", ")
+            ));
+        }
+    }
+
+    match count {
+        0 => Ok(()),
+        _ if count > LISTED => Err(crate::Error::UnknownFormIdLayout(format!(
+            "{} and {} more records",
+            unmapped.join(", "),
+            count - LISTED
+        ))),
+        _ => Err(crate::Error::UnknownFormIdLayout(unmapped.join(", "))),
+    }
+}
+
+/// FormIDs referenced by a record's subrecords, excluding the record's own FormID and null references. Subrecords of
+/// unknown layout are skipped
+pub fn referenced_form_ids(record: &Record) -> Vec<FormId> {
+    record
+        .subrecords()
+        .iter()
+        .flat_map(|subrecord| {
+            form_id_offsets(&record.header.code, subrecord)
+                .into_iter()
+                .map(move |offset| read_form_id(subrecord.data, offset))
+        })
+        .filter(|id| **id != 0)
+        .collect()
+}
+
+/// Rewrite the record's own FormID and every FormID reference it holds. Null references are left alone. Fails,
+/// leaving the record untouched, if it holds subrecords whose FormIDs can't be located
+pub fn remap_form_ids<F>(record: &mut Record, mut remap: F) -> Result<(), crate::Error>
+where
+    F: FnMut(FormId) -> FormId,
+{
+    check_mapped(std::iter::once(&*record))?;
+
+    record.header.id = remap(record.header.id);
+
+    let code = record.header.code.clone();
+
+    record.edit_subrecords(|subrecords| {
+        for (subrecord_code, data) in subrecords.iter_mut() {
+            let subrecord = Subrecord {
+                code: subrecord_code.clone(),
+                data,
+            };
+
+            for offset in form_id_offsets(&code, &subrecord) {
+                let id = read_form_id(data, offset);
+
+                if *id != 0 {
+                    data[offset..offset + 4].copy_from_slice(&remap(id).to_le_bytes());
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Offsets of the TXST FormIDs in alternate texture data: a count, then for each texture a u32-sized 3D name, the
+/// TXST and a 3D index
+fn alternate_texture_offsets(data: &[u8]) -> Option<Vec<usize>> {
+    let count = read_u32(data.get(..4)?, 0);
+    let mut offsets = vec![];
+    let mut position = 4;
+
+    for _ in 0..count {
+        let name_length = read_u32(data.get(position..position + 4)?, 0) as usize;
+        position += 4 + name_length;
+        offsets.push(position);
+        position += 8;
+    }
+
+    if position == data.len() {
+        Some(offsets)
+    } else {
+        None
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+fn read_form_id(data: &[u8], offset: usize) -> FormId {
+    read_u32(data, offset).into()
+}
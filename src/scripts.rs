@@ -0,0 +1,191 @@
+use std::{collections::HashMap, fs, path::Path, path::PathBuf};
+
+use crate::{
+    load_order::{GlobalFormId, LoadOrder, LoadedPlugin},
+    pex::Pex,
+};
+
+pub use crate::parsers::records::vmad::{
+    AliasScripts, FlaggedFragments, Fragment, Fragments, ObjectRef, PerkFragment, Property, PropertyValue,
+    QuestFragment, ScenePhaseFragment, Script, Vmad,
+};
+
+/// An object property pointing at a form that isn't in the load order
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnresolvedProperty {
+    pub record: GlobalFormId,
+    /// The quest alias the script is attached to, for quest alias scripts
+    pub alias: Option<i16>,
+    pub script: String,
+    pub property: String,
+    pub target: GlobalFormId,
+}
+
+/// Check that every object property of the plugin's scripts points at a form defined somewhere in the load order.
+/// Records whose VMAD fails to decode are reported as errors rather than skipped
+pub fn unresolved_properties(
+    plugin: &LoadedPlugin,
+    load_order: &LoadOrder,
+) -> Result<Vec<UnresolvedProperty>, crate::Error> {
+    let mut unresolved = vec![];
+
+    let mut records = plugin.plugin.records();
+    records.sort_by_key(|record| record.header.id);
+
+    for record in records {
+        let vmad = match Vmad::decode(record)? {
+            Some(vmad) => vmad,
+            None => continue,
+        };
+
+        for (alias, script) in vmad.all_scripts() {
+            for property in &script.properties {
+                for object in property.value.objects() {
+                    if *object.form_id == 0 {
+                        continue;
+                    }
+
+                    let target = plugin.global_form_id(object.form_id);
+
+                    if load_order.winning_record(&target).is_none() && plugin.record(&target).is_none() {
+                        unresolved.push(UnresolvedProperty {
+                            record: plugin.global_form_id(record.header.id),
+                            alias: alias.map(|alias| alias.object.alias),
+                            script: script.name.clone(),
+                            property: property.name.clone(),
+                            target,
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(unresolved)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptIssue {
+    /// No compiled script with the name the VMAD gives
+    MissingScript,
+    /// The VMAD sets a property that neither the script nor any script it extends declares
+    MissingProperty(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptProblem {
+    pub record: GlobalFormId,
+    /// The quest alias the script is attached to, for quest alias scripts
+    pub alias: Option<i16>,
+    pub script: String,
+    pub issue: ScriptIssue,
+}
+
+/// Compiled scripts in a directory, looked up by case-insensitive name and read on first use
+struct CompiledScripts {
+    paths: HashMap<String, PathBuf>,
+    loaded: HashMap<String, Option<Pex>>,
+}
+
+impl CompiledScripts {
+    fn new(dir: &Path) -> Result<Self, crate::Error> {
+        let mut paths = HashMap::new();
+
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+
+            if let (Some(stem), Some(extension)) = (path.file_stem(), path.extension()) {
+                if extension.eq_ignore_ascii_case("pex") {
+                    paths.insert(stem.to_string_lossy().to_ascii_lowercase(), path.clone());
+                }
+            }
+        }
+
+        Ok(CompiledScripts {
+            paths,
+            loaded: HashMap::new(),
+        })
+    }
+
+    fn get(&mut self, name: &str) -> Result<Option<&Pex>, crate::Error> {
+        let key = name.to_ascii_lowercase();
+
+        if !self.loaded.contains_key(&key) {
+            let pex = match self.paths.get(&key) {
+                Some(path) => Some(Pex::parse(&fs::read(path)?)?),
+                None => None,
+            };
+
+            self.loaded.insert(key.clone(), pex);
+        }
+
+        Ok(self.loaded[&key].as_ref())
+    }
+
+    /// Whether the script or one it extends declares the property. `None` when part of the chain is missing, so
+    /// the answer can't be known
+    fn declares_property(&mut self, script: &str, property: &str) -> Result<Option<bool>, crate::Error> {
+        let mut name = String::from(script);
+
+        // Guard against scripts that extend themselves in a cycle
+        for _ in 0..64 {
+            let object = match self.get(&name)?.and_then(|pex| pex.object(&name)) {
+                Some(object) => object,
+                None => return Ok(None),
+            };
+
+            if object.property(property).is_some() {
+                return Ok(Some(true));
+            }
+
+            if object.parent.is_empty() {
+                return Ok(Some(false));
+            }
+
+            name = object.parent.clone();
+        }
+
+        Ok(None)
+    }
+}
+
+/// Check every script the plugin's VMADs attach, and every property they set, against the compiled scripts in
+/// `scripts_dir`. Properties are looked up through the scripts each one extends
+pub fn check_compiled_scripts(plugin: &LoadedPlugin, scripts_dir: &Path) -> Result<Vec<ScriptProblem>, crate::Error> {
+    let mut compiled = CompiledScripts::new(scripts_dir)?;
+    let mut problems = vec![];
+
+    let mut records = plugin.plugin.records();
+    records.sort_by_key(|record| record.header.id);
+
+    for record in records {
+        let vmad = match Vmad::decode(record)? {
+            Some(vmad) => vmad,
+            None => continue,
+        };
+
+        for (alias, script) in vmad.all_scripts() {
+            let mut report = |issue| {
+                problems.push(ScriptProblem {
+                    record: plugin.global_form_id(record.header.id),
+                    alias: alias.map(|alias| alias.object.alias),
+                    script: script.name.clone(),
+                    issue,
+                })
+            };
+
+            if compiled.get(&script.name)?.is_none() {
+                report(ScriptIssue::MissingScript);
+                continue;
+            }
+
+            for property in &script.properties {
+                if compiled.declares_property(&script.name, &property.name)? == Some(false) {
+                    report(ScriptIssue::MissingProperty(property.name.clone()));
+                }
+            }
+        }
+    }
+
+    Ok(problems)
+}
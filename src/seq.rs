@@ -0,0 +1,50 @@
+//! SEQ files, which list the start-game-enabled quests of a plugin so the engine can start them when the plugin is
+//! added to a game already in progress
+
+use nom::{combinator::all_consuming, multi::many0};
+
+use crate::parsers::{
+    common::{form_id, FormId},
+    plugin::Plugin,
+};
+
+pub use crate::parsers::records::quest::{flags, Quest};
+
+/// The plugin's start-game-enabled quests, both new ones and overrides, in FormID order
+pub fn start_game_quests(plugin: &Plugin) -> Result<Vec<FormId>, crate::Error> {
+    let mut quests = vec![];
+
+    for record in plugin.records() {
+        if &*record.header.code == b"QUST" && Quest::decode(record)?.is_start_game_enabled() {
+            quests.push(record.header.id);
+        }
+    }
+
+    quests.sort();
+
+    Ok(quests)
+}
+
+/// The SEQ file for the plugin: the FormIDs of its start-game-enabled quests as the plugin stores them, or `None`
+/// if it has no such quests and needs no SEQ file
+pub fn generate(plugin: &Plugin) -> Result<Option<Vec<u8>>, crate::Error> {
+    let quests = start_game_quests(plugin)?;
+
+    if quests.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(quests.iter().flat_map(|quest| quest.to_le_bytes()).collect()))
+}
+
+/// The quest FormIDs listed in a SEQ file
+pub fn parse(bytes: &[u8]) -> Result<Vec<FormId>, crate::Error> {
+    if bytes.len() % 4 != 0 {
+        return Err(crate::Error::CorruptOrInvalidFile(format!(
+            "SEQ file of {} bytes is not a list of FormIDs",
+            bytes.len()
+        )));
+    }
+
+    Ok(all_consuming(many0(form_id))(bytes)?.1)
+}
@@ -0,0 +1,310 @@
+use std::collections::HashMap;
+
+use crate::parsers::{
+    common::{FormId, GridCoord, TypeCode},
+    group::{Group, GroupChild, GroupData, GroupType, Label},
+    plugin::Plugin,
+    records::{
+        reference::{is_placed_reference, PlacedReference},
+        Record,
+    },
+};
+
+use nom::{number::complete::le_i32, sequence::pair};
+
+/// Width of an exterior cell in world units
+pub const CELL_SIZE: f32 = 4096.0;
+
+/// Grid coordinates of the exterior cell containing a world position
+pub fn cell_grid(x: f32, y: f32) -> GridCoord {
+    GridCoord::new((x / CELL_SIZE).floor() as i16, (y / CELL_SIZE).floor() as i16)
+}
+
+/// An axis-aligned rectangle in world units
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounds {
+    pub min: [f32; 2],
+    pub max: [f32; 2],
+}
+
+impl Bounds {
+    pub fn new(min: [f32; 2], max: [f32; 2]) -> Self {
+        Bounds {
+            min: [min[0].min(max[0]), min[1].min(max[1])],
+            max: [min[0].max(max[0]), min[1].max(max[1])],
+        }
+    }
+
+    pub fn around(x: f32, y: f32, radius: f32) -> Self {
+        Bounds::new([x - radius, y - radius], [x + radius, y + radius])
+    }
+
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        (self.min[0]..=self.max[0]).contains(&x) && (self.min[1]..=self.max[1]).contains(&y)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExteriorCell {
+    pub id: FormId,
+    pub editor_id: Option<String>,
+    /// From XCLC
+    pub grid: GridCoord,
+    /// The cell's LAND record
+    pub land: Option<FormId>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexedReference {
+    pub reference: PlacedReference,
+    /// The cell whose children hold the reference. Persistent references all sit in the worldspace's
+    /// persistent cell, wherever they are placed
+    pub cell: FormId,
+    pub persistent: bool,
+}
+
+/// Cells and placed references of one worldspace, bucketed by the exterior cell grid. References are bucketed
+/// by their position rather than the cell holding them
+#[derive(Debug, Default)]
+pub struct WorldspaceIndex {
+    pub world: FormId,
+    pub editor_id: Option<String>,
+    cells: HashMap<GridCoord, ExteriorCell>,
+    cell_grids: HashMap<FormId, GridCoord>,
+    references: Vec<IndexedReference>,
+    buckets: HashMap<GridCoord, Vec<usize>>,
+    extent: Option<(GridCoord, GridCoord)>,
+}
+
+impl WorldspaceIndex {
+    fn new(world: &Record) -> Self {
+        WorldspaceIndex {
+            world: world.header.id,
+            editor_id: world.header.editor_id.clone(),
+            ..Default::default()
+        }
+    }
+
+    fn add_cell(&mut self, cell: ExteriorCell) {
+        self.extend(cell.grid);
+        self.cell_grids.insert(cell.id, cell.grid);
+        self.cells.insert(cell.grid, cell);
+    }
+
+    fn add_land(&mut self, cell: FormId, land: FormId) {
+        let cells = &mut self.cells;
+
+        if let Some(cell) = self.cell_grids.get(&cell).and_then(|grid| cells.get_mut(grid)) {
+            cell.land = Some(land);
+        }
+    }
+
+    fn add_reference(&mut self, reference: IndexedReference) {
+        let [x, y, _] = reference.reference.placement.position;
+        let grid = cell_grid(x, y);
+
+        self.extend(grid);
+        self.buckets.entry(grid).or_default().push(self.references.len());
+        self.references.push(reference);
+    }
+
+    fn extend(&mut self, grid: GridCoord) {
+        self.extent = Some(match self.extent {
+            Some((min, max)) => (
+                GridCoord::new(min.x.min(grid.x), min.y.min(grid.y)),
+                GridCoord::new(max.x.max(grid.x), max.y.max(grid.y)),
+            ),
+            None => (grid, grid),
+        });
+    }
+
+    /// Grid squares overlapping `bounds`, clipped to those that hold anything
+    fn grid_range(&self, bounds: &Bounds) -> impl Iterator<Item = GridCoord> {
+        let (min, max) = self.extent.unwrap_or((GridCoord::new(0, 0), GridCoord::new(-1, -1)));
+        let low = cell_grid(bounds.min[0], bounds.min[1]);
+        let high = cell_grid(bounds.max[0], bounds.max[1]);
+
+        let xs = low.x.max(min.x)..=high.x.min(max.x);
+        let ys = low.y.max(min.y)..=high.y.min(max.y);
+
+        xs.flat_map(move |x| ys.clone().map(move |y| GridCoord::new(x, y)))
+    }
+
+    pub fn cell(&self, grid: GridCoord) -> Option<&ExteriorCell> {
+        self.cells.get(&grid)
+    }
+
+    /// The exterior cell containing a world position
+    pub fn cell_at(&self, x: f32, y: f32) -> Option<&ExteriorCell> {
+        self.cells.get(&cell_grid(x, y))
+    }
+
+    pub fn cells(&self) -> impl Iterator<Item = &ExteriorCell> {
+        self.cells.values()
+    }
+
+    pub fn references(&self) -> &[IndexedReference] {
+        &self.references
+    }
+
+    /// Cells overlapping `bounds`, ordered by grid coordinates
+    pub fn cells_in(&self, bounds: &Bounds) -> Vec<&ExteriorCell> {
+        let mut cells = self
+            .grid_range(bounds)
+            .filter_map(|grid| self.cells.get(&grid))
+            .collect::<Vec<_>>();
+
+        cells.sort_by_key(|cell| cell.grid);
+        cells
+    }
+
+    /// References placed within `bounds`, ordered by FormID
+    pub fn references_in(&self, bounds: &Bounds) -> Vec<&IndexedReference> {
+        let mut references = self
+            .grid_range(bounds)
+            .filter_map(|grid| self.buckets.get(&grid))
+            .flatten()
+            .map(|position| &self.references[*position])
+            .filter(|indexed| {
+                let [x, y, _] = indexed.reference.placement.position;
+                bounds.contains(x, y)
+            })
+            .collect::<Vec<_>>();
+
+        references.sort_by_key(|indexed| indexed.reference.id);
+        references
+    }
+
+    /// References within `radius` of a position on the XY plane, nearest first
+    pub fn references_near(&self, x: f32, y: f32, radius: f32) -> Vec<(&IndexedReference, f32)> {
+        let mut references = self
+            .references_in(&Bounds::around(x, y, radius))
+            .into_iter()
+            .map(|indexed| {
+                let [ref_x, ref_y, _] = indexed.reference.placement.position;
+                (indexed, (ref_x - x).hypot(ref_y - y))
+            })
+            .filter(|(_, distance)| *distance <= radius)
+            .collect::<Vec<_>>();
+
+        references.sort_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap());
+        references
+    }
+}
+
+/// Spatial lookups over every worldspace with cells or references in a plugin
+#[derive(Debug, Default)]
+pub struct SpatialIndex {
+    worlds: HashMap<FormId, WorldspaceIndex>,
+}
+
+impl SpatialIndex {
+    pub fn build(plugin: &Plugin) -> Self {
+        let mut index = Self::default();
+
+        let children = match plugin.groups.get(&TypeCode::from(*b"WRLD")).map(|group| &group.data) {
+            Some(GroupData::Children(children)) => children,
+            _ => return index,
+        };
+
+        for child in children {
+            match child {
+                GroupChild::Record(record) => {
+                    index
+                        .worlds
+                        .entry(record.header.id)
+                        .or_insert_with(|| WorldspaceIndex::new(record));
+                }
+                GroupChild::Group(group) => {
+                    if let Label::ParentWorld(world) = group.label {
+                        let world = index.worlds.entry(world).or_insert_with(|| WorldspaceIndex {
+                            world,
+                            ..Default::default()
+                        });
+
+                        add_group(world, group, None);
+                    }
+                }
+            }
+        }
+
+        index
+    }
+
+    pub fn world(&self, id: FormId) -> Option<&WorldspaceIndex> {
+        self.worlds.get(&id)
+    }
+
+    pub fn world_by_editor_id(&self, editor_id: &str) -> Option<&WorldspaceIndex> {
+        self.worlds.values().find(|world| {
+            world
+                .editor_id
+                .as_deref()
+                .is_some_and(|world_editor_id| world_editor_id.eq_ignore_ascii_case(editor_id))
+        })
+    }
+
+    pub fn worlds(&self) -> impl Iterator<Item = &WorldspaceIndex> {
+        self.worlds.values()
+    }
+}
+
+/// Walk a worldspace's children, tracking the cell the current group belongs to and whether it holds persistent
+/// references
+fn add_group(world: &mut WorldspaceIndex, group: &Group, cell: Option<(FormId, bool)>) {
+    let children = match &group.data {
+        GroupData::Children(children) => children,
+        _ => return,
+    };
+
+    for child in children {
+        match child {
+            GroupChild::Record(record) if &*record.header.code == b"CELL" => {
+                if let Some(grid) = record.subrecord(b"XCLC").and_then(|xclc| grid(xclc.data)) {
+                    world.add_cell(ExteriorCell {
+                        id: record.header.id,
+                        editor_id: record.header.editor_id.clone(),
+                        grid,
+                        land: None,
+                    });
+                }
+            }
+            GroupChild::Record(record) if is_placed_reference(&record.header.code) => {
+                let (cell, persistent) = match cell {
+                    Some(cell) => cell,
+                    None => continue,
+                };
+
+                match PlacedReference::decode(record) {
+                    Ok(reference) => world.add_reference(IndexedReference {
+                        reference,
+                        cell,
+                        persistent,
+                    }),
+                    Err(err) => log::debug!("Skipping reference {}: {}", record.header.id, err),
+                }
+            }
+            GroupChild::Record(record) if &*record.header.code == b"LAND" => {
+                if let Some((cell, _)) = cell {
+                    world.add_land(cell, record.header.id);
+                }
+            }
+            GroupChild::Record(_) => (),
+            GroupChild::Group(child) => {
+                let cell = match (child.group_type, &child.label) {
+                    (GroupType::CellChildren, Label::ParentCell(id)) => Some((*id, false)),
+                    (GroupType::CellPersistenChildren, _) => cell.map(|(id, _)| (id, true)),
+                    _ => cell,
+                };
+
+                add_group(world, child, cell);
+            }
+        }
+    }
+}
+
+fn grid(xclc: &[u8]) -> Option<GridCoord> {
+    pair(le_i32::<_, crate::Error>, le_i32)(xclc)
+        .ok()
+        .map(|(_, (x, y))| GridCoord::new(x as i16, y as i16))
+}
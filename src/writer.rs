@@ -0,0 +1,246 @@
+use std::io::{self, Write};
+
+use crate::parsers::{
+    common::{encode_string, write_subrecord, FormId, TypeCode},
+    group::{Group, GroupChild, GroupData, GroupType, Label},
+    plugin::Plugin,
+    records::{
+        file_header::FileHeaderData,
+        flags::{Flags, RecordFlags},
+        GenericRecord, RecordData,
+    },
+};
+
+use flate2::{write::ZlibEncoder, Compression};
+
+/// The order Skyrim.esm stores its top groups in. Top groups not listed here are written afterwards, sorted
+/// by type code
+pub const TOP_GROUP_ORDER: &[[u8; 4]] = &[
+    *b"GMST", *b"KYWD", *b"LCRT", *b"AACT", *b"TXST", *b"GLOB", *b"CLAS", *b"FACT", *b"HDPT", *b"HAIR", *b"EYES",
+    *b"RACE", *b"SOUN", *b"ASPC", *b"MGEF", *b"SCPT", *b"LTEX", *b"ENCH", *b"SPEL", *b"SCRL", *b"ACTI", *b"TACT",
+    *b"ARMO", *b"BOOK", *b"CONT", *b"DOOR", *b"INGR", *b"LIGH", *b"MISC", *b"APPA", *b"STAT", *b"SCOL", *b"MSTT",
+    *b"PWAT", *b"GRAS", *b"TREE", *b"CLDC", *b"FLOR", *b"FURN", *b"WEAP", *b"AMMO", *b"NPC_", *b"LVLN", *b"KEYM",
+    *b"ALCH", *b"IDLM", *b"COBJ", *b"PROJ", *b"HAZD", *b"SLGM", *b"LVLI", *b"WTHR", *b"CLMT", *b"SPGD", *b"RFCT",
+    *b"REGN", *b"NAVI", *b"CELL", *b"WRLD", *b"DIAL", *b"QUST", *b"IDLE", *b"PACK", *b"CSTY", *b"LSCR", *b"LVSP",
+    *b"ANIO", *b"WATR", *b"EFSH", *b"EXPL", *b"DEBR", *b"IMGS", *b"IMAD", *b"FLST", *b"PERK", *b"BPTD", *b"ADDN",
+    *b"AVIF", *b"CAMS", *b"CPTH", *b"VTYP", *b"MATT", *b"IPCT", *b"IPDS", *b"ARMA", *b"ECZN", *b"LCTN", *b"MESG",
+    *b"RGDL", *b"DOBJ", *b"LGTM", *b"MUSC", *b"FSTP", *b"FSTS", *b"SMBN", *b"SMQN", *b"SMEN", *b"DLBR", *b"MUST",
+    *b"DLVW", *b"WOOP", *b"SHOU", *b"EQUP", *b"RELA", *b"SCEN", *b"ASTP", *b"OTFT", *b"ARTO", *b"MATO", *b"MOVT",
+    *b"SNDR", *b"DUAL", *b"SNCT", *b"SOPM", *b"COLL", *b"CLFM", *b"REVB",
+];
+
+/// How records flagged as compressed are written. Skyrim LE and SE both compress records with zlib
+#[derive(Debug, Clone, Copy)]
+pub struct WriteOptions {
+    /// The zlib level, from 0 for no compression to 9 for the smallest output
+    pub compression_level: u32,
+    /// Write compressed records whose data is unchanged with the bytes they were read with, rather than
+    /// compressing them again, so rewriting a plugin only changes the records that were edited
+    pub keep_compressed: bool,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        WriteOptions {
+            compression_level: Compression::default().level(),
+            keep_compressed: true,
+        }
+    }
+}
+
+/// Serialize a plugin. Records are written in FormID order within flat groups, and groups left empty (for
+/// example after cleaning) are dropped
+pub fn write_plugin<W>(plugin: &Plugin, writer: W) -> Result<(), crate::Error>
+where
+    W: Write,
+{
+    write_plugin_with(plugin, writer, &WriteOptions::default())
+}
+
+pub fn write_plugin_with<W>(plugin: &Plugin, mut writer: W, options: &WriteOptions) -> Result<(), crate::Error>
+where
+    W: Write,
+{
+    writer.write_all(&plugin_bytes_with(plugin, options)?)?;
+    Ok(())
+}
+
+pub fn plugin_bytes(plugin: &Plugin) -> Result<Vec<u8>, crate::Error> {
+    plugin_bytes_with(plugin, &WriteOptions::default())
+}
+
+pub fn plugin_bytes_with(plugin: &Plugin, options: &WriteOptions) -> Result<Vec<u8>, crate::Error> {
+    if options.compression_level > 9 {
+        return Err(crate::Error::CorruptOrInvalidFile(format!(
+            "zlib compression level {} is above 9",
+            options.compression_level
+        )));
+    }
+
+    let mut bytes = vec![];
+
+    let header_data = file_header_data(plugin.file_header());
+    record_bytes(&mut bytes, &plugin.tes4, &header_data, options)?;
+
+    let mut codes = plugin.groups.keys().collect::<Vec<_>>();
+    codes.sort_by_key(|code| {
+        let position = TOP_GROUP_ORDER.iter().position(|top| top == &***code);
+        (position.unwrap_or(TOP_GROUP_ORDER.len()), ***code)
+    });
+
+    for code in codes {
+        group_bytes(&mut bytes, &plugin.groups[code], options)?;
+    }
+
+    Ok(bytes)
+}
+
+fn file_header_data(data: &FileHeaderData) -> Vec<u8> {
+    let mut bytes = vec![];
+
+    let mut hedr = vec![];
+    hedr.extend_from_slice(&data.hedr.version.to_le_bytes());
+    hedr.extend_from_slice(&data.hedr.num_records.to_le_bytes());
+    hedr.extend_from_slice(&data.hedr.next_id.to_le_bytes());
+    write_subrecord(&mut bytes, &TypeCode::from(*b"HEDR"), &hedr);
+
+    if let Some(author) = &data.author {
+        write_subrecord(&mut bytes, &TypeCode::from(*b"CNAM"), &zstring_bytes(author));
+    }
+
+    if let Some(description) = &data.description {
+        write_subrecord(&mut bytes, &TypeCode::from(*b"SNAM"), &zstring_bytes(description));
+    }
+
+    for master in &data.masters {
+        write_subrecord(&mut bytes, &TypeCode::from(*b"MAST"), &zstring_bytes(&master.name));
+        write_subrecord(&mut bytes, &TypeCode::from(*b"DATA"), &master.tag.to_le_bytes());
+    }
+
+    if !data.overrides.is_empty() {
+        let overrides = form_id_bytes(&data.overrides);
+        write_subrecord(&mut bytes, &TypeCode::from(*b"ONAM"), &overrides);
+    }
+
+    write_subrecord(&mut bytes, &TypeCode::from(*b"INTV"), &data.intv.to_le_bytes());
+
+    if data.incc != 0 {
+        write_subrecord(&mut bytes, &TypeCode::from(*b"INCC"), &data.incc.to_le_bytes());
+    }
+
+    bytes
+}
+
+fn group_bytes(out: &mut Vec<u8>, group: &Group, options: &WriteOptions) -> Result<(), crate::Error> {
+    let mut data = vec![];
+
+    match &group.data {
+        GroupData::Records(records) => {
+            if records.is_empty() {
+                return Ok(());
+            }
+
+            let mut records = records.values().collect::<Vec<_>>();
+            records.sort_by_key(|record| record.header.id);
+
+            for record in records {
+                write_record(&mut data, record, options)?;
+            }
+        }
+        GroupData::Children(children) => {
+            for child in children {
+                match child {
+                    GroupChild::Record(record) => write_record(&mut data, record, options)?,
+                    GroupChild::Group(group) => group_bytes(&mut data, group, options)?,
+                }
+            }
+
+            if data.is_empty() {
+                return Ok(());
+            }
+        }
+        GroupData::Unimplemented(bytes) => data.extend_from_slice(bytes),
+    }
+
+    out.extend_from_slice(&*Group::CODE);
+    out.extend_from_slice(&((data.len() + Group::HEADER_SIZE) as u32).to_le_bytes());
+    out.extend_from_slice(&label_bytes(&group.label));
+    out.extend_from_slice(&(group_type(group.group_type)).to_le_bytes());
+    out.extend_from_slice(&group.timestamp.to_le_bytes());
+    out.extend_from_slice(&group.vc_info.to_le_bytes());
+    out.extend_from_slice(&group.unknown.to_le_bytes());
+    out.extend_from_slice(&data);
+
+    Ok(())
+}
+
+fn write_record(out: &mut Vec<u8>, record: &crate::Record, options: &WriteOptions) -> Result<(), crate::Error> {
+    match &record.data {
+        RecordData::Unknown(data) => record_bytes(out, record, data, options),
+        RecordData::FileHeader(data) => record_bytes(out, record, &file_header_data(data), options),
+    }
+}
+
+fn record_bytes<F>(
+    out: &mut Vec<u8>,
+    record: &GenericRecord<F>,
+    data: &[u8],
+    options: &WriteOptions,
+) -> Result<(), crate::Error>
+where
+    F: Flags + Copy + std::fmt::Debug,
+{
+    let flags: u32 = record.header.flags.try_into().or(Err(crate::Error::Unexpected))?;
+    let flags = flags | record.header.unknown_flags;
+
+    let data = if flags & RecordFlags::COMPRESSED.bits() == 0 {
+        data.to_vec()
+    } else {
+        match &record.compressed {
+            Some(original) if options.keep_compressed => original.clone(),
+            _ => compress(data, Compression::new(options.compression_level))?,
+        }
+    };
+
+    out.extend_from_slice(&*record.header.code);
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(&flags.to_le_bytes());
+    out.extend_from_slice(&record.header.id.to_le_bytes());
+    out.extend_from_slice(&record.header.timestamp.to_le_bytes());
+    out.extend_from_slice(&record.header.vc_info.to_le_bytes());
+    out.extend_from_slice(&record.header.version.to_le_bytes());
+    out.extend_from_slice(&record.header.unknown.to_le_bytes());
+    out.extend_from_slice(&data);
+
+    Ok(())
+}
+
+/// Compressed record data is prefixed with its decompressed size
+fn compress(data: &[u8], level: Compression) -> Result<Vec<u8>, io::Error> {
+    let mut encoder = ZlibEncoder::new((data.len() as u32).to_le_bytes().to_vec(), level);
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+fn label_bytes(label: &Label) -> [u8; 4] {
+    match label {
+        Label::RecordType(code) => **code,
+        Label::ParentCell(id) | Label::ParentDialog(id) | Label::ParentWorld(id) => id.to_le_bytes(),
+        Label::BlockNumber(number) | Label::SubBlockNumber(number) => number.to_le_bytes(),
+        Label::GridCoordinate(coord) => coord.to_label(),
+    }
+}
+
+fn group_type(group_type: GroupType) -> i32 {
+    group_type as i32
+}
+
+fn zstring_bytes(value: &str) -> Vec<u8> {
+    let mut bytes = encode_string(value);
+    bytes.push(0);
+    bytes
+}
+
+fn form_id_bytes(ids: &[FormId]) -> Vec<u8> {
+    ids.iter().flat_map(|id| id.to_le_bytes()).collect()
+}